@@ -59,6 +59,16 @@ pub trait ASTVisitor<Return> {
                 res.append(&mut self.visit_constant(&constant));
             }
         }
+        if let ConstantContents::Tuple(items) = &constant.contents {
+            for constant in items {
+                res.append(&mut self.visit_constant(constant));
+            }
+        }
+        if let ConstantContents::Dict(entries) = &constant.contents {
+            for (_, constant) in entries {
+                res.append(&mut self.visit_constant(constant));
+            }
+        }
         res
     }
 
@@ -74,6 +84,12 @@ pub trait ASTVisitor<Return> {
             StatementNode::Refute(rnode) => {
                 res.append(&mut self.visit_refute(&rnode));
             },
+            StatementNode::Catch(cnode) => {
+                res.append(&mut self.visit_catch(cnode));
+            },
+            StatementNode::In(inode) => {
+                res.append(&mut self.visit_in(inode));
+            },
             StatementNode::BinaryFact(bfact) => {
                 res.append(&mut self.visit_bfact(&bfact));
             },
@@ -101,7 +117,29 @@ pub trait ASTVisitor<Return> {
 
     fn visit_refute(&mut self, refute: &RefuteNode) -> Vec<Return> {
         let mut res: Vec<Return> = vec![];
-        res.append(&mut self.visit_relcall(&*refute.statement));
+        match &refute.body {
+            RefuteBody::Statement(statement) => {
+                res.append(&mut self.visit_statement(statement));
+            },
+            RefuteBody::Block(bnode) => {
+                res.append(&mut self.visit_block(bnode));
+            },
+        }
+        res
+    }
+
+    fn visit_catch(&mut self, catch: &CatchNode) -> Vec<Return> {
+        let mut res: Vec<Return> = vec![];
+        res.append(&mut self.visit_block(&catch.body));
+        res.append(&mut self.visit_constant(&catch.pattern));
+        res.append(&mut self.visit_block(&catch.handler));
+        res
+    }
+
+    fn visit_in(&mut self, in_node: &InNode) -> Vec<Return> {
+        let mut res: Vec<Return> = vec![];
+        res.append(&mut self.visit_constant(&in_node.pattern));
+        res.append(&mut self.visit_expr(&in_node.collection));
         res
     }
 
@@ -137,7 +175,24 @@ pub trait ASTVisitor<Return> {
                 for val in vals.iter() {
                     res.append(&mut self.visit_expr(&val));
                 }
-            }
+            },
+            ExpressionContents::Tuple { vals } => {
+                for val in vals.iter() {
+                    res.append(&mut self.visit_expr(val));
+                }
+            },
+            ExpressionContents::Dict { entries } => {
+                for (_, val) in entries.iter() {
+                    res.append(&mut self.visit_expr(val));
+                }
+            },
+            ExpressionContents::Range { lo, hi, step, .. } => {
+                res.append(&mut self.visit_expr(lo));
+                res.append(&mut self.visit_expr(hi));
+                if let Some(step) = step {
+                    res.append(&mut self.visit_expr(step));
+                }
+            },
         }
         res
     }
@@ -155,6 +210,75 @@ pub trait ASTVisitor<Return> {
 #[derive(Debug)]
 pub struct ProgramNode<'p> {
     pub relations: Vec<RelationNode<'p>>,
+    pub declarations: Vec<DeclarationNode<'p>>,
+}
+
+#[derive(Debug)]
+pub enum DeclarationNode<'p> {
+    Type(TypeDeclNode<'p>),
+    Mode(ModeDeclNode<'p>),
+    Dynamic(DynamicDeclNode<'p>),
+    Export(ExportDeclNode<'p>),
+    Table(TableDeclNode<'p>),
+}
+
+// A relation whose clauses can change while the program runs
+#[derive(Debug)]
+pub struct DynamicDeclNode<'p> {
+    pub span: Span<'p>,
+    pub relation: RelationId<'p>,
+    pub arity: usize,
+}
+
+// A relation which is used from outside of the program, so it
+// counts as used even when nothing in the program calls it
+#[derive(Debug)]
+pub struct ExportDeclNode<'p> {
+    pub span: Span<'p>,
+    pub relation: RelationId<'p>,
+    pub arity: usize,
+}
+
+// A relation whose answers are remembered, so that calling it
+// again while it's already running doesn't loop forever
+#[derive(Debug)]
+pub struct TableDeclNode<'p> {
+    pub span: Span<'p>,
+    pub relation: RelationId<'p>,
+    pub arity: usize,
+}
+
+#[derive(Debug)]
+pub struct TypeDeclNode<'p> {
+    pub span: Span<'p>,
+    pub name: String,
+    pub alternatives: Vec<TypeNode<'p>>,
+}
+
+#[derive(Debug)]
+pub struct ModeDeclNode<'p> {
+    pub span: Span<'p>,
+    pub relation: RelationId<'p>,
+    pub params: Vec<Mode>,
+    pub outputs: Vec<Mode>,
+}
+
+impl<'p> ModeDeclNode<'p> {
+    // Modes of every argument, including outputs
+    pub fn modes(&self) -> Vec<Mode> {
+        let mut res = self.params.clone();
+        res.extend(self.outputs.iter().cloned());
+        res
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // Must be bound when called
+    In,
+    // Will be bound after the call succeeds
+    Out,
+    Any,
 }
 
 #[derive(Debug)]
@@ -162,9 +286,29 @@ pub struct RelationNode<'p> {
     pub span: Span<'p>,
     pub relation: RelationId<'p>,
     pub params: ConstList<'p>,
+    // Optional type annotations, one per parameter
+    pub param_types: Vec<Option<TypeNode<'p>>>,
+    // Optional type annotations for the outputs (after '->')
+    pub output_types: Option<Vec<TypeNode<'p>>>,
     pub block: RelationBlock<'p>,
 }
 
+#[derive(Debug)]
+pub struct TypeNode<'p> {
+    pub span: Span<'p>,
+    pub contents: TypeContents<'p>,
+}
+
+#[derive(Debug)]
+pub enum TypeContents<'p> {
+    // number, atom, any, or a user defined type
+    Name(String),
+    // A single atom, like 'red
+    Literal(String),
+    List(Box<TypeNode<'p>>),
+    Tuple(Vec<TypeNode<'p>>),
+}
+
 #[derive(Debug)]
 pub enum RelationBlock<'p> {
     Const(ConstList<'p>),
@@ -189,15 +333,40 @@ pub struct BlockNode<'p> {
     pub statements: Vec<StatementNode<'p>>,
 }
 
+// Relations which are given a clause as data, rather than calling it
+pub fn is_database_relation(name: &str) -> bool {
+    name == "assert" || name == "asserta" || name == "retract"
+}
+
 pub fn find_num_results<'p>(bnode: &BlockNode<'p>) -> usize {
     for statement in bnode.statements.iter() {
-        if let StatementNode::Relate(rnode) = statement {
-            return rnode.result.len();
+        match statement {
+            StatementNode::Relate(rnode) => return rnode.result.len(),
+            // A catch can relate from its body or its handler
+            StatementNode::Catch(cnode) => {
+                let num_results = find_num_results(&cnode.body).max(find_num_results(&cnode.handler));
+                if num_results > 0 {
+                    return num_results;
+                }
+            },
+            _ => {},
         }
     }
     return 0;
 }
 
+// A tuple pattern in place of several patterns (like `(a, b) ~ f()`)
+// stands for each of its elements
+pub fn spread_tuple<'p>(constant: ConstantNode<'p>) -> Vec<ConstantNode<'p>> {
+    match constant.contents {
+        ConstantContents::Tuple(items) => items,
+        contents => vec![ConstantNode {
+            span: constant.span,
+            contents,
+        }],
+    }
+}
+
 #[derive(Debug)]
 pub struct ConstantNode<'p> {
     pub span: Span<'p>,
@@ -212,6 +381,9 @@ pub enum ConstantContents<'p> {
     Literal(String),
     List(Vec<ConstantNode<'p>>),
     ConsList(Vec<ConstantNode<'p>>),
+    Tuple(Vec<ConstantNode<'p>>),
+    // Matches any dict which has these keys
+    Dict(Vec<(String, ConstantNode<'p>)>),
 }
 
 #[derive(Debug)]
@@ -219,6 +391,8 @@ pub enum StatementNode<'p> {
     Assignment(AssignmentNode<'p>),
     Relate(RelateNode<'p>),
     Refute(RefuteNode<'p>),
+    Catch(CatchNode<'p>),
+    In(InNode<'p>),
     BinaryFact(BinaryFactNode<'p>),
     Relation(RelationCallNode<'p>),
 }
@@ -239,7 +413,32 @@ pub struct RelateNode<'p> {
 #[derive(Debug)]
 pub struct RefuteNode<'p> {
     pub span: Span<'p>,
-    pub statement: Box<RelationCallNode<'p>>,
+    pub body: RefuteBody<'p>,
+}
+
+#[derive(Debug)]
+pub enum RefuteBody<'p> {
+    Statement(Box<StatementNode<'p>>),
+    Block(BlockNode<'p>),
+}
+
+// Runs the handler if the body throws
+// something matching the pattern
+#[derive(Debug)]
+pub struct CatchNode<'p> {
+    pub span: Span<'p>,
+    pub body: BlockNode<'p>,
+    pub pattern: ConstantNode<'p>,
+    pub handler: BlockNode<'p>,
+}
+
+// Matches the pattern against each element
+// of a list or range in turn
+#[derive(Debug)]
+pub struct InNode<'p> {
+    pub span: Span<'p>,
+    pub pattern: ConstantNode<'p>,
+    pub collection: ExpressionNode<'p>,
 }
 
 #[derive(Debug)]
@@ -266,6 +465,39 @@ pub struct ExpressionNode<'p> {
     pub contents: ExpressionContents<'p>,
 }
 
+impl<'p> ConstantNode<'p> {
+    // The constants directly inside of this one
+    pub fn subconstants(&self) -> Vec<&ConstantNode<'p>> {
+        match &self.contents {
+            ConstantContents::List(items)
+            | ConstantContents::ConsList(items)
+            | ConstantContents::Tuple(items) => items.iter().collect(),
+            ConstantContents::Dict(entries) => entries.iter().map(|(_, item)| item).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl<'p> ExpressionNode<'p> {
+    // The expressions directly inside of this one
+    pub fn subexpressions(&self) -> Vec<&ExpressionNode<'p>> {
+        match &self.contents {
+            ExpressionContents::Const(_) => vec![],
+            ExpressionContents::Operation { lhs, rhs, .. } => vec![&**lhs, &**rhs],
+            ExpressionContents::Call { args: vals, .. }
+            | ExpressionContents::List { vals }
+            | ExpressionContents::ConsList { vals }
+            | ExpressionContents::Tuple { vals } => vals.iter().collect(),
+            ExpressionContents::Dict { entries } => entries.iter().map(|(_, val)| val).collect(),
+            ExpressionContents::Range { lo, hi, step, .. } => {
+                let mut res = vec![&**lo, &**hi];
+                res.extend(step.as_deref());
+                res
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ExpressionContents<'p> {
     Const(ConstantNode<'p>),
@@ -284,6 +516,20 @@ pub enum ExpressionContents<'p> {
     ConsList {
         vals: Vec<ExpressionNode<'p>>,
     },
+    Tuple {
+        vals: Vec<ExpressionNode<'p>>,
+    },
+    Dict {
+        entries: Vec<(String, ExpressionNode<'p>)>,
+    },
+    // lo..hi, or lo..<hi without hi itself, counting up by
+    // step (or down, if it's negative)
+    Range {
+        lo: Box<ExpressionNode<'p>>,
+        hi: Box<ExpressionNode<'p>>,
+        step: Option<Box<ExpressionNode<'p>>>,
+        exclusive: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -15,18 +15,45 @@ pub trait ParseNode<'p> {
 }
 
 pub fn parse_program<'p>(pairs: Pairs<'p, Rule>, source: &'p str) -> ProgramNode<'p> {
-    let relations: Vec<RelationNode<'p>> = 
-        pairs.filter_map(|pair| {
-            match pair.as_rule() {
-                Rule::EOI => None,
-                _ => Some(RelationNode::parse(pair, source))
-            }
-        }).collect();
+    let mut relations: Vec<RelationNode<'p>> = vec![];
+    let mut declarations: Vec<DeclarationNode<'p>> = vec![];
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::EOI => {},
+            Rule::type_decl | Rule::mode_decl | Rule::dynamic_decl | Rule::export_decl | Rule::table_decl => declarations.push(DeclarationNode::parse(pair, source)),
+            _ => relations.push(RelationNode::parse(pair, source)),
+        }
+    }
     ProgramNode {
-        relations: relations,
+        relations,
+        declarations,
     }
 }
 
+// Break down a relation_decl into its name, parameters,
+// and any type annotations given
+fn parse_relation_decl<'p>(pair: Pair<'p, Rule>, source: &'p str)
+        -> (RelationId<'p>, ConstList<'p>, Vec<Option<TypeNode<'p>>>, Option<Vec<TypeNode<'p>>>) {
+    let mut relation_decl_breakdown = pair.into_inner();
+    let relation: RelationId<'p> =
+        RelationId::parse(relation_decl_breakdown.next().unwrap(), source);
+    let param_list = relation_decl_breakdown.next().unwrap();
+    let param_types: Vec<Option<TypeNode<'p>>> = param_list.clone().into_inner()
+        .map(|param| {
+            param.into_inner().nth(1).map(|tpair| TypeNode::parse(tpair, source))
+        })
+        .collect();
+    let params: ConstList<'p> =
+        ConstList::parse(param_list, source);
+    let output_types: Option<Vec<TypeNode<'p>>> = relation_decl_breakdown.next()
+        .map(|outputs| {
+            outputs.into_inner()
+                .map(|tpair| TypeNode::parse(tpair, source))
+                .collect()
+        });
+    (relation, params, param_types, output_types)
+}
+
 impl<'p> ParseNode<'p> for RelationNode<'p> {
     fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
         let span: Span<'p> = new_span(pair.as_span(), source);
@@ -34,41 +61,39 @@ impl<'p> ParseNode<'p> for RelationNode<'p> {
             Rule::relation_block => {
                 let mut inners = pair.into_inner();
                 let relation_decl = inners.next().unwrap();
-                let mut relation_decl_breakdown = relation_decl.into_inner();
-                let relation: RelationId<'p> =
-                    RelationId::parse(relation_decl_breakdown.next().unwrap(), source);
-                let params: ConstList<'p> =
-                    ConstList::parse(relation_decl_breakdown.next().unwrap(), source);
+                let (relation, params, param_types, output_types) =
+                    parse_relation_decl(relation_decl, source);
                 
                 let block_or_const = inners.next().unwrap();
                 let block: RelationBlock<'p> =
                     RelationBlock::parse(block_or_const, source);
 
                 RelationNode {
-                    span: span,
-                    relation: relation,
-                    params: params,
-                    block: block
+                    span,
+                    relation,
+                    params,
+                    param_types,
+                    output_types,
+                    block
                 }
             },
             Rule::simple_relation | Rule::multiple_relation => {
                 let mut inners = pair.into_inner();
                 let relation_decl = inners.next().unwrap();
-                let mut relation_decl_breakdown = relation_decl.into_inner();
-                let relation: RelationId<'p> =
-                    RelationId::parse(relation_decl_breakdown.next().unwrap(), source);
-                let params: ConstList<'p> =
-                    ConstList::parse(relation_decl_breakdown.next().unwrap(), source);
+                let (relation, params, param_types, output_types) =
+                    parse_relation_decl(relation_decl, source);
                 
                 let block_or_const = inners.next().unwrap();
                 let block: RelationBlock<'p> =
                     RelationBlock::parse(block_or_const, source);
 
                 RelationNode {
-                    span: span,
-                    relation: relation,
-                    params: params,
-                    block: block
+                    span,
+                    relation,
+                    params,
+                    param_types,
+                    output_types,
+                    block
                 }
             },
             x => panic!("unexpected: {:?}", x),
@@ -134,6 +159,18 @@ impl<'p> ParseNode<'p> for ConstList<'p> {
     fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
         let span: Span<'p> = new_span(pair.as_span(), source);
         match pair.as_rule() {
+            Rule::param_list => {
+                // Type annotations are parsed separately, see parse_relation_decl
+                let constants: Vec<ConstantNode<'p>> =
+                    pair.into_inner()
+                    .map(|param| ConstantNode::parse(param.into_inner().next().unwrap(), source))
+                    .collect();
+
+                ConstList {
+                    span,
+                    constants,
+                }
+            },
             Rule::pattern_list => {
                 let inner_const_terms = pair.into_inner();
 
@@ -152,9 +189,10 @@ impl<'p> ParseNode<'p> for ConstList<'p> {
                 let constant: ConstantNode<'p> =
                     ConstantNode::parse(inner_const_term.next().unwrap(), source);
                 
+                // f() ~ (1, 2) has two outputs, like f() ~ 1, 2
                 ConstList {
                     span: span,
-                    constants: vec![constant],
+                    constants: spread_tuple(constant),
                 }
             },
             x => panic!("unexpected: {:?}", x),
@@ -215,6 +253,10 @@ impl<'p> ParseNode<'p> for ConstantNode<'p> {
                     Rule::atom => {
                         ConstantContents::Atom(ident)
                     },
+                    // Strings are atoms whose names can be any text
+                    Rule::string => {
+                        ConstantContents::Atom(format!("'{}", unescape(&ident[1..ident.len() - 1])))
+                    },
                     Rule::ident => {
                         ConstantContents::Var(ident)
                     },
@@ -235,6 +277,24 @@ impl<'p> ParseNode<'p> for ConstantNode<'p> {
                             .collect();
                         ConstantContents::ConsList(contents)
                     },
+                    Rule::tuple_pattern => {
+                        let innerds = pair.into_inner();
+                        let contents: Vec<ConstantNode<'p>> =
+                            innerds.map(|pair| ConstantNode::parse(pair, source))
+                            .collect();
+                        ConstantContents::Tuple(contents)
+                    },
+                    Rule::dict_pattern => {
+                        let entries: Vec<(String, ConstantNode<'p>)> = pair.into_inner()
+                            .map(|entry| {
+                                let mut innerds = entry.into_inner();
+                                let key = innerds.next().unwrap().as_str().to_string();
+                                let val = ConstantNode::parse(innerds.next().unwrap(), source);
+                                (key, val)
+                            })
+                            .collect();
+                        ConstantContents::Dict(entries)
+                    },
                     x => panic!("unexpected: {:?} | {:?} | {:?}", x, pair, pair.as_span().lines().collect::<Vec<_>>()),
                 }
             }
@@ -246,6 +306,239 @@ impl<'p> ParseNode<'p> for ConstantNode<'p> {
     }
 }
 
+impl<'p> ParseNode<'p> for DeclarationNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        match pair.as_rule() {
+            Rule::type_decl => {
+                DeclarationNode::Type(TypeDeclNode::parse(pair, source))
+            },
+            Rule::mode_decl => {
+                DeclarationNode::Mode(ModeDeclNode::parse(pair, source))
+            },
+            Rule::dynamic_decl => {
+                DeclarationNode::Dynamic(DynamicDeclNode::parse(pair, source))
+            },
+            Rule::export_decl => {
+                DeclarationNode::Export(ExportDeclNode::parse(pair, source))
+            },
+            Rule::table_decl => {
+                DeclarationNode::Table(TableDeclNode::parse(pair, source))
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        match self {
+            DeclarationNode::Type(tdnode) => &tdnode.span,
+            DeclarationNode::Mode(mdnode) => &mdnode.span,
+            DeclarationNode::Dynamic(ddnode) => &ddnode.span,
+            DeclarationNode::Export(ednode) => &ednode.span,
+            DeclarationNode::Table(tdnode) => &tdnode.span,
+        }
+    }
+}
+
+impl<'p> ParseNode<'p> for DynamicDeclNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::dynamic_decl => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::dynamic_kw);
+                let relation: RelationId<'p> = RelationId::parse(innerds.next().unwrap(), source);
+                let arity: usize = innerds.next().unwrap().as_str().parse().unwrap();
+                DynamicDeclNode {
+                    span,
+                    relation,
+                    arity,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for ExportDeclNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::export_decl => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::export_kw);
+                let relation: RelationId<'p> = RelationId::parse(innerds.next().unwrap(), source);
+                let arity: usize = innerds.next().unwrap().as_str().parse().unwrap();
+                ExportDeclNode {
+                    span,
+                    relation,
+                    arity,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for TableDeclNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::table_decl => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::table_kw);
+                let relation: RelationId<'p> = RelationId::parse(innerds.next().unwrap(), source);
+                let arity: usize = innerds.next().unwrap().as_str().parse().unwrap();
+                TableDeclNode {
+                    span,
+                    relation,
+                    arity,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+// The text of a string literal, without its escapes
+pub fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some(other) => res.push(other),
+            None => {},
+        }
+    }
+    res
+}
+
+fn parse_mode(pair: Pair<Rule>) -> Mode {
+    match pair.as_str() {
+        "in" => Mode::In,
+        "out" => Mode::Out,
+        "any" => Mode::Any,
+        x => panic!("unexpected: {:?}", x),
+    }
+}
+
+impl<'p> ParseNode<'p> for ModeDeclNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::mode_decl => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::mode_kw);
+                let relation: RelationId<'p> = RelationId::parse(innerds.next().unwrap(), source);
+                let mut params: Vec<Mode> = vec![];
+                let mut outputs: Vec<Mode> = vec![];
+                for inner in innerds {
+                    match inner.as_rule() {
+                        Rule::mode => params.push(parse_mode(inner)),
+                        Rule::mode_outputs => {
+                            outputs = inner.into_inner().map(parse_mode).collect();
+                        },
+                        x => panic!("unexpected: {:?}", x)
+                    }
+                }
+                ModeDeclNode {
+                    span,
+                    relation,
+                    params,
+                    outputs,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for TypeDeclNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::type_decl => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::type_kw);
+                let name: String = innerds.next().unwrap().as_str().to_string();
+                let alternatives: Vec<TypeNode<'p>> =
+                    innerds.map(|pair| TypeNode::parse(pair, source))
+                    .collect();
+                TypeDeclNode {
+                    span,
+                    name,
+                    alternatives,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for TypeNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::type_expr => {
+                let inner = pair.into_inner().next().unwrap();
+                let contents: TypeContents<'p> = match inner.as_rule() {
+                    Rule::ident => TypeContents::Name(inner.as_str().to_string()),
+                    Rule::atom => TypeContents::Literal(inner.as_str().to_string()),
+                    Rule::list_type => {
+                        let element = inner.into_inner().next().unwrap();
+                        TypeContents::List(Box::new(TypeNode::parse(element, source)))
+                    },
+                    Rule::tuple_type => {
+                        TypeContents::Tuple(inner.into_inner()
+                            .map(|element| TypeNode::parse(element, source))
+                            .collect())
+                    },
+                    x => panic!("unexpected: {:?}", x)
+                };
+                TypeNode {
+                    span,
+                    contents,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
 impl<'p> ParseNode<'p> for StatementNode<'p> {
     fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
         match pair.as_rule() {
@@ -264,6 +557,12 @@ impl<'p> ParseNode<'p> for StatementNode<'p> {
             Rule::refute => {
                 StatementNode::Refute(RefuteNode::parse(pair, source))
             },
+            Rule::catch => {
+                StatementNode::Catch(CatchNode::parse(pair, source))
+            },
+            Rule::in_stmt => {
+                StatementNode::In(InNode::parse(pair, source))
+            },
             x => panic!("unexpected: {:?}", x)
         }
     }
@@ -273,6 +572,8 @@ impl<'p> ParseNode<'p> for StatementNode<'p> {
             StatementNode::Assignment(anode) => &anode.span,
             StatementNode::Relate(rnode) => &rnode.span,
             StatementNode::Refute(rnode) => &rnode.span,
+            StatementNode::Catch(cnode) => &cnode.span,
+            StatementNode::In(inode) => &inode.span,
             StatementNode::BinaryFact(bfnode) => &bfnode.span,
             StatementNode::Relation(rcallnode) => &rcallnode.span,
         }
@@ -321,14 +622,12 @@ impl<'p> ParseNode<'p> for AssignmentNode<'p> {
                 let lhs: ConstantNode<'p> = ConstantNode::parse(constant_term, source);
                 let expr_term = innerds.next().unwrap();
                 let rhs: ExpressionNode<'p> = ExpressionNode::parse(expr_term, source);
+                // (a, b) ~ f() takes both outputs of f
                 AssignmentNode {
                     span: span,
                     lhs: ConstList {
                         span: lhs.span.clone(),
-                        constants: vec![ConstantNode {
-                            span: lhs.span,
-                            contents: lhs.contents,
-                        }],
+                        constants: spread_tuple(lhs),
                     },
                     rhs: rhs,
                 }
@@ -359,12 +658,75 @@ impl<'p> ParseNode<'p> for RefuteNode<'p> {
         let span: Span<'p> = new_span(pair.as_span(), source);
         match pair.as_rule() {
             Rule::refute => {
-                let rcall_term = pair.into_inner().next().unwrap();
-                let result: RelationCallNode<'p> =
-                    RelationCallNode::parse(rcall_term, source);
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::refute_kw);
+                let inner_term = innerds.next().unwrap();
+                let body: RefuteBody<'p> = match inner_term.as_rule() {
+                    Rule::block => {
+                        RefuteBody::Block(BlockNode::parse(inner_term, source))
+                    },
+                    _ => {
+                        RefuteBody::Statement(Box::new(StatementNode::parse(inner_term, source)))
+                    },
+                };
                 RefuteNode {
-                    span: span,
-                    statement: Box::new(result)
+                    span,
+                    body
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for CatchNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::catch => {
+                let mut innerds = pair.into_inner();
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::catch_kw);
+                let body = BlockNode::parse(innerds.next().unwrap(), source);
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::with_kw);
+                let pattern = ConstantNode::parse(innerds.next().unwrap(), source);
+                let handler = BlockNode::parse(innerds.next().unwrap(), source);
+                CatchNode {
+                    span,
+                    body,
+                    pattern,
+                    handler,
+                }
+            },
+            x => panic!("unexpected: {:?}", x)
+        }
+    }
+
+    fn as_span(&self) -> &Span<'p> {
+        &self.span
+    }
+}
+
+impl<'p> ParseNode<'p> for InNode<'p> {
+    fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
+        let span: Span<'p> = new_span(pair.as_span(), source);
+        match pair.as_rule() {
+            Rule::in_stmt => {
+                let mut innerds = pair.into_inner();
+                let pattern = ConstantNode::parse(innerds.next().unwrap(), source);
+                let keyword = innerds.next().unwrap();
+                assert!(keyword.as_rule() == Rule::in_kw);
+                let collection = ExpressionNode::parse(innerds.next().unwrap(), source);
+                InNode {
+                    span,
+                    pattern,
+                    collection,
                 }
             },
             x => panic!("unexpected: {:?}", x)
@@ -384,9 +746,17 @@ impl<'p> ParseNode<'p> for RelateNode<'p> {
                 let expr_term = pair.into_inner().next().unwrap();
                 let result: ExpressionNode<'p> = 
                     ExpressionNode::parse(expr_term, source);
+                // relate (1, 2) is the same as relate 1, 2
+                let results = match result.contents {
+                    ExpressionContents::Tuple { vals } => vals,
+                    contents => vec![ExpressionNode {
+                        span: result.span,
+                        contents,
+                    }],
+                };
                 RelateNode {
                     span: span,
-                    result: vec![result]
+                    result: results
                 }
             },
             Rule::mul_relate => {
@@ -449,10 +819,117 @@ impl<'p> ParseNode<'p> for BinaryFactNode<'p> {
     }
 }
 
+// A term of an expression which isn't an operation
+fn parse_primary<'p>(pair: Pair<'p, Rule>, source: &'p str) -> ExpressionNode<'p> {
+    ExpressionNode {
+        span: new_span(pair.as_span(), source),
+        contents: {
+            match pair.as_rule() {
+                Rule::num_literal | Rule::atom | Rule::string | Rule::ident =>
+                    ExpressionContents::Const(ConstantNode::parse(pair, source)),
+                Rule::relation_call => {
+                    let mut innerds = pair.into_inner();
+                    let ident_pair = innerds.next().unwrap();
+                    let ident = RelationId::parse(ident_pair, source);
+                    
+                    let expr_list = innerds.next().unwrap();
+                    assert!(expr_list.as_rule() == Rule::expr_list);
+                    let innerds = expr_list.into_inner();
+
+                    let args: Vec<ExpressionNode<'p>> = innerds.map(|pair| {
+                        ExpressionNode::parse(pair, source)
+                    }).collect();
+                    
+                    ExpressionContents::Call {
+                        rel: ident,
+                        args,
+                    }
+                },
+                Rule::list_expr => {
+                    let innerds = pair.into_inner();
+                    let vals: Vec<ExpressionNode<'p>> =
+                        innerds.map(|pair| ExpressionNode::parse(pair, source))
+                        .collect();
+                    ExpressionContents::List {
+                        vals
+                    }
+                },
+                Rule::conslist_expr => {
+                    let innerds = pair.into_inner();
+                    let vals: Vec<ExpressionNode<'p>> =
+                        innerds.map(|pair| ExpressionNode::parse(pair, source))
+                        .collect();
+                    ExpressionContents::ConsList {
+                        vals
+                    }
+                },
+                Rule::tuple_expr => {
+                    let innerds = pair.into_inner();
+                    let vals: Vec<ExpressionNode<'p>> =
+                        innerds.map(|pair| ExpressionNode::parse(pair, source))
+                        .collect();
+                    ExpressionContents::Tuple {
+                        vals
+                    }
+                },
+                Rule::dict_expr => {
+                    let entries: Vec<(String, ExpressionNode<'p>)> = pair.into_inner()
+                        .map(|entry| {
+                            let mut innerds = entry.into_inner();
+                            let key = innerds.next().unwrap().as_str().to_string();
+                            let val = ExpressionNode::parse(innerds.next().unwrap(), source);
+                            (key, val)
+                        })
+                        .collect();
+                    ExpressionContents::Dict {
+                        entries
+                    }
+                },
+                Rule::access_expr => {
+                    // p.age is short for get_dict('age, p)
+                    let mut innerds = pair.into_inner();
+                    let mut base = parse_primary(innerds.next().unwrap(), source);
+                    for key in innerds {
+                        let span = new_span(key.as_span(), source);
+                        let key_expr = ExpressionNode {
+                            span: span.clone(),
+                            contents: ExpressionContents::Const(ConstantNode {
+                                span: span.clone(),
+                                contents: ConstantContents::Atom(format!("'{}", key.as_str())),
+                            }),
+                        };
+                        base = ExpressionNode {
+                            span: span.clone(),
+                            contents: ExpressionContents::Call {
+                                rel: RelationId {
+                                    span,
+                                    name: "get_dict".to_string(),
+                                },
+                                args: vec![key_expr, base],
+                            },
+                        };
+                    }
+                    base.contents
+                },
+                _ => {
+                    // Parenthetical expression
+
+                    ExpressionNode::parse(pair, source).contents
+                }
+            }
+        },
+    }
+}
+
 impl<'p> ParseNode<'p> for ExpressionNode<'p> {
     fn parse(pair: Pair<'p, Rule>, source: &'p str) -> Self {
         // dbg!(&pair);
-        let pairs = pair.into_inner();
+        let span = new_span(pair.as_span(), source);
+        let mut pairs: Vec<Pair<'p, Rule>> = pair.into_inner().collect();
+        let range_tail = match pairs.last().map(|pair| pair.as_rule()) {
+            Some(Rule::range_tail) => pairs.pop(),
+            _ => None,
+        };
 
         let climber = PrecClimber::new(vec![
             Operator::new(Rule::modulo, Assoc::Left),
@@ -460,58 +937,7 @@ impl<'p> ParseNode<'p> for ExpressionNode<'p> {
             Operator::new(Rule::multiply, Assoc::Left) | Operator::new(Rule::divide, Assoc::Left),
         ]);
 
-        let primary = |pair: Pair<'p, Rule>| {
-            ExpressionNode {
-                span: new_span(pair.as_span(), source),
-                contents: {
-                    match pair.as_rule() {
-                        Rule::num_literal | Rule::atom | Rule::ident =>
-                            ExpressionContents::Const(ConstantNode::parse(pair, source)),
-                        Rule::relation_call => {
-                            let mut innerds = pair.into_inner();
-                            let ident_pair = innerds.next().unwrap();
-                            let ident = RelationId::parse(ident_pair, source);
-                            
-                            let expr_list = innerds.next().unwrap();
-                            assert!(expr_list.as_rule() == Rule::expr_list);
-                            let innerds = expr_list.into_inner();
-
-                            let args: Vec<ExpressionNode<'p>> = innerds.map(|pair| {
-                                ExpressionNode::parse(pair, source)
-                            }).collect();
-                            
-                            ExpressionContents::Call {
-                                rel: ident,
-                                args: args,
-                            }
-                        },
-                        Rule::list_expr => {
-                            let innerds = pair.into_inner();
-                            let vals: Vec<ExpressionNode<'p>> =
-                                innerds.map(|pair| ExpressionNode::parse(pair, source))
-                                .collect();
-                            ExpressionContents::List {
-                                vals: vals
-                            }
-                        },
-                        Rule::conslist_expr => {
-                            let innerds = pair.into_inner();
-                            let vals: Vec<ExpressionNode<'p>> =
-                                innerds.map(|pair| ExpressionNode::parse(pair, source))
-                                .collect();
-                            ExpressionContents::ConsList {
-                                vals: vals
-                            }
-                        },
-                        _ => {
-                            // Parenthetical expression
-
-                            ExpressionNode::parse(pair, source).contents
-                        }
-                    }
-                },
-            }
-        };
+        let primary = |pair: Pair<'p, Rule>| parse_primary(pair, source);
 
         let infix = |lhs: ExpressionNode<'p>, op: Pair<'p, Rule>, rhs: ExpressionNode<'p>| {
             ExpressionNode {
@@ -531,7 +957,25 @@ impl<'p> ParseNode<'p> for ExpressionNode<'p> {
             }
         };
         // dbg!(&pairs);
-        climber.climb(pairs, primary, infix)
+        let lhs = climber.climb(pairs.into_iter(), primary, infix);
+        match range_tail {
+            None => lhs,
+            Some(tail) => {
+                let mut innerds = tail.into_inner();
+                let exclusive = innerds.next().unwrap().as_rule() == Rule::range_excl;
+                let hi = ExpressionNode::parse(innerds.next().unwrap(), source);
+                let step = innerds.nth(1).map(|step| Box::new(ExpressionNode::parse(step, source)));
+                ExpressionNode {
+                    span,
+                    contents: ExpressionContents::Range {
+                        lo: Box::new(lhs),
+                        hi: Box::new(hi),
+                        step,
+                        exclusive,
+                    },
+                }
+            },
+        }
     }
 
     fn as_span(&self) -> &Span<'p> {
@@ -2,16 +2,33 @@
 use annotate_snippets::snippet::*;
 
 use crate::ast::*;
-use crate::error::{Error, Result, union_spans};
+use crate::error::{code_snippet, span_slice, Diagnostic, Error, Result, union_spans};
 use crate::span::Span;
+use crate::types;
+use crate::codes;
+use crate::solver::builtins::{self, builtins, database_builtins, meta_builtins};
 
-pub fn perform_checks(program: &ProgramNode, source: String) -> Vec<Error> {
+use std::collections::{HashMap, HashSet};
+
+pub fn perform_checks(program: &ProgramNode, prelude: Option<&ProgramNode>, source: String) -> Vec<Error> {
     let mut result = vec![];
+    result.append(&mut check_undefined(program, prelude, &source));
+    result.append(&mut check_arities(program, prelude, &source));
     result.append(&mut check_proc_relates(program, &source));
     result.append(&mut check_odd_ops(program, &source));
+    result.append(&mut check_dict_keys(program, &source));
+    result.append(&mut types::check_types(program, &source));
+    result.append(&mut check_modes(program, &source));
+    result.append(&mut check_singletons(program, &source));
+    result.append(&mut check_unused(program, &source));
+    result.append(&mut check_duplicate_clauses(program, &source));
+    result.append(&mut check_left_recursion(program, &source));
     result
 }
 
+// The lints which can be named by -A and -D, along with "warnings"
+pub const LINTS: &[&str] = &["singleton", "unbound", "mode", "arity", "unused", "duplicate", "left_recursion"];
+
 // Traverse looking for odd operations (adding lists, etc.)
 pub fn check_odd_ops(program: &ProgramNode, source: &String) -> Vec<Error> {
     (OddOps { state: source }).visit_program(program)
@@ -40,11 +57,29 @@ impl<'a> ASTVisitor<Error> for OddOps<'a> {
                 if let ExpressionContents::ConsList {..} = &rhs.contents {
                     invalid = true;
                 }
+                if let ExpressionContents::Dict {..} = &lhs.contents {
+                    invalid = true;
+                }
+                if let ExpressionContents::Dict {..} = &rhs.contents {
+                    invalid = true;
+                }
+                if let ExpressionContents::Tuple {..} = &lhs.contents {
+                    invalid = true;
+                }
+                if let ExpressionContents::Tuple {..} = &rhs.contents {
+                    invalid = true;
+                }
+                if let ExpressionContents::Range {..} = &lhs.contents {
+                    invalid = true;
+                }
+                if let ExpressionContents::Range {..} = &rhs.contents {
+                    invalid = true;
+                }
 
                 if invalid {
                     let span = union_spans(&lhs.span, &rhs.span);
                     res.push(Error::Code(
-                                check_odd_ops_snippet(&span, state)
+                                check_odd_ops_snippet(&span, state).into()
                             ));
                 }
                 res.append(&mut self.visit_expr(&lhs));
@@ -65,10 +100,707 @@ impl<'a> ASTVisitor<Error> for OddOps<'a> {
                 for val in vals.iter() {
                     res.append(&mut self.visit_expr(&val));
                 }
+            },
+            ExpressionContents::Tuple { vals } => {
+                for val in vals.iter() {
+                    res.append(&mut self.visit_expr(val));
+                }
+            },
+            ExpressionContents::Dict { entries } => {
+                for (_, val) in entries.iter() {
+                    res.append(&mut self.visit_expr(val));
+                }
+            },
+            ExpressionContents::Range { lo, hi, step, .. } => {
+                res.append(&mut self.visit_expr(lo));
+                res.append(&mut self.visit_expr(hi));
+                if let Some(step) = step {
+                    res.append(&mut self.visit_expr(step));
+                }
+            },
+        }
+        res
+    }
+}
+
+// A dict can only give each key one value
+pub fn check_dict_keys(program: &ProgramNode, source: &String) -> Vec<Error> {
+    let mut result = vec![];
+    let mut dict_keys = DictKeys { state: source };
+    for relation in program.relations.iter() {
+        result.append(&mut dict_keys.visit_constlist(&relation.params));
+        result.append(&mut dict_keys.visit_relation(relation));
+    }
+    result
+}
+
+struct DictKeys<'a> {state: &'a String}
+
+impl<'a> DictKeys<'a> {
+    fn check<'k>(&self, keys: impl Iterator<Item = &'k String>, span: &Span) -> Vec<Error> {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        keys.filter(|key| !seen.insert(*key) && reported.insert(*key))
+            .map(|key| Error::Code(check_dict_keys_snippet(span, key, self.state).into()))
+            .collect()
+    }
+}
+
+impl<'a> ASTVisitor<Error> for DictKeys<'a> {
+    fn visit_constant(&mut self, constant: &ConstantNode) -> Vec<Error> {
+        let mut res = vec![];
+        if let ConstantContents::Dict(entries) = &constant.contents {
+            res.append(&mut self.check(entries.iter().map(|(key, _)| key), &constant.span));
+        }
+        for item in constant.subconstants() {
+            res.append(&mut self.visit_constant(item));
+        }
+        res
+    }
+
+    fn visit_expr(&mut self, expression: &ExpressionNode) -> Vec<Error> {
+        let mut res = vec![];
+        match &expression.contents {
+            ExpressionContents::Const(cnode) => {
+                res.append(&mut self.visit_constant(cnode));
+            },
+            ExpressionContents::Dict { entries } => {
+                res.append(&mut self.check(entries.iter().map(|(key, _)| key), &expression.span));
+            },
+            _ => {},
+        }
+        for sub in expression.subexpressions() {
+            res.append(&mut self.visit_expr(sub));
+        }
+        res
+    }
+}
+
+// Relations which the solver handles itself, rather than as builtins,
+// with the numbers of arguments they take. call takes any number
+const SOLVER_RELATIONS: &[(&str, Option<&[usize]>)] = &[("call", None), ("throw", Some(&[1]))];
+
+// Lints named on the command line which don't exist
+pub fn unknown_lint(lint: &str) -> Error {
+    Error::Code(Snippet {
+        title: Some(Annotation {
+            label: Some(format!("unknown lint `{}`", lint)),
+            id: None,
+            annotation_type: AnnotationType::Warning,
+        }),
+        footer: vec![
+            Annotation {
+                id: None,
+                label: Some(format!("the lints are {}", LINTS.join(", "))),
+                annotation_type: AnnotationType::Note,
+            },
+        ],
+        slices: vec![],
+    }.into())
+}
+
+// Each builtin with the numbers of arguments it can be
+// called with, or None if it takes any number
+fn builtin_arities() -> HashMap<String, Option<Vec<usize>>> {
+    let mut listed = builtins::builtin_arities();
+    let names = builtins().into_keys()
+        .chain(database_builtins().into_keys())
+        .chain(meta_builtins().into_keys());
+    let mut arities: HashMap<String, Option<Vec<usize>>> = names
+        .map(|name| {
+            let allowed = listed.remove(&name);
+            (name, allowed)
+        })
+        .collect();
+    for (name, allowed) in SOLVER_RELATIONS.iter() {
+        arities.insert(name.to_string(), allowed.map(|allowed| allowed.to_vec()));
+    }
+    arities
+}
+
+// Report calls to relations which aren't defined by the program,
+// the prelude or the builtins, suggesting the closest one which is
+pub fn check_undefined(program: &ProgramNode, prelude: Option<&ProgramNode>, source: &String) -> Vec<Error> {
+    let mut defined: HashSet<String> = HashSet::new();
+    let prelude_relations = prelude.iter().flat_map(|prelude| prelude.relations.iter());
+    for relation in program.relations.iter().chain(prelude_relations) {
+        defined.insert(relation.relation.name.clone());
+    }
+    for decl in program.declarations.iter() {
+        if let DeclarationNode::Dynamic(ddnode) = decl {
+            defined.insert(ddnode.relation.name.clone());
+        }
+    }
+    defined.extend(builtin_arities().into_keys());
+    (UndefinedRelations { state: source, defined: &defined }).visit_program(program)
+}
+
+struct UndefinedRelations<'a> {
+    state: &'a String,
+    defined: &'a HashSet<String>,
+}
+
+impl<'a> ASTVisitor<Error> for UndefinedRelations<'a> {
+    fn visit_relationid(&mut self, rid: &RelationId) -> Vec<Error> {
+        if self.defined.contains(&rid.name) {
+            return vec![];
+        }
+        // Operators and internal relations can't be called by name
+        let mut candidates: Vec<&String> = self.defined.iter()
+            .filter(|name| name.starts_with(char::is_alphabetic))
+            .collect();
+        candidates.sort();
+        let suggestion = candidates.into_iter()
+            .map(|name| (edit_distance(&rid.name, name), name))
+            .filter(|(distance, _)| *distance <= (rid.name.len() / 3).max(1))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, name)| name);
+        vec![Error::Code(check_undefined_snippet(&rid.span, &rid.name, suggestion, self.state).into())]
+    }
+}
+
+// Outputs are arguments too, so `fib(0) ~ 1` and a block which
+// relates one value both define fib/2. Clauses of a relation which
+// disagree are warned about, and calls to an arity which no clause
+// defines are errors, and so are calls to a builtin with
+// a number of arguments which it doesn't take
+pub fn check_arities(program: &ProgramNode, prelude: Option<&ProgramNode>, source: &str) -> Vec<Error> {
+    let mut result = vec![];
+    let mut arities: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut first_clauses: HashMap<&String, (usize, &RelationNode)> = HashMap::new();
+    for relation in program.relations.iter() {
+        let name = &relation.relation.name;
+        let arity = types::relation_arity(relation);
+        arities.entry(name.clone()).or_insert(vec![]).push(arity);
+        match first_clauses.get(name) {
+            None => {
+                first_clauses.insert(name, (arity, relation));
+            },
+            Some((first_arity, first)) if *first_arity != arity => {
+                let snippet = check_clause_arity_snippet(first, *first_arity, relation, arity, source);
+                result.push(Error::Code(Diagnostic::lint("arity", snippet)));
+            },
+            Some(_) => {},
+        }
+    }
+    for relation in prelude.iter().flat_map(|prelude| prelude.relations.iter()) {
+        arities.entry(relation.relation.name.clone()).or_insert(vec![]).push(types::relation_arity(relation));
+    }
+    for decl in program.declarations.iter() {
+        if let DeclarationNode::Dynamic(ddnode) = decl {
+            arities.entry(ddnode.relation.name.clone()).or_insert(vec![]).push(ddnode.arity);
+        }
+    }
+    for defined in arities.values_mut() {
+        defined.sort();
+        defined.dedup();
+    }
+    let builtins = builtin_arities();
+    let mut calls = CallSites {
+        on_call: |rel: &RelationId, arity: usize, span: &Span| {
+            // User relations can be called alongside a builtin with the same name
+            let mut defined = arities.get(&rel.name).cloned().unwrap_or_default();
+            match builtins.get(&rel.name) {
+                Some(None) => return vec![],
+                Some(Some(allowed)) => defined.extend(allowed),
+                // Relations which aren't defined at all are reported by check_undefined
+                None if defined.is_empty() => return vec![],
+                None => {},
+            }
+            if defined.contains(&arity) {
+                return vec![];
+            }
+            defined.sort();
+            defined.dedup();
+            vec![Error::Code(check_call_arity_snippet(span, &rel.name, arity, &defined, source).into())]
+        },
+    };
+    result.append(&mut calls.visit_program(program));
+    result
+}
+
+// Visits each call along with the arity which it calls,
+// where the outputs which are taken count as arguments
+struct CallSites<F> {
+    on_call: F,
+}
+
+impl<R, F: FnMut(&RelationId, usize, &Span) -> Vec<R>> ASTVisitor<R> for CallSites<F> {
+    fn visit_assignment(&mut self, assignment: &AssignmentNode) -> Vec<R> {
+        match &assignment.rhs.contents {
+            ExpressionContents::Call { rel, args } => {
+                let mut res = (self.on_call)(rel, args.len() + assignment.lhs.constants.len(), &assignment.span);
+                for arg in args.iter() {
+                    res.append(&mut self.visit_expr(arg));
+                }
+                res
+            },
+            _ => self.visit_expr(&assignment.rhs),
+        }
+    }
+
+    fn visit_relcall(&mut self, rcall: &RelationCallNode) -> Vec<R> {
+        let mut res = (self.on_call)(&rcall.rel, rcall.args.len(), &rcall.span);
+        for arg in rcall.args.iter() {
+            match &arg.contents {
+                // The fact given to assert or retract has no output
+                ExpressionContents::Call { rel, args } if is_database_relation(&rcall.rel.name) => {
+                    res.append(&mut (self.on_call)(rel, args.len(), &arg.span));
+                    for arg in args.iter() {
+                        res.append(&mut self.visit_expr(arg));
+                    }
+                },
+                _ => res.append(&mut self.visit_expr(arg)),
             }
         }
         res
     }
+
+    fn visit_expr(&mut self, expression: &ExpressionNode) -> Vec<R> {
+        let mut res = match &expression.contents {
+            ExpressionContents::Call { rel, args } => (self.on_call)(rel, args.len() + 1, &expression.span),
+            _ => vec![],
+        };
+        for subexpression in expression.subexpressions() {
+            res.append(&mut self.visit_expr(subexpression));
+        }
+        res
+    }
+}
+
+// The number of characters which need to be inserted,
+// deleted or replaced to turn one name into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = prev[j] + if ca == *cb { 0 } else { 1 };
+            row.push(replace.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+// Relations named main or test, or starting with test_, are where
+// a program starts, along with the relations which it exports
+fn is_entry_point(name: &str) -> bool {
+    name == "main" || name == "test" || name.starts_with("test_")
+}
+
+// Warn about relations which can't be reached from an entry point.
+// A program without any is only queried from the REPL, where any
+// relation could be used, so there's nothing to warn about
+pub fn check_unused(program: &ProgramNode, source: &str) -> Vec<Error> {
+    let exports: HashSet<(String, usize)> = program.declarations.iter()
+        .filter_map(|decl| match decl {
+            DeclarationNode::Export(ednode) => Some((ednode.relation.name.clone(), ednode.arity)),
+            _ => None,
+        })
+        .collect();
+    let mut first_clauses: Vec<((String, usize), &RelationNode)> = vec![];
+    let mut calls: HashMap<(String, usize), Vec<(String, usize)>> = HashMap::new();
+    let mut atoms: HashMap<(String, usize), Vec<String>> = HashMap::new();
+    let mut arities: HashMap<&String, Vec<usize>> = HashMap::new();
+    for relation in program.relations.iter() {
+        let key = (relation.relation.name.clone(), types::relation_arity(relation));
+        if !calls.contains_key(&key) {
+            first_clauses.push((key.clone(), relation));
+            arities.entry(&relation.relation.name).or_insert(vec![]).push(key.1);
+        }
+        let mut sites = CallSites {
+            on_call: |rel: &RelationId, arity: usize, _span: &Span| vec![(rel.name.clone(), arity)],
+        };
+        calls.entry(key.clone()).or_insert(vec![]).append(&mut sites.visit_relation(relation));
+        // Atoms can name relations for call and the prelude to run
+        atoms.entry(key).or_insert(vec![]).append(&mut AtomNames.visit_relation(relation));
+    }
+    let mut reached: HashSet<(String, usize)> = first_clauses.iter()
+        .map(|(key, _)| key.clone())
+        .filter(|key| is_entry_point(&key.0) || exports.contains(key))
+        .collect();
+    if reached.is_empty() {
+        return vec![];
+    }
+    let mut stack: Vec<(String, usize)> = reached.iter().cloned().collect();
+    while let Some(key) = stack.pop() {
+        let mut used: Vec<(String, usize)> = calls[&key].clone();
+        for name in atoms[&key].iter() {
+            for arity in arities.get(name).into_iter().flatten() {
+                used.push((name.clone(), *arity));
+            }
+        }
+        for callee in used {
+            if calls.contains_key(&callee) && reached.insert(callee.clone()) {
+                stack.push(callee);
+            }
+        }
+    }
+    first_clauses.into_iter()
+        .filter(|(key, _)| !reached.contains(key))
+        .map(|((name, arity), relation)| {
+            let snippet = check_unused_snippet(&relation.relation.span, &name, arity, source);
+            Error::Code(Diagnostic::lint("unused", snippet))
+        })
+        .collect()
+}
+
+struct AtomNames;
+impl ASTVisitor<String> for AtomNames {
+    fn visit_constant(&mut self, constant: &ConstantNode) -> Vec<String> {
+        let mut res = match &constant.contents {
+            ConstantContents::Atom(atom) => vec![atom[1..].to_string()],
+            _ => vec![],
+        };
+        for subconstant in constant.subconstants() {
+            res.append(&mut self.visit_constant(subconstant));
+        }
+        res
+    }
+}
+
+// Warn about clauses which are the same as an earlier one, apart from
+// the names of their variables. This covers facts, and blocks which
+// only relate values, since both always succeed with their head. The
+// later clause can only give answers which the earlier one already gave
+pub fn check_duplicate_clauses(program: &ProgramNode, source: &str) -> Vec<Error> {
+    let mut result = vec![];
+    let heads: Vec<Option<Vec<Shape>>> = program.relations.iter().map(clause_head).collect();
+    for (i, relation) in program.relations.iter().enumerate() {
+        let head = match &heads[i] {
+            Some(head) => head,
+            None => continue,
+        };
+        let earlier = program.relations[..i].iter().zip(heads.iter())
+            .find(|(earlier, earlier_head)| match earlier_head {
+                Some(earlier_head) => earlier.relation.name == relation.relation.name && same_head(earlier_head, head),
+                None => false,
+            });
+        if let Some((earlier, _)) = earlier {
+            let snippet = check_duplicate_snippet(&earlier.span, &relation.span, source);
+            result.push(Error::Code(Diagnostic::lint("duplicate", snippet)));
+        }
+    }
+    result
+}
+
+// The parts of a value which matter when comparing clause heads
+enum Shape<'a> {
+    Wildcard,
+    Var(&'a String),
+    Atom(&'a String),
+    Literal(&'a String),
+    List(Vec<Shape<'a>>),
+    ConsList(Vec<Shape<'a>>),
+    Tuple(Vec<Shape<'a>>),
+    Dict(Vec<(&'a String, Shape<'a>)>),
+}
+
+fn constant_shape<'a>(constant: &'a ConstantNode) -> Shape<'a> {
+    let items = || constant.subconstants().into_iter().map(constant_shape).collect();
+    match &constant.contents {
+        ConstantContents::EmptyPattern => Shape::Wildcard,
+        ConstantContents::Var(name) => Shape::Var(name),
+        ConstantContents::Atom(name) => Shape::Atom(name),
+        ConstantContents::Literal(n) => Shape::Literal(n),
+        ConstantContents::List(_) => Shape::List(items()),
+        ConstantContents::ConsList(_) => Shape::ConsList(items()),
+        ConstantContents::Tuple(_) => Shape::Tuple(items()),
+        ConstantContents::Dict(entries) => {
+            Shape::Dict(entries.iter().map(|(key, val)| (key, constant_shape(val))).collect())
+        },
+    }
+}
+
+// Expressions which are built only out of constants, and so
+// don't need anything to be run to find their value
+fn expr_shape<'a>(expr: &'a ExpressionNode) -> Option<Shape<'a>> {
+    let items = || expr.subexpressions().into_iter().map(expr_shape).collect::<Option<Vec<_>>>();
+    match &expr.contents {
+        ExpressionContents::Const(cnode) => Some(constant_shape(cnode)),
+        ExpressionContents::List { .. } => Some(Shape::List(items()?)),
+        ExpressionContents::ConsList { .. } => Some(Shape::ConsList(items()?)),
+        ExpressionContents::Tuple { .. } => Some(Shape::Tuple(items()?)),
+        ExpressionContents::Dict { entries } => {
+            let entries = entries.iter()
+                .map(|(key, val)| Some((key, expr_shape(val)?)))
+                .collect::<Option<Vec<_>>>()?;
+            Some(Shape::Dict(entries))
+        },
+        _ => None,
+    }
+}
+
+// The parameters and outputs of a clause which always succeeds without
+// running anything: a fact, or a block which only has a relate
+fn clause_head<'a>(relation: &'a RelationNode) -> Option<Vec<Shape<'a>>> {
+    let mut head: Vec<Shape> = relation.params.constants.iter().map(constant_shape).collect();
+    match &relation.block {
+        RelationBlock::Const(outputs) => {
+            head.extend(outputs.constants.iter().map(constant_shape));
+        },
+        RelationBlock::Block(bnode) => match &bnode.statements[..] {
+            [StatementNode::Relate(rnode)] => {
+                for expr in rnode.result.iter() {
+                    head.push(expr_shape(expr)?);
+                }
+            },
+            _ => return None,
+        },
+    }
+    Some(head)
+}
+
+fn same_head(head1: &[Shape], head2: &[Shape]) -> bool {
+    let mut renaming = HashMap::new();
+    let mut renamed = HashMap::new();
+    head1.len() == head2.len()
+        && head1.iter().zip(head2.iter()).all(|(s1, s2)| same_shape(s1, s2, &mut renaming, &mut renamed))
+}
+
+// Values are the same if renaming the variables of one, each
+// to a different name, gives the other
+fn same_shape<'a>(s1: &Shape<'a>, s2: &Shape<'a>,
+                  renaming: &mut HashMap<&'a String, &'a String>, renamed: &mut HashMap<&'a String, &'a String>) -> bool {
+    match (s1, s2) {
+        (Shape::Wildcard, Shape::Wildcard) => true,
+        (Shape::Var(v1), Shape::Var(v2)) => {
+            let to = *renaming.entry(v1).or_insert(v2);
+            let from = *renamed.entry(v2).or_insert(v1);
+            to == *v2 && from == *v1
+        },
+        (Shape::Atom(a1), Shape::Atom(a2)) => a1 == a2,
+        (Shape::Literal(l1), Shape::Literal(l2)) => l1 == l2,
+        (Shape::List(items1), Shape::List(items2))
+        | (Shape::ConsList(items1), Shape::ConsList(items2))
+        | (Shape::Tuple(items1), Shape::Tuple(items2)) => {
+            items1.len() == items2.len()
+                && items1.iter().zip(items2.iter()).all(|(i1, i2)| same_shape(i1, i2, renaming, renamed))
+        },
+        (Shape::Dict(entries1), Shape::Dict(entries2)) => {
+            entries1.len() == entries2.len()
+                && entries1.iter().zip(entries2.iter())
+                    .all(|((k1, i1), (k2, i2))| k1 == k2 && same_shape(i1, i2, renaming, renamed))
+        },
+        _ => false,
+    }
+}
+
+// A call which a clause makes before anything else, along with
+// whether one of its arguments is smaller than the clause's own
+struct LeftCall<'a, 'p> {
+    callee: (String, usize),
+    span: &'a Span<'p>,
+    smaller: bool,
+}
+
+// Warn about relations which can call themselves, directly or through
+// other relations, before doing anything else, and without making any
+// of their arguments smaller, since solving them never finishes.
+// Tabled relations remember their answers, so they can do this
+pub fn check_left_recursion(program: &ProgramNode, source: &str) -> Vec<Error> {
+    let tabled: HashSet<(String, usize)> = program.declarations.iter()
+        .filter_map(|decl| match decl {
+            DeclarationNode::Table(tdnode) => Some((tdnode.relation.name.clone(), tdnode.arity)),
+            _ => None,
+        })
+        .collect();
+    let defined: HashSet<(String, usize)> = program.relations.iter()
+        .map(|relation| (relation.relation.name.clone(), types::relation_arity(relation)))
+        .collect();
+    let mut left_calls: Vec<((String, usize), LeftCall)> = vec![];
+    for relation in program.relations.iter() {
+        let key = (relation.relation.name.clone(), types::relation_arity(relation));
+        if let RelationBlock::Block(bnode) = &relation.block {
+            if let Some(call) = left_call(relation, bnode) {
+                if defined.contains(&call.callee) && !call.smaller && !tabled.contains(&key) {
+                    left_calls.push((key, call));
+                }
+            }
+        }
+    }
+    let mut result = vec![];
+    let mut reported: Vec<Vec<(String, usize)>> = vec![];
+    for (start, call) in left_calls.iter() {
+        // The shortest way back to where the cycle started
+        let mut paths: HashMap<&(String, usize), Vec<&(String, usize)>> = HashMap::new();
+        let mut queue = std::collections::VecDeque::new();
+        paths.insert(&call.callee, vec![start, &call.callee]);
+        queue.push_back(&call.callee);
+        let mut cycle = None;
+        while let Some(curr) = queue.pop_front() {
+            if curr == start {
+                cycle = paths.get(curr).cloned();
+                break;
+            }
+            for (from, next) in left_calls.iter() {
+                if from == curr && !paths.contains_key(&next.callee) {
+                    let mut path = paths[curr].clone();
+                    path.push(&next.callee);
+                    paths.insert(&next.callee, path);
+                    queue.push_back(&next.callee);
+                }
+            }
+        }
+        if let Some(cycle) = cycle {
+            let mut members: Vec<(String, usize)> = cycle.iter().map(|key| (*key).clone()).collect();
+            members.sort();
+            members.dedup();
+            if reported.contains(&members) {
+                continue;
+            }
+            reported.push(members);
+            let path: Vec<String> = cycle.iter().map(|(name, arity)| format!("`{}/{}`", name, arity)).collect();
+            let snippet = check_left_recursion_snippet(call.span, &path.join(" -> "), start, source);
+            result.push(Error::Code(Diagnostic::lint("left_recursion", snippet)));
+        }
+    }
+    result
+}
+
+// The first call which solving a block makes. Calls in expressions
+// happen before the statement they're in, innermost first
+fn left_call<'a, 'p>(relation: &RelationNode<'p>, bnode: &'a BlockNode<'p>) -> Option<LeftCall<'a, 'p>> {
+    // Variables inside of a parameter are smaller than the parameter
+    let mut smaller: HashSet<&String> = HashSet::new();
+    let mut params: HashSet<&String> = HashSet::new();
+    for param in relation.params.constants.iter() {
+        match &param.contents {
+            ConstantContents::Var(name) => {
+                params.insert(name);
+            },
+            _ => smaller.extend(pattern_vars(param)),
+        }
+    }
+    let mut statements: Vec<&StatementNode> = bnode.statements.iter().collect();
+    statements.reverse();
+    while let Some(statement) = statements.pop() {
+        // Calls in expressions have an output, as well as their arguments
+        let nested = |(rel, args, span): CallParts<'a, 'p>| ((rel, args, span), args.len() + 1);
+        let ((rel, args, span), arity) = match statement {
+            StatementNode::Assignment(anode) => match &anode.rhs.contents {
+                // Taking apart a parameter gives smaller values
+                ExpressionContents::Const(ConstantNode { contents: ConstantContents::Var(name), .. }) => {
+                    if params.contains(name) || smaller.contains(name) {
+                        for constant in anode.lhs.constants.iter() {
+                            if let ConstantContents::Var(_) = &constant.contents {
+                                continue;
+                            }
+                            smaller.extend(pattern_vars(constant));
+                        }
+                    }
+                    continue;
+                },
+                ExpressionContents::Const(_) => continue,
+                ExpressionContents::Call { rel, args } => match args.iter().find_map(first_call) {
+                    Some(call) => nested(call),
+                    None => ((rel, args, &anode.span), args.len() + anode.lhs.constants.len()),
+                },
+                // Arithmetic is solved before anything it calls
+                _ => match first_call(&anode.rhs) {
+                    Some(call) => nested(call),
+                    None => return None,
+                },
+            },
+            StatementNode::Relation(rcall) => match rcall.args.iter().find_map(first_call) {
+                Some(call) => nested(call),
+                None => ((&rcall.rel, &rcall.args, &rcall.span), rcall.args.len()),
+            },
+            StatementNode::Relate(rnode) => match rnode.result.iter().find_map(first_call) {
+                Some(call) => nested(call),
+                None => continue,
+            },
+            StatementNode::BinaryFact(bfact) => match first_call(&bfact.lhs).or_else(|| first_call(&bfact.rhs)) {
+                Some(call) => nested(call),
+                None => return None,
+            },
+            StatementNode::Catch(cnode) => {
+                statements.extend(cnode.body.statements.iter().rev());
+                continue;
+            },
+            StatementNode::In(_) | StatementNode::Refute(_) => return None,
+        };
+        let is_smaller = args.iter().any(|arg| match &arg.contents {
+            ExpressionContents::Const(ConstantNode { contents: ConstantContents::Var(name), .. }) => smaller.contains(name),
+            _ => false,
+        });
+        return Some(LeftCall {
+            callee: (rel.name.clone(), arity),
+            span,
+            smaller: is_smaller,
+        });
+    }
+    None
+}
+
+type CallParts<'a, 'p> = (&'a RelationId<'p>, &'a Vec<ExpressionNode<'p>>, &'a Span<'p>);
+
+// The innermost and leftmost call in an expression
+fn first_call<'a, 'p>(expr: &'a ExpressionNode<'p>) -> Option<CallParts<'a, 'p>> {
+    if let Some(call) = expr.subexpressions().into_iter().find_map(first_call) {
+        return Some(call);
+    }
+    match &expr.contents {
+        ExpressionContents::Call { rel, args } => Some((rel, args, &expr.span)),
+        _ => None,
+    }
+}
+
+fn pattern_vars<'a>(constant: &'a ConstantNode) -> Vec<&'a String> {
+    match &constant.contents {
+        ConstantContents::Var(name) => vec![name],
+        _ => constant.subconstants().into_iter().flat_map(pattern_vars).collect(),
+    }
+}
+
+// Warn about variables which only appear once in a clause, since
+// they're usually typos. Names starting with _ are meant to be unused
+pub fn check_singletons(program: &ProgramNode, source: &String) -> Vec<Error> {
+    let mut result = vec![];
+    for relation in program.relations.iter() {
+        let mut singletons = Singletons {
+            state: source,
+            counts: HashMap::new(),
+            reporting: false,
+        };
+        // Count every use first, and then report the ones used once
+        for reporting in [false, true].iter() {
+            singletons.reporting = *reporting;
+            result.append(&mut singletons.visit_constlist(&relation.params));
+            result.append(&mut singletons.visit_relation(relation));
+        }
+    }
+    result
+}
+
+struct Singletons<'a> {
+    state: &'a String,
+    counts: HashMap<String, usize>,
+    reporting: bool,
+}
+
+impl<'a> ASTVisitor<Error> for Singletons<'a> {
+    fn visit_constant(&mut self, constant: &ConstantNode) -> Vec<Error> {
+        let mut res = vec![];
+        match &constant.contents {
+            ConstantContents::Var(name) if !name.starts_with('_') => {
+                if !self.reporting {
+                    *self.counts.entry(name.clone()).or_insert(0) += 1;
+                } else if self.counts[name] == 1 {
+                    let snippet = check_singleton_snippet(&constant.span, name, self.state);
+                    res.push(Error::Code(Diagnostic::lint("singleton", snippet)));
+                }
+            },
+            _ => {},
+        }
+        // Variables inside of lists, tuples and dicts
+        for item in constant.subconstants() {
+            res.append(&mut self.visit_constant(item));
+        }
+        res
+    }
 }
 
 // For each relation defined, check to make sure
@@ -103,7 +835,7 @@ fn check_relates_block(block: &BlockNode, source: &String) -> Result<()> {
                         let bottom_span = rnode.span.clone();
                         return Err(
                             Error::Code(
-                                check_relate_error_snippet(&top_span, val, &bottom_span, this_val, source)
+                                check_relate_error_snippet(&top_span, val, &bottom_span, this_val, source).into()
                             )
                         );
                     }
@@ -132,7 +864,7 @@ fn check_relate_error_snippet(span1: &Span, fst_retno: usize, span2: &Span, snd_
     Snippet {
         title: Some(Annotation {
             label: Some("mismatched relates".to_string()),
-            id: None,
+            id: Some(codes::MISMATCHED_RELATES.to_string()),
             annotation_type: AnnotationType::Error,
         }),
         footer: vec![
@@ -166,43 +898,481 @@ fn check_relate_error_snippet(span1: &Span, fst_retno: usize, span2: &Span, snd_
 }
 
 fn check_odd_ops_snippet(span: &Span, source: &String) -> Snippet {
-    let full_span = span.from_line_begin().to_line_end();
-    let starting_lno = span.line_no();
-    let range: (usize, usize) = (
-            full_span.distance_from_start(span.start), 
-            full_span.distance_from_start(span.end)
-        );
-    Snippet {
-        title: Some(Annotation {
-            label: Some("invalid operation".to_string()),
-            id: None,
-            annotation_type: AnnotationType::Error,
-        }),
-        footer: vec![
-            Annotation {
-                id: None,
-                label: Some("numeric operations can't be used on lists".to_string()),
-                annotation_type: AnnotationType::Note,
+    code_snippet(
+        "invalid operation".to_string(), codes::INVALID_OPERATION, AnnotationType::Error,
+        vec![(AnnotationType::Note, "numeric operations can't be used on lists".to_string())],
+        vec![span_slice(span, "invalid operation here".to_string(), AnnotationType::Error, source)],
+    )
+}
+
+// How much we know about whether a variable is bound
+// at some point in a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Groundness {
+    // Definitely unbound
+    Free,
+    // Might be (partially) bound
+    Maybe,
+    // Definitely bound to a ground term
+    Ground,
+}
+
+type ModeTable = HashMap<(String, usize), Vec<Vec<Mode>>>;
+
+// Propagate groundness through each block, warning when
+// builtins are reached with unbound arguments, and when calls
+// don't agree with any declared mode of a relation
+pub fn check_modes(program: &ProgramNode, source: &String) -> Vec<Error> {
+    let mut modes: ModeTable = HashMap::new();
+    let mut decls: HashMap<(String, usize), Vec<&ModeDeclNode>> = HashMap::new();
+    for decl in program.declarations.iter() {
+        if let DeclarationNode::Mode(mdnode) = decl {
+            let key = (mdnode.relation.name.clone(), mdnode.modes().len());
+            modes.entry(key.clone()).or_insert(vec![]).push(mdnode.modes());
+            decls.entry(key).or_insert(vec![]).push(mdnode);
+        }
+    }
+    let mut analysis = ModeAnalysis {
+        state: source,
+        modes: &modes,
+        decls: &decls,
+        ground: HashMap::new(),
+        reported: HashSet::new(),
+    };
+    let mut result = vec![];
+    for relation in program.relations.iter() {
+        let bnode = match &relation.block {
+            RelationBlock::Const(..) => continue,
+            RelationBlock::Block(bnode) => bnode,
+        };
+        let key = (relation.relation.name.clone(), types::relation_arity(relation));
+        // Analyse the block once for each way it can be called
+        let entry_modes: Vec<Option<&Vec<Mode>>> = match modes.get(&key) {
+            None => vec![None],
+            Some(ms) => ms.iter().map(Some).collect(),
+        };
+        for entry_mode in entry_modes {
+            analysis.ground.clear();
+            for (i, param) in relation.params.constants.iter().enumerate() {
+                let groundness = match entry_mode.map(|m| m[i]) {
+                    None | Some(Mode::Any) => Groundness::Maybe,
+                    Some(Mode::In) => Groundness::Ground,
+                    Some(Mode::Out) => Groundness::Free,
+                };
+                analysis.bind(param, groundness);
+            }
+            result.append(&mut analysis.visit_block(bnode));
+        }
+    }
+    result
+}
+
+struct ModeAnalysis<'a, 'p> {
+    state: &'a String,
+    modes: &'a ModeTable,
+    decls: &'a HashMap<(String, usize), Vec<&'a ModeDeclNode<'p>>>,
+    ground: HashMap<String, Groundness>,
+    // Spans which have already been warned about
+    reported: HashSet<(usize, usize)>,
+}
+
+impl<'a, 'p> ModeAnalysis<'a, 'p> {
+    fn bind(&mut self, cnode: &ConstantNode, groundness: Groundness) {
+        match &cnode.contents {
+            ConstantContents::Var(name) => {
+                let prev = self.ground.get(name).cloned().unwrap_or(Groundness::Free);
+                self.ground.insert(name.clone(), prev.max(groundness));
             },
-        ],
-        slices: vec![
-            Slice {
-                source: full_span.as_str().to_string(),
-                line_start: starting_lno,
-                origin: Some(source.clone()),
-                fold: false,
-                annotations: vec![
-                    SourceAnnotation {
-                        range: range,
-                        label: format!("invalid operation here").to_string(),
-                        annotation_type: AnnotationType::Error
-                    },
-                ],
+            ConstantContents::List(items)
+            | ConstantContents::ConsList(items)
+            | ConstantContents::Tuple(items) => {
+                for item in items.iter() {
+                    self.bind(item, groundness);
+                }
+            },
+            ConstantContents::Dict(entries) => {
+                for (_, item) in entries.iter() {
+                    self.bind(item, groundness);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn constant_groundness(&self, cnode: &ConstantNode) -> Groundness {
+        match &cnode.contents {
+            ConstantContents::EmptyPattern => Groundness::Free,
+            ConstantContents::Var(name) => self.ground.get(name).cloned().unwrap_or(Groundness::Free),
+            ConstantContents::Atom(..) | ConstantContents::Literal(..) => Groundness::Ground,
+            ConstantContents::List(items)
+            | ConstantContents::ConsList(items)
+            | ConstantContents::Tuple(items) => {
+                self.compound_groundness(items.iter().map(|c| self.constant_groundness(c)))
+            },
+            ConstantContents::Dict(entries) => {
+                self.compound_groundness(entries.iter().map(|(_, c)| self.constant_groundness(c)))
+            },
+        }
+    }
+
+    // A list is bound to something, even if its elements aren't
+    fn compound_groundness<I: Iterator<Item=Groundness>>(&self, mut items: I) -> Groundness {
+        if items.all(|g| g == Groundness::Ground) {
+            Groundness::Ground
+        } else {
+            Groundness::Maybe
+        }
+    }
+
+    fn warn(&mut self, res: &mut Vec<Error>, lint: &'static str, snippet: Snippet, span: &Span) {
+        if self.reported.insert((span.start, span.end)) {
+            res.push(Error::Code(Diagnostic::lint(lint, snippet)));
+        }
+    }
+
+    // Arguments which need to be bound (in a builtin or an operation)
+    fn require_bound(&mut self, expr: &ExpressionNode, groundness: Groundness, op: &str, res: &mut Vec<Error>) {
+        if groundness == Groundness::Free {
+            let span = types::expr_span(expr);
+            let snippet = check_unbound_snippet(&span, op, self.state);
+            self.warn(res, "unbound", snippet, &span);
+        }
+    }
+
+    fn eval_expr(&mut self, expr: &ExpressionNode, res: &mut Vec<Error>) -> Groundness {
+        match &expr.contents {
+            ExpressionContents::Const(cnode) => self.constant_groundness(cnode),
+            ExpressionContents::Operation { op, lhs, rhs } => {
+                let op_str = match op {
+                    BinaryOperation::Add => "+",
+                    BinaryOperation::Sub => "-",
+                    BinaryOperation::Mul => "*",
+                    BinaryOperation::Div => "/",
+                    BinaryOperation::Mod => "%",
+                };
+                let lground = self.eval_expr(lhs, res);
+                let rground = self.eval_expr(rhs, res);
+                self.require_bound(lhs, lground, op_str, res);
+                self.require_bound(rhs, rground, op_str, res);
+                if lground == Groundness::Ground && rground == Groundness::Ground {
+                    Groundness::Ground
+                } else {
+                    Groundness::Maybe
+                }
+            },
+            ExpressionContents::Call { rel, args } => {
+                let mut arg_ground: Vec<Groundness> = args.iter()
+                    .map(|arg| self.eval_expr(arg, res))
+                    .collect();
+                // The result is always a new temporary
+                arg_ground.push(Groundness::Free);
+                let span = expr.span.clone();
+                self.call(rel, &span, arg_ground, res).pop().unwrap()
+            },
+            ExpressionContents::List { vals }
+            | ExpressionContents::ConsList { vals }
+            | ExpressionContents::Tuple { vals } => {
+                let ground: Vec<Groundness> = vals.iter()
+                    .map(|val| self.eval_expr(val, res))
+                    .collect();
+                self.compound_groundness(ground.into_iter())
+            },
+            ExpressionContents::Dict { entries } => {
+                let ground: Vec<Groundness> = entries.iter()
+                    .map(|(_, val)| self.eval_expr(val, res))
+                    .collect();
+                self.compound_groundness(ground.into_iter())
+            },
+            ExpressionContents::Range { lo, hi, step, .. } => {
+                for bound in [Some(lo), Some(hi), step.as_ref()].iter().flatten() {
+                    let groundness = self.eval_expr(bound, res);
+                    self.require_bound(bound, groundness, "..", res);
+                }
+                Groundness::Ground
+            },
+        }
+    }
+
+    // Check a call against the declared modes of the relation, and
+    // return the groundness of every argument after the call
+    fn call(&mut self, rel: &RelationId, span: &Span, args: Vec<Groundness>, res: &mut Vec<Error>) -> Vec<Groundness> {
+        let maybe_bound: Vec<Groundness> = args.iter()
+            .map(|g| (*g).max(Groundness::Maybe))
+            .collect();
+        let key = (rel.name.clone(), args.len());
+        let declared = match self.modes.get(&key) {
+            None => return maybe_bound,
+            Some(declared) => declared,
+        };
+        let accepting: Vec<&Vec<Mode>> = declared.iter()
+            .filter(|modes| {
+                modes.iter().zip(args.iter())
+                    .all(|(mode, g)| *mode != Mode::In || *g != Groundness::Free)
+            })
+            .collect();
+        if accepting.is_empty() {
+            let snippet = check_mode_violation_snippet(span, &rel.name, &self.decls[&key], self.state);
+            self.warn(res, "mode", snippet, span);
+            return maybe_bound;
+        }
+        // Only arguments which every accepting mode binds are known to be bound
+        maybe_bound.into_iter().enumerate()
+            .map(|(i, g)| {
+                if accepting.iter().all(|modes| modes[i] == Mode::Out) {
+                    Groundness::Ground
+                } else {
+                    g
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a, 'p> ASTVisitor<Error> for ModeAnalysis<'a, 'p> {
+    fn visit_assignment(&mut self, assignment: &AssignmentNode) -> Vec<Error> {
+        let mut res = vec![];
+        let lhs = &assignment.lhs.constants;
+        match &assignment.rhs.contents {
+            ExpressionContents::Call { rel, args } => {
+                let mut arg_ground: Vec<Groundness> = args.iter()
+                    .map(|arg| self.eval_expr(arg, &mut res))
+                    .collect();
+                arg_ground.extend(lhs.iter().map(|c| self.constant_groundness(c)));
+                let after = self.call(rel, &assignment.rhs.span, arg_ground, &mut res);
+                for (constant, groundness) in lhs.iter().zip(after[args.len()..].iter()) {
+                    self.bind(constant, *groundness);
+                }
+            },
+            _ => {
+                let rground = self.eval_expr(&assignment.rhs, &mut res);
+                // Several patterns are matched against a tuple
+                let lground = if lhs.len() == 1 {
+                    self.constant_groundness(&lhs[0])
+                } else {
+                    self.compound_groundness(lhs.iter().map(|c| self.constant_groundness(c)))
+                };
+                for constant in lhs.iter() {
+                    self.bind(constant, rground);
+                }
+                // Unifying with a bound pattern binds the right hand side too
+                if let ExpressionContents::Const(cnode) = &assignment.rhs.contents {
+                    self.bind(cnode, lground);
+                }
             },
+        }
+        res
+    }
+
+    fn visit_relate(&mut self, relate: &RelateNode) -> Vec<Error> {
+        let mut res = vec![];
+        for result in relate.result.iter() {
+            self.eval_expr(result, &mut res);
+        }
+        res
+    }
+
+    fn visit_refute(&mut self, refute: &RefuteNode) -> Vec<Error> {
+        // Nothing bound inside of a refute is visible outside of it
+        let ground = self.ground.clone();
+        let mut res = vec![];
+        match &refute.body {
+            RefuteBody::Statement(statement) => {
+                res.append(&mut self.visit_statement(statement));
+            },
+            RefuteBody::Block(bnode) => {
+                res.append(&mut self.visit_block(bnode));
+            },
+        }
+        self.ground = ground;
+        res
+    }
+
+    fn visit_catch(&mut self, catch: &CatchNode) -> Vec<Error> {
+        // Either the body or the handler runs to completion
+        let before = self.ground.clone();
+        let mut res = self.visit_block(&catch.body);
+        let after_body = std::mem::replace(&mut self.ground, before);
+        self.bind(&catch.pattern, Groundness::Maybe);
+        res.append(&mut self.visit_block(&catch.handler));
+        let names: HashSet<String> = after_body.keys().chain(self.ground.keys()).cloned().collect();
+        for name in names {
+            let body_ground = after_body.get(&name).cloned().unwrap_or(Groundness::Free);
+            let handler_ground = self.ground.get(&name).cloned().unwrap_or(Groundness::Free);
+            let merged = if body_ground == handler_ground { body_ground } else { Groundness::Maybe };
+            self.ground.insert(name, merged);
+        }
+        res
+    }
+
+    fn visit_in(&mut self, in_node: &InNode) -> Vec<Error> {
+        let mut res = vec![];
+        let groundness = self.eval_expr(&in_node.collection, &mut res);
+        self.require_bound(&in_node.collection, groundness, "in", &mut res);
+        let element = if groundness == Groundness::Ground { Groundness::Ground } else { Groundness::Maybe };
+        self.bind(&in_node.pattern, element);
+        res
+    }
+
+    fn visit_bfact(&mut self, bfact: &BinaryFactNode) -> Vec<Error> {
+        let mut res = vec![];
+        let op = match bfact.op {
+            BinaryFactOperation::Gt => ">",
+            BinaryFactOperation::Lt => "<",
+            BinaryFactOperation::Leq => "<=",
+            BinaryFactOperation::Geq => ">=",
+            BinaryFactOperation::Equ => "==",
+            BinaryFactOperation::Neq => "!=",
+        };
+        let lground = self.eval_expr(&bfact.lhs, &mut res);
+        let rground = self.eval_expr(&bfact.rhs, &mut res);
+        self.require_bound(&bfact.lhs, lground, op, &mut res);
+        self.require_bound(&bfact.rhs, rground, op, &mut res);
+        res
+    }
+
+    fn visit_relcall(&mut self, rcall: &RelationCallNode) -> Vec<Error> {
+        let mut res = vec![];
+        let arg_ground: Vec<Groundness> = rcall.args.iter()
+            .map(|arg| self.eval_expr(arg, &mut res))
+            .collect();
+        let after = self.call(&rcall.rel, &rcall.span, arg_ground, &mut res);
+        for (arg, groundness) in rcall.args.iter().zip(after) {
+            if let ExpressionContents::Const(cnode) = &arg.contents {
+                self.bind(cnode, groundness);
+            }
+        }
+        res
+    }
+}
+
+fn fmt_mode_decl(mdnode: &ModeDeclNode) -> String {
+    let fmt_modes = |modes: &Vec<Mode>| {
+        modes.iter()
+            .map(|mode| match mode {
+                Mode::In => "in",
+                Mode::Out => "out",
+                Mode::Any => "any",
+            })
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+    match mdnode.outputs.len() {
+        0 => format!("{}({})", mdnode.relation.name, fmt_modes(&mdnode.params)),
+        1 => format!("{}({}) -> {}", mdnode.relation.name, fmt_modes(&mdnode.params), fmt_modes(&mdnode.outputs)),
+        _ => format!("{}({}) -> ({})", mdnode.relation.name, fmt_modes(&mdnode.params), fmt_modes(&mdnode.outputs)),
+    }
+}
+
+fn check_unbound_snippet(span: &Span, op: &str, source: &str) -> Snippet {
+    code_snippet(
+        "unbound argument".to_string(), codes::UNBOUND_ARGUMENT, AnnotationType::Warning,
+        vec![(AnnotationType::Note, format!("`{}` needs its arguments to be bound", op))],
+        vec![span_slice(span, "this is never bound before here".to_string(), AnnotationType::Warning, source)],
+    )
+}
+
+fn check_mode_violation_snippet(span: &Span, name: &str, decls: &[&ModeDeclNode], source: &str) -> Snippet {
+    let mut footer = vec![(AnnotationType::Note, "arguments with mode `in` must be bound when called".to_string())];
+    footer.extend(decls.iter().map(|mdnode| (AnnotationType::Note, format!("declared mode: {}", fmt_mode_decl(mdnode)))));
+    code_snippet(
+        "mode violation".to_string(), codes::MODE_VIOLATION, AnnotationType::Warning,
+        footer,
+        vec![span_slice(span, format!("no declared mode of `{}` allows this call", name), AnnotationType::Warning, source)],
+    )
+}
+
+fn check_singleton_snippet(span: &Span, name: &str, source: &str) -> Snippet {
+    code_snippet(
+        "singleton variable".to_string(), codes::SINGLETON_VARIABLE, AnnotationType::Warning,
+        vec![(AnnotationType::Help, format!("if this is intentional, name it `_{}` instead", name))],
+        vec![span_slice(span, format!("`{}` is only used once", name), AnnotationType::Warning, source)],
+    )
+}
+
+fn check_unused_snippet(span: &Span, name: &str, arity: usize, source: &str) -> Snippet {
+    code_snippet(
+        "unused relation".to_string(), codes::UNUSED_RELATION, AnnotationType::Warning,
+        vec![
+            (AnnotationType::Note, "relations are used when they can be reached from `main`, a test, or an export".to_string()),
+            (AnnotationType::Help, format!("if it's used from outside of the program, add `export {}/{};`", name, arity)),
+        ],
+        vec![span_slice(span, format!("`{}/{}` is never used", name, arity), AnnotationType::Warning, source)],
+    )
+}
+
+fn check_duplicate_snippet(first: &Span, span: &Span, source: &str) -> Snippet {
+    code_snippet(
+        "duplicate clause".to_string(), codes::DUPLICATE_CLAUSE, AnnotationType::Warning,
+        vec![(AnnotationType::Note, "it can only give answers which the first one already gave".to_string())],
+        vec![
+            span_slice(first, "the first clause is here".to_string(), AnnotationType::Info, source),
+            span_slice(span, "this clause is the same as an earlier one".to_string(), AnnotationType::Warning, source),
         ],
+    )
+}
+
+fn check_left_recursion_snippet(span: &Span, cycle: &str, start: &(String, usize), source: &str) -> Snippet {
+    code_snippet(
+        "left recursion".to_string(), codes::LEFT_RECURSION, AnnotationType::Warning,
+        vec![
+            (AnnotationType::Note, format!("the calls go {}", cycle)),
+            (AnnotationType::Help, format!("call something which makes an argument smaller first, or if the program is run through Prolog, add `table {}/{};`", start.0, start.1)),
+        ],
+        vec![span_slice(span, "this is called first, with no smaller arguments, so it can loop forever".to_string(), AnnotationType::Warning, source)],
+    )
+}
+
+fn fmt_arities(name: &str, arities: &[usize]) -> String {
+    let names: Vec<String> = arities.iter()
+        .map(|arity| format!("`{}/{}`", name, arity))
+        .collect();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => names.join(""),
     }
 }
 
+fn check_clause_arity_snippet(first: &RelationNode, first_arity: usize, clause: &RelationNode, arity: usize, source: &str) -> Snippet {
+    let name = &clause.relation.name;
+    code_snippet(
+        "clauses have different arities".to_string(), codes::CLAUSE_ARITIES, AnnotationType::Warning,
+        vec![(AnnotationType::Note, "outputs count as arguments, so `fib(0) ~ 1` defines `fib/2`".to_string())],
+        vec![
+            span_slice(&first.relation.span, format!("the first clause defines `{}/{}`", name, first_arity), AnnotationType::Info, source),
+            span_slice(&clause.relation.span, format!("but this clause defines `{}/{}`", name, arity), AnnotationType::Warning, source),
+        ],
+    )
+}
+
+fn check_call_arity_snippet(span: &Span, name: &str, arity: usize, defined: &[usize], source: &str) -> Snippet {
+    code_snippet(
+        "wrong number of arguments".to_string(), codes::WRONG_ARGUMENT_COUNT, AnnotationType::Error,
+        vec![
+            (AnnotationType::Note, format!("`{}` is defined as {}", name, fmt_arities(name, defined))),
+            (AnnotationType::Note, "outputs count as arguments, so `x ~ f(a)` calls `f/2`".to_string()),
+        ],
+        vec![span_slice(span, format!("this calls `{}/{}`, which isn't defined", name, arity), AnnotationType::Error, source)],
+    )
+}
+
+fn check_dict_keys_snippet(span: &Span, key: &str, source: &str) -> Snippet {
+    code_snippet(
+        "duplicate dict key".to_string(), codes::DUPLICATE_KEY, AnnotationType::Error,
+        vec![],
+        vec![span_slice(span, format!("`{}` is given more than once", key), AnnotationType::Error, source)],
+    )
+}
+
+fn check_undefined_snippet(span: &Span, name: &str, suggestion: Option<&String>, source: &str) -> Snippet {
+    code_snippet(
+        "undefined relation".to_string(), codes::UNDEFINED_RELATION, AnnotationType::Error,
+        suggestion.into_iter()
+            .map(|suggestion| (AnnotationType::Help, format!("did you mean `{}`?", suggestion)))
+            .collect(),
+        vec![span_slice(span, format!("no relation named `{}` is defined", name), AnnotationType::Error, source)],
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,7 +1392,7 @@ impossible(x) {
 
         let prog = parse_program(pairs, program_input.as_ref());
 
-        let errs = checks::perform_checks(&prog, "test".to_string());
+        let errs = checks::perform_checks(&prog, None, "test".to_string());
         assert!(errs.len() == 1);
         let err_msg = format!("{}", errs[0]).to_string();
         assert!(err_msg.contains("test"));
@@ -242,10 +1412,189 @@ impossible(sthing) {
 
         let prog = parse_program(pairs, program_input.as_ref());
 
-        let errs = checks::perform_checks(&prog, "test".to_string());
+        let errs = checks::perform_checks(&prog, None, "test".to_string());
         assert!(errs.len() == 1);
         let err_msg = format!("{}", errs[0]).to_string();
         assert!(err_msg.contains("test"));
         assert!(err_msg.contains("sthing + [1, 2]"))
     }
+
+    #[test]
+    pub fn test_edit_distance() {
+        assert_eq!(edit_distance("lenght", "length"), 2);
+        assert_eq!(edit_distance("map", "maps"), 1);
+    }
+
+    // Each case is the codes which it's about, a program, and the
+    // text which each error with one of those codes has, in order
+    type Case<'a> = (&'a [&'a str], &'a str, &'a [&'a [&'a str]]);
+
+    #[test]
+    pub fn invoke_checks() {
+        let cases: &[Case] = &[
+            (&[codes::UNBOUND_ARGUMENT, codes::MODE_VIOLATION], r#"
+mode length(in) -> out;
+length([]) ~ 0;
+length((a:as)) {
+    relate length(as) + 1
+};
+bad(l) {
+    n ~ length(m)
+    k > 3
+    relate n
+};
+good(l) {
+    n ~ length(l)
+    n > 3
+    relate n * 2
+};
+"#, &[
+                &["no declared mode of `length` allows this call", "length(in) -> out"],
+                &["k > 3", "`>` needs its arguments to be bound"],
+            ]),
+            (&[codes::UNDEFINED_RELATION], r#"
+double(x) {
+    relate x * 2
+};
+quad(x) {
+    relate doubel(double(x))
+};
+"#, &[
+                &["no relation named `doubel` is defined", "did you mean `double`?"],
+            ]),
+            (&[codes::CLAUSE_ARITIES, codes::WRONG_ARGUMENT_COUNT], r#"
+pair(x) ~ (x, x);
+pair(x) ~ x;
+swap((a, b)) {
+    relate (b, a)
+};
+use_pair(x) {
+    (a, b) ~ pair(x)
+    c ~ swap((a, b))
+    relate a + b + c
+};
+"#, &[
+                &["the first clause defines `pair/3`", "but this clause defines `pair/2`"],
+                &["this calls `swap/2`, which isn't defined", "`swap` is defined as `swap/3`"],
+            ]),
+            // Builtins take fixed numbers of arguments, unless a
+            // relation with the same name is defined alongside them
+            (&[codes::WRONG_ARGUMENT_COUNT], r#"
+length(a, b) ~ a;
+calls(x) {
+    y ~ functor(x)
+    length(x, y, y)
+    length(x, y, y, y)
+    throw(x, x)
+    nl()
+    writeln(x, y)
+    z ~ call('length, x)
+    relate y + z
+};
+"#, &[
+                &["this calls `functor/2`, which isn't defined", "`functor` is defined as `functor/3`"],
+                &["this calls `length/4`, which isn't defined", "`length` is defined as `length/2` and `length/3`"],
+                &["this calls `throw/2`, which isn't defined"],
+            ]),
+            (&[codes::UNUSED_RELATION], r#"
+export area/3;
+area(w, h) {
+    relate w * h
+};
+inc(x) {
+    relate x + 1
+};
+step(x) {
+    relate inc(x)
+};
+loop_a(x) {
+    relate loop_b(x)
+};
+loop_b(x) {
+    relate loop_a(x)
+};
+test_step() {
+    relate map('step, [1, 2])
+};
+"#, &[
+                &["`loop_a/2` is never used"],
+                &["`loop_b/2` is never used"],
+            ]),
+            // Clauses with a guard or an operation aren't compared
+            (&[codes::DUPLICATE_CLAUSE], r#"
+f(x) {
+    relate 1
+};
+f(y) ~ 1;
+g(x) ~ [x, 'a];
+g(y) {
+    relate [y, 'a]
+};
+h(x) {
+    x > 1
+    relate 1
+};
+h(y) ~ 1;
+k(x) {
+    relate x + 1
+};
+k(y) {
+    relate y + 1
+};
+p(x) ~ (x, x);
+p(y) {
+    relate (y, y)
+};
+"#, &[
+                &["f(y) ~ 1;"],
+                &["relate [y, 'a]"],
+                &["relate (y, y)"],
+            ]),
+            (&[codes::LEFT_RECURSION], r#"
+edge(1) ~ 2;
+path(a) {
+    relate edge(a)
+};
+path(a) {
+    b ~ path(a)
+    relate edge(b)
+};
+even(n) {
+    relate odd(n)
+};
+odd(n) {
+    x ~ even(n)
+    relate x
+};
+len([]) ~ 0;
+len((_:rest)) {
+    relate len(rest) + 1
+};
+table reach/2;
+reach(a) {
+    b ~ reach(a)
+    relate edge(b)
+};
+"#, &[
+                &["b ~ path(a)", "the calls go `path/2` -> `path/2`"],
+                &["the calls go `even/2` -> `odd/2` -> `even/2`"],
+            ]),
+        ];
+        for (wanted, program_input, expected) in cases.iter() {
+            let pairs = BevelParser::parse(Rule::program, program_input).unwrap();
+
+            let prog = parse_program(pairs, program_input);
+
+            let errs: Vec<String> = checks::perform_checks(&prog, None, "test".to_string()).iter()
+                .filter(|err| err.code().is_some_and(|code| wanted.contains(&code)))
+                .map(|err| format!("{}", err))
+                .collect();
+            assert_eq!(errs.len(), expected.len(), "{:?}", errs);
+            for (err, parts) in errs.iter().zip(expected.iter()) {
+                for part in parts.iter() {
+                    assert!(err.contains(part), "{:?} doesn't contain {:?}", err, part);
+                }
+            }
+        }
+    }
 }
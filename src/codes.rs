@@ -0,0 +1,358 @@
+// Every kind of diagnostic has a code, which stays the same between
+// versions, so tools can match on it and `bevel --explain` can say
+// more about it. New codes go at the end, and codes are never reused
+pub const MISMATCHED_RELATES: &str = "B0001";
+pub const INVALID_OPERATION: &str = "B0002";
+pub const UNKNOWN_TYPE: &str = "B0003";
+pub const MISMATCHED_TYPES: &str = "B0004";
+pub const UNBOUND_ARGUMENT: &str = "B0005";
+pub const MODE_VIOLATION: &str = "B0006";
+pub const UNDEFINED_RELATION: &str = "B0007";
+pub const SINGLETON_VARIABLE: &str = "B0008";
+pub const CLAUSE_ARITIES: &str = "B0009";
+pub const WRONG_ARGUMENT_COUNT: &str = "B0010";
+pub const SYNTAX_ERROR: &str = "B0011";
+pub const UNUSED_RELATION: &str = "B0012";
+pub const DUPLICATE_CLAUSE: &str = "B0013";
+pub const LEFT_RECURSION: &str = "B0014";
+pub const DUPLICATE_KEY: &str = "B0015";
+
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (MISMATCHED_RELATES, r#"The relates in a block give different numbers of outputs.
+
+Each `relate` gives the outputs of the relation, so every one in a
+block has to give the same number of them. For example:
+
+    sign(x) {
+        x < 0
+        relate -1
+        relate (x, 'negative)
+    };
+
+The first `relate` gives one output, and the second gives two. Either
+make them agree:
+
+    sign(x) {
+        x < 0
+        relate (-1, 'negative)
+    };
+
+or split the block into clauses which each relate one way.
+"#),
+    (INVALID_OPERATION, r#"A numeric operation is used on a list.
+
+Operations like `+` and `*` only work on numbers. For example:
+
+    both(a, b) {
+        relate [a] + [b]
+    };
+
+To join lists together, use `append` instead:
+
+    both(a, b) {
+        relate append([a], [b])
+    };
+"#),
+    (UNKNOWN_TYPE, r#"A type is used which was never declared.
+
+The types which can be used are the builtin ones, like `number`,
+`atom` and `any`, lists and tuples of them, and ones declared with
+`type`. For example:
+
+    paint(c: shade) ~ c;
+
+There is no type named `shade`, so it needs to be declared first:
+
+    type shade = 'light | 'dark;
+    paint(c: shade) ~ c;
+"#),
+    (MISMATCHED_TYPES, r#"A value doesn't have the type which it needs to have.
+
+When a relation declares the types of its arguments or outputs, the
+values given to it are checked against them. For example:
+
+    double(x: number) -> number {
+        relate x * 2
+    };
+    twice() {
+        relate double('two)
+    };
+
+`double` needs a number, but is given an atom. Give it a number, or
+change the declared type of the argument if any value is fine:
+
+    twice() {
+        relate double(2)
+    };
+"#),
+    (UNBOUND_ARGUMENT, r#"An argument is never given a value before it's needed.
+
+Comparisons and arithmetic, along with relations declared with an
+`in` mode, need their arguments to have values when they're run.
+For example:
+
+    big(l) {
+        k > 3
+        relate l
+    };
+
+`k` never has a value, so `k > 3` can't be checked. Usually this is
+a typo, or a statement which needs to come earlier:
+
+    big(l) {
+        k ~ length(l)
+        k > 3
+        relate l
+    };
+
+This is a warning from the `unbound` lint, which `-A unbound` allows.
+"#),
+    (MODE_VIOLATION, r#"A relation is called in a way which none of its modes allow.
+
+A `mode` declaration says which arguments of a relation have to have
+values when it's called (`in`), which it gives values to (`out`), and
+which can be either (`any`). For example:
+
+    mode length(in) -> out;
+    count() {
+        n ~ length(l)
+        relate n
+    };
+
+`length` needs its argument to have a value, but `l` doesn't have one.
+Give the argument a value first, or declare another mode which allows
+it to be unknown. This is a warning from the `mode` lint, which
+`-A mode` allows.
+"#),
+    (UNDEFINED_RELATION, r#"A relation is called which is never defined.
+
+Every relation which is called has to be defined by the program, by
+the prelude, or as a builtin, or be declared with `dynamic`. For
+example:
+
+    sizes(l) {
+        relate lenght(l)
+    };
+
+There is no relation named `lenght`. When a relation with a similar
+name exists, it's suggested, which here is `length`. Relations which
+are only added while the program runs, with `assert`, should be
+declared:
+
+    dynamic seen/1;
+"#),
+    (SINGLETON_VARIABLE, r#"A variable is only used once in a clause.
+
+A variable which only appears once can't connect anything together,
+so it's usually a typo. For example:
+
+    first((x:rest)) ~ x;
+
+`rest` is never used again. If that's intentional, start its name with
+an underscore, or use `_` on its own:
+
+    first((x:_rest)) ~ x;
+
+This is a warning from the `singleton` lint, which `-A singleton`
+allows.
+"#),
+    (CLAUSE_ARITIES, r#"The clauses of a relation have different numbers of arguments.
+
+The outputs of a relation count as arguments, so `fib(0) ~ 1` defines
+`fib/2`, the same as `fib(n) { relate ... }`. For example:
+
+    fib(0) ~ 1;
+    fib(1) ~ 1;
+    fib(n, m) ~ 2;
+
+The last clause defines `fib/3`, which is a different relation from
+the others, and won't be used when `fib` is called with one argument
+and one output. Make the clauses agree, or give the relations
+different names. This is a warning from the `arity` lint, which
+`-A arity` allows.
+"#),
+    (WRONG_ARGUMENT_COUNT, r#"A relation is called with a number of arguments which it doesn't have.
+
+The outputs taken from a call count as arguments, so `x ~ f(a)` calls
+`f/2`, and `(x, y) ~ f(a)` calls `f/3`. For example:
+
+    double(x) {
+        relate x * 2
+    };
+    both(x) {
+        (a, b) ~ double(x)
+        relate a + b
+    };
+
+`double` has one argument and one output, but is called for two
+outputs. Call it the way it's defined:
+
+    both(x) {
+        a ~ double(x)
+        relate a + a
+    };
+
+Builtins are checked the same way, so `y ~ functor(x)` is an error,
+since `functor` takes a term and gives its name and arity.
+"#),
+    (SYNTAX_ERROR, r#"The program couldn't be parsed.
+
+After a syntax error, the parser carries on at the next relation, or
+at the next statement in a block, so that every error can be fixed at
+once. Common mistakes are leaving out the `;` after a relation, or a
+bracket which is never closed. For example:
+
+    double(x) {
+        relate (x * 2
+    }
+
+needs the `(` to be closed, and a `;` after the block:
+
+    double(x) {
+        relate (x * 2)
+    };
+"#),
+    (UNUSED_RELATION, r#"A relation can't be reached from where the program starts.
+
+When a program has a `main` relation, test relations named `test` or
+starting with `test_`, or relations declared with `export`, those are
+where it starts. Any other relation which none of them can reach,
+through calls or atoms which name it, is never used. For example:
+
+    main() {
+        relate 1
+    };
+    helper(x) {
+        relate x + 1
+    };
+
+Nothing reaches `helper/2`, so it can be removed. If it's used from
+outside of the program, like from the REPL, export it:
+
+    export helper/2;
+
+Programs without any of these are only queried from the REPL, so none
+of their relations are reported. This is a warning from the `unused`
+lint, which `-A unused` allows.
+"#),
+    (DUPLICATE_CLAUSE, r#"A clause is the same as an earlier one.
+
+Two clauses are the same when they only differ by the names of their
+variables. The second one can only give answers which the first one
+already gave, so each answer is given twice. For example:
+
+    pair(x) ~ (x, x);
+    pair(y) {
+        relate (y, y)
+    };
+
+The second clause is the same as the first. Usually one of them was
+meant to be different, or can be removed. Only facts, and blocks which
+do nothing but relate values, are compared, since those always succeed
+with their head. This is a warning from the `duplicate` lint, which
+`-A duplicate` allows.
+"#),
+    (LEFT_RECURSION, r#"A relation can call itself before doing anything else.
+
+Relations are solved by trying their clauses in order, and solving
+the statements of a block from the top. When the first thing a clause
+does is call its own relation, directly or through other relations,
+without any of the arguments getting smaller, it can keep calling
+itself forever. For example:
+
+    path(a, b) {
+        c ~ path(a)
+        edge(c, b)
+    };
+
+Solving `path` starts by solving `path` again, with the same `a`.
+Putting a call which finds something smaller first fixes this:
+
+    path(a, b) {
+        edge(a, c)
+        path(c, b)
+    };
+
+Calls with a part of a parameter, like `len(rest)` in a clause for
+`len((x:rest))`, are smaller, so they're fine. A relation declared
+with `table path/2;` isn't warned about, and is printed as
+`:- table path/2.` by `bevel -p`, so that Prolog remembers its
+answers and the left recursion ends. Bevel's own solver doesn't
+table relations yet, so it still loops forever when run with `bevel`
+itself. This is a warning from the `left_recursion` lint, which
+`-A left_recursion` allows.
+"#),
+    (DUPLICATE_KEY, r#"A dict gives the same key more than once.
+
+Each key in a dict has one value, so a dict can't list a key twice.
+For example:
+
+    person() ~ {name: 'bob, age: 30, name: 'rob};
+
+gives `name` two different values. Remove one of them, or if they
+were meant to be different keys, rename one:
+
+    person() ~ {name: 'bob, age: 30, nickname: 'rob};
+"#),
+];
+
+// All of the codes, in order
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS.iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use crate::error::Error;
+
+    fn diagnostics(program_input: &str) -> Vec<Error> {
+        match syntax::parse_source(program_input, "test") {
+            Err(errs) => errs,
+            Ok(pairs) => {
+                let prog = parse_program(pairs, program_input);
+                checks::perform_checks(&prog, None, "test".to_string())
+            },
+        }
+    }
+
+    // A program which triggers each code
+    const TRIGGERS: &[(&str, &str)] = &[
+        (MISMATCHED_RELATES, "\nsign(x) {\n    relate -1\n    relate (x, 'negative)\n};\n"),
+        (INVALID_OPERATION, "\nboth(a, b) {\n    relate [a] + [b]\n};\n"),
+        (UNKNOWN_TYPE, "\npaint(c: shade) ~ c;\n"),
+        (MISMATCHED_TYPES, "\ndouble(x: number) -> number {\n    relate x * 2\n};\ntwice() {\n    relate double('two)\n};\n"),
+        (UNBOUND_ARGUMENT, "\nbig(l) {\n    k > 3\n    relate l\n};\n"),
+        (MODE_VIOLATION, "\nmode size(in) -> out;\nsize(l) ~ 1;\ncount() {\n    n ~ size(l)\n    relate n\n};\n"),
+        (UNDEFINED_RELATION, "\nsizes(l) {\n    relate lenght(l)\n};\n"),
+        (SINGLETON_VARIABLE, "\nfirst((x:rest)) ~ x;\n"),
+        (CLAUSE_ARITIES, "\nfib(0) ~ 1;\nfib(_n, _m) ~ 2;\n"),
+        (WRONG_ARGUMENT_COUNT, "\ndouble(x) {\n    relate x * 2\n};\nboth(x) {\n    (a, b) ~ double(x)\n    relate a + b\n};\n"),
+        (SYNTAX_ERROR, "\ndouble(x) {\n    relate (x * 2\n}\n"),
+        (UNUSED_RELATION, "\nmain() {\n    relate 1\n};\nhelper(x) {\n    relate x + 1\n};\n"),
+        (DUPLICATE_CLAUSE, "\npair(x) ~ (x, x);\npair(y) ~ (y, y);\n"),
+        (LEFT_RECURSION, "\nedge(1) ~ 2;\npath(a) ~ a;\npath(a) {\n    b ~ path(a)\n    relate edge(b)\n};\n"),
+        (DUPLICATE_KEY, "\nperson() ~ {name: 'bob, age: 30, name: 'rob};\n"),
+    ];
+
+    #[test]
+    fn test_every_code_triggers() {
+        for code in codes() {
+            let (_, program_input) = TRIGGERS.iter().find(|(c, _)| *c == code)
+                .unwrap_or_else(|| panic!("no test program for {}", code));
+            let errs = diagnostics(program_input);
+            let err = errs.iter().find(|err| err.code() == Some(code))
+                .unwrap_or_else(|| panic!("{} wasn't reported", code));
+            assert!(format!("{}", err).contains(&format!("[{}]", code)));
+        }
+        assert!(explain("b0001").is_some());
+        assert!(explain("B9999").is_none());
+    }
+}
@@ -1,11 +1,12 @@
 
-use annotate_snippets::snippet::Snippet;
+use annotate_snippets::snippet::{Annotation, Snippet, Slice, SourceAnnotation, AnnotationType};
 use annotate_snippets::display_list::DisplayList;
 use annotate_snippets::formatter::DisplayListFormatter;
 
 use std::result;
 use std::error;
 use std::fmt;
+use std::mem;
 use pest;
 
 use crate::span::Span;
@@ -14,18 +15,142 @@ use crate::Rule;
 #[derive(Debug)]
 pub enum Error {
     Parsing(pest::error::Error<Rule>),
-    Code(Snippet),
+    Code(Diagnostic),
     Formatting(fmt::Error),
 }
 
 pub type Result<A> = result::Result<A, Error>;
 
+// Only errors stop the program from running. Warnings
+// and notes are reported, and then it runs anyway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Level {
+    fn annotation_type(self) -> AnnotationType {
+        match self {
+            Level::Error => AnnotationType::Error,
+            Level::Warning => AnnotationType::Warning,
+            Level::Note => AnnotationType::Note,
+        }
+    }
+}
+
+// A problem found in a program. Warnings which come from a lint can
+// be allowed or turned into errors from the command line by its name
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub lint: Option<&'static str>,
+    pub snippet: Snippet,
+}
+
+impl Diagnostic {
+    pub fn lint(lint: &'static str, snippet: Snippet) -> Diagnostic {
+        Diagnostic {
+            lint: Some(lint),
+            ..snippet.into()
+        }
+    }
+
+    // Change the level, along with how the snippet is shown
+    pub fn set_level(&mut self, level: Level) {
+        let old = self.level.annotation_type();
+        self.level = level;
+        if let Some(title) = &mut self.snippet.title {
+            title.annotation_type = level.annotation_type();
+        }
+        for slice in self.snippet.slices.iter_mut() {
+            for annotation in slice.annotations.iter_mut() {
+                if mem::discriminant(&annotation.annotation_type) == mem::discriminant(&old) {
+                    annotation.annotation_type = level.annotation_type();
+                }
+            }
+        }
+    }
+}
+
+// The level of a snippet is the kind of its title
+impl From<Snippet> for Diagnostic {
+    fn from(snippet: Snippet) -> Self {
+        let level = match snippet.title.as_ref().map(|title| &title.annotation_type) {
+            Some(AnnotationType::Warning) => Level::Warning,
+            Some(AnnotationType::Note) | Some(AnnotationType::Info) | Some(AnnotationType::Help) => Level::Note,
+            _ => Level::Error,
+        };
+        Diagnostic {
+            level,
+            lint: None,
+            snippet,
+        }
+    }
+}
+
+impl Error {
+    pub fn level(&self) -> Level {
+        match self {
+            Error::Code(diagnostic) => diagnostic.level,
+            _ => Level::Error,
+        }
+    }
+
+    // The stable code of the diagnostic, like B0001
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Code(diagnostic) => diagnostic.snippet.title.as_ref().and_then(|title| title.id.as_deref()),
+            _ => None,
+        }
+    }
+
+    pub fn lint(&self) -> Option<&'static str> {
+        match self {
+            Error::Code(diagnostic) => diagnostic.lint,
+            _ => None,
+        }
+    }
+
+    // Warnings are reported, but don't stop the program from running
+    pub fn is_warning(&self) -> bool {
+        self.level() == Level::Warning
+    }
+}
+
+// Lints named by -A are left out, and ones named by -D are made into
+// errors. "warnings" names every lint, but a lint's own name wins
+pub fn apply_lint_levels(errs: Vec<Error>, allow: &[&str], deny: &[&str]) -> Vec<Error> {
+    errs.into_iter()
+        .filter_map(|mut err| {
+            if let Error::Code(diagnostic) = &mut err {
+                if let Some(lint) = diagnostic.lint {
+                    let level = if deny.contains(&lint) {
+                        Some(Level::Error)
+                    } else if allow.contains(&lint) {
+                        None
+                    } else if deny.contains(&"warnings") {
+                        Some(Level::Error)
+                    } else if allow.contains(&"warnings") {
+                        None
+                    } else {
+                        Some(diagnostic.level)
+                    };
+                    diagnostic.set_level(level?);
+                }
+            }
+            Some(err)
+        })
+        .collect()
+}
+
 impl error::Error for Error {
     fn description(&self) -> &str {
         match self {
             Error::Parsing(e) => <pest::error::Error<Rule> as error::Error>::description(&e),
-            Error::Code(s) => {
-                match &s.title {
+            Error::Code(d) => {
+                match &d.snippet.title {
                     None => "code error",
                     Some(title) => {
                         match &title.label {
@@ -46,10 +171,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut res_str = match self {
             Error::Parsing(e) => format!("parsing error:\n{}", e),
-            Error::Code(s) => {
+            Error::Code(d) => {
                 let dlf = DisplayListFormatter::new(true, false);
                 
-                let dl: DisplayList = s.clone().into();
+                let dl: DisplayList = d.snippet.clone().into();
 
                 dlf.format(&dl)
             },
@@ -61,6 +186,117 @@ impl fmt::Display for Error {
     }
 }
 
+fn severity(annotation_type: &AnnotationType) -> &'static str {
+    match annotation_type {
+        AnnotationType::Error => "error",
+        AnnotationType::Warning => "warning",
+        AnnotationType::Info => "info",
+        AnnotationType::Note => "note",
+        AnnotationType::Help => "help",
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn json_option(s: Option<&str>) -> String {
+    s.map_or("null".to_string(), json_string)
+}
+
+// A place in the source file, with lines and columns counted from 1
+struct Location {
+    byte: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn new(input: &str, byte: usize) -> Location {
+        let before = &input[..byte];
+        let line_begin = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            byte,
+            line: before.matches('\n').count() + 1,
+            column: before[line_begin..].chars().count() + 1,
+        }
+    }
+}
+
+// Snippets are shown with tabs as spaces, and their annotations count
+// newlines twice, so find where a range starts in the real source
+fn source_offset(input: &str, slice_start: usize, distance: usize) -> usize {
+    let mut curr = 0;
+    for (i, c) in input[slice_start..].char_indices() {
+        if curr >= distance {
+            return slice_start + i;
+        }
+        curr += match c {
+            '\t' => 4,
+            '\n' => 2,
+            _ => 1,
+        };
+    }
+    input.len()
+}
+
+fn json_label(input: &str, slice: &Slice, annotation: &SourceAnnotation, primary: bool) -> String {
+    // Slices start at the beginning of a line
+    let slice_start = input.split('\n').take(slice.line_start - 1).map(|line| line.len() + 1).sum();
+    let start = Location::new(input, source_offset(input, slice_start, annotation.range.0));
+    let end = Location::new(input, source_offset(input, slice_start, annotation.range.1));
+    format!("{{\"message\":{},\"severity\":\"{}\",\"primary\":{},\"file\":{},\
+        \"byte_start\":{},\"byte_end\":{},\"line_start\":{},\"column_start\":{},\"line_end\":{},\"column_end\":{}}}",
+        json_string(&annotation.label), severity(&annotation.annotation_type), primary, json_option(slice.origin.as_deref()),
+        start.byte, end.byte, start.line, start.column, end.line, end.column)
+}
+
+impl Error {
+    // The diagnostic as a line of JSON, for editors and other tools to read.
+    // The input is the source of the program, to find where labels are
+    pub fn to_json(&self, input: &str) -> String {
+        let (level, code, lint, message, file, labels, notes) = match self {
+            Error::Code(d) => {
+                let title = d.snippet.title.as_ref();
+                let level = severity(&d.level.annotation_type());
+                let mut labels = vec![];
+                for slice in d.snippet.slices.iter() {
+                    for annotation in slice.annotations.iter() {
+                        // The labels of the same kind as the diagnostic say what's wrong
+                        let primary = mem::discriminant(&annotation.annotation_type) == mem::discriminant(&d.level.annotation_type());
+                        labels.push(json_label(input, slice, annotation, primary));
+                    }
+                }
+                let notes: Vec<String> = d.snippet.footer.iter()
+                    .map(|note| format!("{{\"severity\":\"{}\",\"message\":{}}}",
+                        severity(&note.annotation_type), json_option(note.label.as_deref())))
+                    .collect();
+                (level, self.code().map(|code| code.to_string()), d.lint,
+                    title.and_then(|title| title.label.clone()).unwrap_or_default(),
+                    d.snippet.slices.first().and_then(|slice| slice.origin.clone()), labels, notes)
+            },
+            Error::Parsing(e) => ("error", None, None, format!("{}", e), None, vec![], vec![]),
+            Error::Formatting(e) => ("error", None, None, format!("{}", e), None, vec![], vec![]),
+        };
+        format!("{{\"severity\":\"{}\",\"code\":{},\"lint\":{},\"message\":{},\"file\":{},\"labels\":[{}],\"notes\":[{}]}}",
+            level, json_option(code.as_deref()), json_option(lint), json_string(&message), json_option(file.as_deref()),
+            labels.join(","), notes.join(","))
+    }
+}
+
 impl From<pest::error::Error<Rule>> for Error {
     fn from(error: pest::error::Error<Rule>) -> Self {
         Error::Parsing(error)
@@ -80,3 +316,41 @@ pub fn union_spans<'p>(span1: &Span<'p>, span2: &Span<'p>) -> Span<'p> {
         end: span2.end,
     }
 }
+
+// A diagnostic with a code, which shows the given slices of source
+pub fn code_snippet(title: String, code: &str, annotation_type: AnnotationType,
+        footer: Vec<(AnnotationType, String)>, slices: Vec<Slice>) -> Snippet {
+    Snippet {
+        title: Some(Annotation {
+            label: Some(title),
+            id: Some(code.to_string()),
+            annotation_type,
+        }),
+        footer: footer.into_iter()
+            .map(|(annotation_type, label)| Annotation {
+                id: None,
+                label: Some(label),
+                annotation_type,
+            })
+            .collect(),
+        slices,
+    }
+}
+
+// The lines which a span is on, with the span marked
+pub fn span_slice(span: &Span, label: String, annotation_type: AnnotationType, source: &str) -> Slice {
+    let full_span = span.from_line_begin().to_line_end();
+    Slice {
+        source: full_span.as_str().to_string(),
+        line_start: span.line_no(),
+        origin: Some(source.to_string()),
+        fold: false,
+        annotations: vec![
+            SourceAnnotation {
+                range: (full_span.distance_from_start(span.start), full_span.distance_from_start(span.end)),
+                label,
+                annotation_type,
+            },
+        ],
+    }
+}
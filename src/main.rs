@@ -2,8 +2,12 @@ pub mod ast;
 pub mod span;
 pub mod error;
 pub mod checks;
+pub mod types;
 pub mod solver;
 pub mod prolog_print;
+pub mod prelude;
+pub mod syntax;
+pub mod codes;
 
 extern crate clap;
 extern crate rand;
@@ -42,6 +46,55 @@ fn quit(e: Error) -> ! {
     exit(1)
 }
 
+// Print every error and warning, and stop if there were any errors.
+// With the source of the program, they're printed as lines of JSON
+#[cfg_attr(tarpaulin, skip)]
+fn report_errors(errs: &[Error], json_source: Option<&str>) {
+    let num_errs = errs.iter().filter(|err| !err.is_warning()).count();
+
+    if let Some(source) = json_source {
+        for err in errs.iter() {
+            eprintln!("{}", err.to_json(source));
+        }
+        if num_errs > 0 {
+            exit(1);
+        }
+        return;
+    }
+
+    for err in errs.iter() {
+        eprintln!("{}\n", err);
+    }
+
+    if num_errs > 0 {
+        eprintln!("aborting due to the previous {} error{}", num_errs, if num_errs != 1 { "s" } else { "" });
+        let mut codes: Vec<&str> = errs.iter()
+            .filter(|err| !err.is_warning())
+            .filter_map(|err| err.code())
+            .collect();
+        codes.sort();
+        codes.dedup();
+        if let Some(code) = codes.first() {
+            eprintln!("for more information about an error, try `bevel --explain {}`", code);
+        }
+        exit(1);
+    }
+}
+
+// Facts can be added to the program from the REPL, by
+// entering them like they would be written in a program
+fn add_repl_fact(input: &str, rules: &mut solver::Rules) -> bool {
+    match BevelParser::parse(Rule::repl_fact, input) {
+        Err(_) => false,
+        Ok(mut pairs) => {
+            let relation = pairs.next().unwrap().into_inner().next().unwrap();
+            let rnode = ast::RelationNode::parse(relation, input);
+            rules.assertz(solver::parse::parse_relation(&rnode));
+            true
+        },
+    }
+}
+
 #[cfg_attr(tarpaulin, skip)]
 fn main() {
     let matches = App::new("bevel")
@@ -50,7 +103,7 @@ fn main() {
             .about("Bevel Programming Language")
             .arg(Arg::with_name("INPUT")
                  .help("The bevel source input")
-                 .required(true)
+                 .required_unless("explain")
                  .index(1))
             .arg(Arg::with_name("prolog_print")
                  .short("p")
@@ -58,42 +111,108 @@ fn main() {
             .arg(Arg::with_name("from_stdin")
                  .short("i")
                  .help("Read queries from stdin instead of initializing a REPL"))
+            .arg(Arg::with_name("no_prelude")
+                 .long("no-prelude")
+                 .help("Don't load the standard prelude"))
+            .arg(Arg::with_name("deny")
+                 .short("D")
+                 .value_name("LINT")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .help("Make warnings from a lint into errors, or every lint's with -D warnings"))
+            .arg(Arg::with_name("allow")
+                 .short("A")
+                 .value_name("LINT")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .help("Don't report warnings from a lint, or any warnings with -A warnings"))
+            .arg(Arg::with_name("error_format")
+                 .long("error-format")
+                 .value_name("FORMAT")
+                 .takes_value(true)
+                 .possible_values(&["human", "json"])
+                 .default_value("human")
+                 .help("Print errors and warnings for people to read, or as lines of JSON"))
+            .arg(Arg::with_name("explain")
+                 .long("explain")
+                 .value_name("CODE")
+                 .takes_value(true)
+                 .help("Explain the error or warning with a code, like B0001"))
             .get_matches();
     
+    if let Some(code) = matches.value_of("explain") {
+        match codes::explain(code) {
+            Some(explanation) => print!("{}", explanation),
+            None => {
+                eprintln!("error: no error or warning has the code `{}`", code);
+                exit(1);
+            },
+        }
+        return;
+    }
+
     let input_file = matches.value_of("INPUT").unwrap();
 
+    let source_input = fs::read_to_string(input_file).expect("error reading input file");
+    let json_source = if matches.value_of("error_format") == Some("json") {
+        Some(source_input.as_ref())
+    } else {
+        None
+    };
+
     // Replace tabs with spaces for formatting errors
-    let program_input = fs::read_to_string(input_file).expect("error reading input file").replace("\t", "    ");
+    let program_input = source_input.replace("\t", "    ");
 
-    let pairs = BevelParser::parse(Rule::program, &program_input).unwrap_or_else(|e| quit(e.into()));
+    let pairs = syntax::parse_source(&program_input, input_file).unwrap_or_else(|errs| {
+        report_errors(&errs, json_source);
+        exit(1)
+    });
     
     let prog = parse_program(pairs, program_input.as_ref());
     
-    let errs = checks::perform_checks(&prog, input_file.to_string());
-    let num_errs = errs.len();
+    let prelude = if matches.is_present("no_prelude") {
+        None
+    } else {
+        Some(prelude::parse_prelude())
+    };
 
-    if num_errs > 0 {
-        for err in errs {
-            eprintln!("{}\n", err);
-        }
-        eprintln!("aborting due to the previous {} error{}", num_errs, if num_errs != 1 { "s" } else { "" });
-        exit(1);
-    }
+    let allow: Vec<&str> = matches.values_of("allow").map(|lints| lints.collect()).unwrap_or_default();
+    let deny: Vec<&str> = matches.values_of("deny").map(|lints| lints.collect()).unwrap_or_default();
+    let mut errs: Vec<Error> = allow.iter().chain(deny.iter())
+        .filter(|lint| **lint != "warnings" && !checks::LINTS.contains(lint))
+        .map(|lint| checks::unknown_lint(lint))
+        .collect();
+
+    errs.append(&mut checks::perform_checks(&prog, prelude.as_ref(), input_file.to_string()));
+    let errs = error::apply_lint_levels(errs, &allow, &deny);
+    report_errors(&errs, json_source);
     
     if matches.is_present("prolog_print") {
         let mut s = String::new();
 
         prog.prolog_print(&mut s).unwrap_or_else(|e| quit(e));
+        if let Some(prelude) = &prelude {
+            prelude::prolog_print_prelude(prelude, &prog, &mut s).unwrap_or_else(|e| quit(e));
+        }
 
         println!("{}", s);
     } else {
         let mut prog_rules = solver::parse::parse_program(&prog);
+        if let Some(prelude) = &prelude {
+            prelude::merge_prelude(prelude, &mut prog_rules);
+        }
 
         solver::optimize::apply_optimizations(&mut prog_rules);
         if matches.is_present("from_stdin") {
             let stdin = io::stdin();
             for input in stdin.lock().lines() {
                 let input = input.unwrap();
+                if add_repl_fact(&input, &mut prog_rules) {
+                    println!("success");
+                    continue;
+                }
                 let raw_parse = BevelParser::parse(Rule::query, &input).expect("Error parsing input!"); // TODO
                 let as_terms: Vec<solver::Term> = raw_parse.into_iter()
                     .map(|pair| {
@@ -106,6 +225,10 @@ fn main() {
                                 let rcallnode = ast::RelationCallNode::parse(pair, &input);
                                 solver::parse::parse_relationcall(&rcallnode, REPL_FRAME_ID).into_iter()
                             },
+                            Rule::in_stmt => {
+                                let inode = ast::InNode::parse(pair, &input);
+                                solver::parse::parse_in(&inode, REPL_FRAME_ID).into_iter()
+                            },
                             _ => unreachable!()
                         }
                     })
@@ -115,17 +238,20 @@ fn main() {
                     goals: as_terms
                 };
                 let mut state = solver::solve::new_solver_state(query);
-                let solution = solver::solve::solve(&prog_rules, solver::solve::SolverState {
+                let solution = solver::solve::solve(&mut prog_rules, solver::solve::SolverState {
                     master: &mut state.master,
                     curr_query: &mut state.curr_query,
                     new_query: &mut state.new_query,
                     fact_indx: &mut state.fact_indx,
+                    clauses: &mut state.clauses,
                     choice_points: &mut state.choice_points,
+                    catches: &mut state.catches,
                 });
                 
                 match &solution {
-                    None => println!("fail"),
-                    Some(solution) => {
+                    Err(ball) => println!("uncaught exception: {}", ball),
+                    Ok(None) => println!("fail"),
+                    Ok(Some(solution)) => {
                         let s = solver::fmt_unifier(&solution);
                         if s.trim().len() == 0 {
                             println!("success");
@@ -143,6 +269,10 @@ fn main() {
 
             while let ReadResult::Input(input) = reader.read_line().unwrap() {
                 reader.add_history(input.clone());
+                if add_repl_fact(&input, &mut prog_rules) {
+                    println!("success");
+                    continue;
+                }
                 let raw_parse = BevelParser::parse(Rule::query, &input).expect("Error parsing input!"); // TODO
                 let as_terms: Vec<solver::Term> = raw_parse.into_iter()
                     .map(|pair| {
@@ -155,6 +285,10 @@ fn main() {
                                 let rcallnode = ast::RelationCallNode::parse(pair, &input);
                                 solver::parse::parse_relationcall(&rcallnode, REPL_FRAME_ID).into_iter()
                             },
+                            Rule::in_stmt => {
+                                let inode = ast::InNode::parse(pair, &input);
+                                solver::parse::parse_in(&inode, REPL_FRAME_ID).into_iter()
+                            },
                             _ => unreachable!()
                         }
                     })
@@ -168,17 +302,20 @@ fn main() {
                 let mut line = String::new();
                 let stdin = io::stdin();
                 while !line.starts_with("q") {
-                    let solution = solver::solve::solve(&prog_rules, solver::solve::SolverState {
+                    let solution = solver::solve::solve(&mut prog_rules, solver::solve::SolverState {
                         master: &mut state.master,
                         curr_query: &mut state.curr_query,
                         new_query: &mut state.new_query,
                         fact_indx: &mut state.fact_indx,
+                        clauses: &mut state.clauses,
                         choice_points: &mut state.choice_points,
+                        catches: &mut state.catches,
                     });
                     
                     match &solution {
-                        None => { println!("fail"); break; },
-                        Some(solution) => {
+                        Err(ball) => { println!("uncaught exception: {}", ball); break; },
+                        Ok(None) => { println!("fail"); break; },
+                        Ok(Some(solution)) => {
                             let s = solver::fmt_unifier(&solution);
                             if s.trim().len() == 0 {
                                 println!("success");
@@ -196,6 +333,8 @@ fn main() {
                                         state.master = choice_point.0;
                                         state.curr_query = choice_point.1;
                                         state.fact_indx = choice_point.2;
+                                        state.clauses = Some(choice_point.3);
+                                        state.catches = choice_point.4;
                                     }
                                 }
                             }
@@ -278,8 +417,8 @@ swaptwo([x, y]) ~ [y, x];
             
             let prog = parse_program(pairs, program_input.as_ref());
             
-            let errs = checks::perform_checks(&prog, "test".to_string());
-            let num_errs = errs.len();
+            let errs = checks::perform_checks(&prog, None, "test".to_string());
+            let num_errs = errs.iter().filter(|err| !err.is_warning()).count();
             
             assert!(num_errs == 0);
             let mut prog_rules = solver::parse::parse_program(&prog);
@@ -307,14 +446,16 @@ swaptwo([x, y]) ~ [y, x];
                 goals: as_terms
             };
             let mut state = solver::solve::new_solver_state(query);
-            let solution = solver::solve::solve(&prog_rules, solver::solve::SolverState {
+            let solution = solver::solve::solve(&mut prog_rules, solver::solve::SolverState {
                 master: &mut state.master,
                 curr_query: &mut state.curr_query,
                 new_query: &mut state.new_query,
                 fact_indx: &mut state.fact_indx,
+                clauses: &mut state.clauses,
                 choice_points: &mut state.choice_points,
+                catches: &mut state.catches,
             });
-            match solution {
+            match solution.expect("uncaught exception") {
                 Some(solution) => {
                     let asstr = solver::fmt_unifier(&solution);
                     if asstr.trim().len() == 0 {
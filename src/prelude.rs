@@ -0,0 +1,116 @@
+use crate::ast::{parse_program, ProgramNode, RelationId, ASTVisitor};
+use crate::prolog_print::PrologPrint;
+use crate::solver::{self, Rules};
+use crate::{BevelParser, Rule};
+
+use pest::Parser;
+use std::collections::HashSet;
+use crate::error::Result;
+use std::fmt::Write;
+
+// Relations written in Bevel which every program can use
+pub const PRELUDE: &str = include_str!("prelude.bv");
+
+pub fn parse_prelude() -> ProgramNode<'static> {
+    let pairs = BevelParser::parse(Rule::program, PRELUDE).expect("error parsing the prelude");
+    parse_program(pairs, PRELUDE)
+}
+
+// The name and arity of every relation a program defines
+fn defined(prog: &ProgramNode) -> HashSet<(String, usize)> {
+    (*solver::parse::parse_program(prog).defined).clone()
+}
+
+// Put the prelude's rules before the program's, leaving out
+// any relations which the program defines itself
+pub fn merge_prelude(prelude: &ProgramNode, rules: &mut Rules) {
+    let mut merged: Vec<solver::Rule> = solver::parse::parse_program(prelude).contents.iter()
+        .filter(|rule| !rules.defined.contains(&rule.key()))
+        .cloned()
+        .collect();
+    merged.extend(rules.contents.iter().cloned());
+    *rules = Rules::new(merged, (*rules.dynamic).clone());
+}
+
+// Collects the names of the relations called
+struct CalledRelations {
+    names: HashSet<String>,
+}
+
+impl ASTVisitor<()> for CalledRelations {
+    fn visit_relationid(&mut self, rid: &RelationId) -> Vec<()> {
+        self.names.insert(rid.name.clone());
+        vec![]
+    }
+}
+
+// Print the prelude's relations which the program calls, and
+// the ones those call in turn, so the Prolog source is complete
+pub fn prolog_print_prelude<W: Write>(prelude: &ProgramNode, prog: &ProgramNode, w: &mut W) -> Result<()> {
+    let user_defined = defined(prog);
+    let mut called = CalledRelations {
+        names: HashSet::new(),
+    };
+    called.visit_program(prog);
+    let mut printed: HashSet<String> = HashSet::new();
+    loop {
+        let mut more = false;
+        for relation in prelude.relations.iter() {
+            let name = relation.relation.name.clone();
+            let arity = solver::parse::parse_relation(relation).gives.args.len();
+            if called.names.contains(&name) && !printed.contains(&name)
+                && !user_defined.contains(&(name.clone(), arity)) {
+                more = true;
+                printed.insert(name);
+            }
+        }
+        if !more {
+            break;
+        }
+        for relation in prelude.relations.iter() {
+            if printed.contains(&relation.relation.name) {
+                called.visit_relation(relation);
+            }
+        }
+    }
+    for relation in prelude.relations.iter() {
+        if printed.contains(&relation.relation.name) {
+            relation.prolog_print(w)?;
+            writeln!(w, ".")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::perform_checks;
+
+    #[test]
+    fn test_prelude_checks() {
+        let prelude = parse_prelude();
+        let errs = perform_checks(&prelude, None, "prelude".to_string());
+        assert!(errs.is_empty(), "{:?}", errs.iter().map(|err| err.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_merge_prelude() {
+        let source = "map(f, l) ~ 'mine;\n".to_string();
+        let pairs = BevelParser::parse(Rule::program, &source).unwrap();
+        let prog = parse_program(pairs, &source);
+        let mut rules = solver::parse::parse_program(&prog);
+        merge_prelude(&parse_prelude(), &mut rules);
+
+        // Only the program's own map is left
+        let maps: Vec<&solver::Rule> = rules.contents.iter()
+            .filter(|rule| rule.gives.name == "map")
+            .collect();
+        assert_eq!(maps.len(), 1);
+        assert!(rules.contents.iter().any(|rule| rule.gives.name == "filter"));
+
+        let mut s = String::new();
+        prolog_print_prelude(&parse_prelude(), &prog, &mut s).unwrap();
+        assert_eq!(s, "");
+    }
+}
@@ -17,12 +17,62 @@ pub trait PrologPrintVal {
     fn prolog_print_val<W: Write>(&self, w: &mut W) -> Result<String>;
 }
 
+// Builtins which are spelled differently in Prolog
+const PROLOG_NAMES: &[(&str, &str)] = &[
+    ("univ", "'=..'"),
+    ("term_lt", "'@<'"),
+    ("term_gt", "'@>'"),
+    ("term_leq", "'@=<'"),
+    ("term_geq", "'@>='"),
+    ("compare", "bevel_compare"),
+    ("predsort", "bevel_predsort"),
+    ("nth", "nth1"),
+    ("read_line", "bevel_read_line"),
+    // Reads Prolog terms ending in a ., rather than Bevel's syntax
+    ("read_term", "read"),
+    ("format", "bevel_format"),
+];
+
+// Clauses for builtins whose arguments are in a different order
+// in Prolog, which are printed if the program uses them
+const PROLOG_HELPERS: &[(&str, &str)] = &[
+    ("bevel_compare(", "bevel_compare(A, B, O) :- compare(O, A, B)."),
+    ("bevel_predsort(", "bevel_predsort(P, L, S) :- predsort([O, A, B]>>call(P, A, B, O), L, S)."),
+    ("sort_by_key(", "sort_by_key(N, L, S) :- sort(N, @=<, L, S)."),
+    ("zip(", "zip(A, B, Z) :- maplist([X, Y, tuple(X, Y)]>>true, A, B, Z)."),
+    ("bevel_read_line(", "bevel_read_line(L) :- bevel_read_line(user_input, L).\n\
+        bevel_read_line(S, L) :- read_line_to_string(S, L0), (L0 == end_of_file -> L = L0 ; atom_string(L, L0))."),
+    ("bevel_format(", "bevel_format(F, A) :- format(F, A).\n\
+        bevel_format(F, A, S) :- format(atom(S), F, A)."),
+    ("format_to_string(", "format_to_string(F, A, S) :- format(atom(S), F, A)."),
+    ("read_file_to_lines(", "read_file_to_lines(F, Ls) :- read_file_to_string(F, S, []), \
+        split_string(S, \"\\n\", \"\\r\", Ls0), (append(Ls1, [\"\"], Ls0) -> true ; Ls1 = Ls0), \
+        maplist([L0, L]>>atom_string(L, L0), Ls1, Ls)."),
+];
+
 impl<'p> PrologPrint for ProgramNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
+        for decl in self.declarations.iter() {
+            match decl {
+                DeclarationNode::Dynamic(ddnode) => writeln!(w, ":- dynamic {}/{}.", ddnode.relation.name, ddnode.arity)?,
+                DeclarationNode::Table(tdnode) => writeln!(w, ":- table {}/{}.", tdnode.relation.name, tdnode.arity)?,
+                _ => {},
+            }
+        }
+        let mut relations = String::new();
         for relation in self.relations.iter() {
-            relation.prolog_print(w)?;
-            write!(w, ".\n")?;
+            relation.prolog_print(&mut relations)?;
+            writeln!(relations, ".")?;
+        }
+        for (used, helper) in PROLOG_HELPERS.iter() {
+            // Relations defined by the program replace builtins
+            let defined = self.relations.iter()
+                .any(|rnode| used.starts_with(&format!("{}(", rnode.relation.name)));
+            if relations.contains(used) && !defined {
+                writeln!(w, "{}", helper)?;
+            }
         }
+        write!(w, "{}", relations)?;
         Ok(())
     }
 }
@@ -31,7 +81,16 @@ impl<'p> PrologPrint for RelationNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
         self.relation.prolog_print(w)?;
         write!(w, "(")?;
-        self.params.prolog_print(w)?;
+        let mut dict_goals = vec![];
+        let mut first = true;
+        for param in self.params.constants.iter() {
+            if !first {
+                write!(w, ", ")?;
+            } else {
+                first = false;
+            }
+            prolog_print_pattern(param, w, &mut dict_goals)?;
+        }
         let pcomma = self.params.constants.len() > 0;
         match &self.block {
             RelationBlock::Const(cnode) => {
@@ -40,6 +99,9 @@ impl<'p> PrologPrint for RelationNode<'p> {
                 }
                 cnode.prolog_print(w)?;
                 write!(w, ")")?;
+                if !dict_goals.is_empty() {
+                    write!(w, " :- {}", dict_goals.join(", "))?;
+                }
             },
             RelationBlock::Block(block) => {
                 let num_results = find_num_results(block);
@@ -54,6 +116,9 @@ impl<'p> PrologPrint for RelationNode<'p> {
                     }
                 }
                 write!(w, ") :- ")?;
+                for goal in dict_goals.iter() {
+                    write!(w, "{}, ", goal)?;
+                }
                 block.prolog_print(w)?;
             }
         }
@@ -61,9 +126,81 @@ impl<'p> PrologPrint for RelationNode<'p> {
     }
 }
 
+fn prolog_atom(name: &str) -> String {
+    let plain = name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    match name.chars().next() {
+        Some(c) if plain && c.is_lowercase() => name.to_string(),
+        Some(c) if plain && c.is_alphabetic() => format!("atom_{}", name),
+        // Strings, which are quoted atoms in Prolog
+        _ => format!("'{}'", name.replace("\\", "\\\\").replace("'", "\\'").replace("\n", "\\n")),
+    }
+}
+
+// Print a pattern which is being matched against. Dict patterns
+// only need their own keys to be present, so they're printed as
+// a variable and a goal selecting those keys from it
+fn prolog_print_pattern<W: Write>(cnode: &ConstantNode, w: &mut W, goals: &mut Vec<String>) -> Result<()> {
+    match &cnode.contents {
+        ConstantContents::List(l) | ConstantContents::ConsList(l) => {
+            let cons = matches!(cnode.contents, ConstantContents::ConsList(_));
+            write!(w, "[")?;
+            for i in 0..l.len() {
+                if i == l.len() - 1 && cons {
+                    write!(w, "|")?;
+                } else if i != 0 {
+                    write!(w, ", ")?;
+                }
+                prolog_print_pattern(&l[i], w, goals)?;
+            }
+            write!(w, "]")?;
+        },
+        ConstantContents::Tuple(items) => {
+            write!(w, "tuple(")?;
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    write!(w, ", ")?;
+                }
+                prolog_print_pattern(item, w, goals)?;
+            }
+            write!(w, ")")?;
+        },
+        ConstantContents::Dict(entries) => {
+            let mut rng = thread_rng();
+            let name: String = format!("Dict{}", iter::repeat(())
+                .map(|()| rng.sample(Alphanumeric))
+                .filter(|c| !c.is_ascii_digit())
+                .take(6)
+                .collect::<String>());
+            let mut inner_goals = vec![];
+            let mut select = String::new();
+            write!(select, "_{{")?;
+            let mut first = true;
+            for (key, val) in entries.iter() {
+                if !first {
+                    write!(select, ", ")?;
+                } else {
+                    first = false;
+                }
+                write!(select, "{}: ", prolog_atom(key))?;
+                prolog_print_pattern(val, &mut select, &mut inner_goals)?;
+            }
+            write!(select, "}} :< {}", name)?;
+            goals.push(format!("is_dict({})", name));
+            goals.push(select);
+            goals.append(&mut inner_goals);
+            write!(w, "{}", name)?;
+        },
+        _ => cnode.prolog_print(w)?,
+    }
+    Ok(())
+}
+
 impl<'p> PrologPrint for RelationId<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
-        write!(w, "{}", self.name)?;
+        match PROLOG_NAMES.iter().find(|(name, _)| *name == self.name) {
+            Some((_, prolog_name)) => write!(w, "{}", prolog_name)?,
+            None => write!(w, "{}", self.name)?,
+        }
         Ok(())
     }
 }
@@ -107,12 +244,10 @@ impl<'p> PrologPrint for ConstantNode<'p> {
                 write!(w, "_")?;
             },
             ConstantContents::Atom(x) => {
-                if x.chars().nth(1).unwrap().is_lowercase() {
-                    write!(w, "{}", x[1..].to_string())?;
-                } else {
-                    write!(w, "atom_{}", x[1..].to_string())?;
-                }
+                write!(w, "{}", prolog_atom(&x[1..]))?;
             },
+            // Prolog doesn't warn about singletons starting with _ either
+            ConstantContents::Var(x) if x.starts_with('_') => write!(w, "_Var{}", x)?,
             ConstantContents::Var(x) => write!(w, "Var_{}", x)?,
             ConstantContents::Literal(x) => write!(w, "{}", x)?,
             ConstantContents::List(l) => {
@@ -142,7 +277,31 @@ impl<'p> PrologPrint for ConstantNode<'p> {
                 write!(w, "|")?;
                 l[l.len() - 1].prolog_print(w)?;
                 write!(w, "]")?;
-            }
+            },
+            ConstantContents::Tuple(items) => {
+                write!(w, "tuple(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(w, ", ")?;
+                    }
+                    item.prolog_print(w)?;
+                }
+                write!(w, ")")?;
+            },
+            ConstantContents::Dict(entries) => {
+                write!(w, "_{{")?;
+                let mut first = true;
+                for (key, val) in entries.iter() {
+                    if first {
+                        first = false;
+                    } else {
+                        write!(w, ", ")?;
+                    }
+                    write!(w, "{}: ", prolog_atom(key))?;
+                    val.prolog_print(w)?;
+                }
+                write!(w, "}}")?;
+            },
         }
         Ok(())
     }
@@ -154,6 +313,8 @@ impl<'p> PrologPrint for StatementNode<'p> {
             StatementNode::Assignment(anode) => anode.prolog_print(w)?,
             StatementNode::Relate(rnode) => rnode.prolog_print(w)?,
             StatementNode::Refute(rnode) => rnode.prolog_print(w)?,
+            StatementNode::Catch(cnode) => cnode.prolog_print(w)?,
+            StatementNode::In(inode) => inode.prolog_print(w)?,
             StatementNode::BinaryFact(bfnode) => bfnode.prolog_print(w)?,
             StatementNode::Relation(rnode) => rnode.prolog_print(w)?,
         }
@@ -163,11 +324,30 @@ impl<'p> PrologPrint for StatementNode<'p> {
 
 impl<'p> PrologPrint for RelationCallNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
+            let database_call = is_database_relation(&self.rel.name);
             let mut arg_names = Vec::with_capacity(self.args.len());
             for arg in self.args.iter() {
-                arg_names.push(arg.prolog_print_val(w)?);
+                match &arg.contents {
+                    // The fact given to assert or retract isn't called
+                    ExpressionContents::Call { rel, args } if database_call => {
+                        let mut fact = String::new();
+                        let mut fact_args = Vec::with_capacity(args.len());
+                        for fact_arg in args.iter() {
+                            fact_args.push(fact_arg.prolog_print_val(w)?);
+                        }
+                        rel.prolog_print(&mut fact)?;
+                        write!(fact, "({})", fact_args.join(", "))?;
+                        arg_names.push(fact);
+                    },
+                    _ => arg_names.push(arg.prolog_print_val(w)?),
+                }
+            }
+            if self.rel.name == "assert" {
+                // assert is deprecated in most prologs
+                write!(w, "assertz")?;
+            } else {
+                self.rel.prolog_print(w)?;
             }
-            self.rel.prolog_print(w)?;
             write!(w, "(")?;
             let mut first = true;
             for arg in arg_names.iter() {
@@ -185,6 +365,7 @@ impl<'p> PrologPrint for RelationCallNode<'p> {
 
 impl<'p> PrologPrint for AssignmentNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut dict_goals = vec![];
         match &self.rhs.contents {
             ExpressionContents::Call {rel, args} => {
                 let mut arg_names = Vec::with_capacity(args.len());
@@ -202,33 +383,135 @@ impl<'p> PrologPrint for AssignmentNode<'p> {
                     }
                     write!(w, "{}", arg)?;
                 }
-                if !first {
-                    write!(w, ", ")?;
+                for constant in self.lhs.constants.iter() {
+                    if !first {
+                        write!(w, ", ")?;
+                    } else {
+                        first = false;
+                    }
+                    prolog_print_pattern(constant, w, &mut dict_goals)?;
                 }
-                self.lhs.prolog_print(w)?;
                 write!(w, ")")?;
             },
             _ => {
                 let list = &self.lhs.constants;
-                if list.len() > 1 {
-                    panic!("cannot assign to tuple: {}", self.span.as_str());
-                }
                 let result = self.rhs.prolog_print_val(w)?;
-                list[0].prolog_print(w)?;
+                if list.len() == 1 {
+                    prolog_print_pattern(&list[0], w, &mut dict_goals)?;
+                } else {
+                    // Several patterns destructure a tuple
+                    write!(w, "tuple(")?;
+                    for (i, constant) in list.iter().enumerate() {
+                        if i != 0 {
+                            write!(w, ", ")?;
+                        }
+                        prolog_print_pattern(constant, w, &mut dict_goals)?;
+                    }
+                    write!(w, ")")?;
+                }
                 write!(w, " = {}", result)?;
             }
         }
+        for goal in dict_goals.iter() {
+            write!(w, ", {}", goal)?;
+        }
         Ok(())
     }
 }
 
 impl<'p> PrologPrint for RefuteNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
-        write!(w, "\\+ ")?;
-        self.statement.prolog_print(w)?;
+        write!(w, "\\+ (")?;
+        match &self.body {
+            RefuteBody::Statement(statement) => statement.prolog_print(w)?,
+            RefuteBody::Block(bnode) => bnode.prolog_print(w)?,
+        }
+        write!(w, ")")?;
+        Ok(())
+    }
+}
+impl<'p> PrologPrint for CatchNode<'p> {
+    fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut rng = thread_rng();
+        let ball: String = format!("Ball{}", iter::repeat(())
+            .map(|()| rng.sample(Alphanumeric))
+            .filter(|c| !c.is_ascii_digit())
+            .take(6)
+            .collect::<String>());
+        // Anything the pattern doesn't match is thrown again
+        let mut dict_goals = vec![];
+        let mut pattern = String::new();
+        prolog_print_pattern(&self.pattern, &mut pattern, &mut dict_goals)?;
+        write!(w, "catch((")?;
+        self.body.prolog_print(w)?;
+        write!(w, "), {}, (({} = {}", ball, pattern, ball)?;
+        for goal in dict_goals.iter() {
+            write!(w, ", {}", goal)?;
+        }
+        write!(w, ") -> (")?;
+        self.handler.prolog_print(w)?;
+        write!(w, ") ; throw({})))", ball)?;
         Ok(())
     }
 }
+
+fn fresh_name(prefix: &str) -> String {
+    let mut rng = thread_rng();
+    format!("{}{}", prefix, iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .filter(|c| !c.is_ascii_digit())
+        .take(6)
+        .collect::<String>())
+}
+
+// Print the goals which bind each element of a range in turn
+// to a new variable, giving back its name
+fn prolog_print_range<W: Write, V: Write>(lo: &ExpressionNode, hi: &ExpressionNode, step: &Option<Box<ExpressionNode>>,
+                                          exclusive: bool, w: &mut W, goals: &mut V) -> Result<String> {
+    let lo = lo.prolog_print_val(w)?;
+    let hi = hi.prolog_print_val(w)?;
+    let step = match step {
+        Some(step) => step.prolog_print_val(w)?,
+        None => "1".to_string(),
+    };
+    // The index of the last element
+    let last = fresh_name("Last");
+    let indx = fresh_name("Indx");
+    let elem = fresh_name("Elem");
+    if exclusive {
+        write!(goals, "{} is ({} - sign({}) - {}) div ({}), ", last, hi, step, lo, step)?;
+    } else {
+        write!(goals, "{} is ({} - {}) div ({}), ", last, hi, lo, step)?;
+    }
+    write!(goals, "between(0, {}, {}), {} is {} + {} * ({})", last, indx, elem, lo, indx, step)?;
+    Ok(elem)
+}
+
+impl<'p> PrologPrint for InNode<'p> {
+    fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
+        let mut dict_goals = vec![];
+        match &self.collection.contents {
+            ExpressionContents::Range { lo, hi, step, exclusive } => {
+                let mut goals = String::new();
+                let elem = prolog_print_range(lo, hi, step, *exclusive, w, &mut goals)?;
+                write!(w, "{}, ", goals)?;
+                prolog_print_pattern(&self.pattern, w, &mut dict_goals)?;
+                write!(w, " = {}", elem)?;
+            },
+            _ => {
+                let list = self.collection.prolog_print_val(w)?;
+                write!(w, "member(")?;
+                prolog_print_pattern(&self.pattern, w, &mut dict_goals)?;
+                write!(w, ", {})", list)?;
+            },
+        }
+        for goal in dict_goals.iter() {
+            write!(w, ", {}", goal)?;
+        }
+        Ok(())
+    }
+}
+
 impl<'p> PrologPrint for RelateNode<'p> {
     fn prolog_print<W: Write>(&self, w: &mut W) -> Result<()> {
         let mut res: Vec<String> = Vec::with_capacity(self.result.len());
@@ -255,7 +538,7 @@ impl<'p> PrologPrint for BinaryFactNode<'p> {
             BinaryFactOperation::Lt => "<",
             BinaryFactOperation::Leq => "=<",
             BinaryFactOperation::Geq => ">=",
-            BinaryFactOperation::Equ => "=:=",
+            BinaryFactOperation::Equ => "==",
             BinaryFactOperation::Neq => "\\==",
         };
         write!(w, "{} {} {}", leftval, op, rightval)?;
@@ -346,6 +629,36 @@ impl<'p> PrologPrintVal for ExpressionNode<'p> {
                 }
                 write!(w, "|{}]", names[names.len() - 1])?;
             },
+            ExpressionContents::Range { lo, hi, step, exclusive } => {
+                // Only the elements are found inside the findall, so
+                // that the bounds are only computed once
+                let mut goals = String::new();
+                let elem = prolog_print_range(lo, hi, step, *exclusive, w, &mut goals)?;
+                write!(w, "findall({}, ({}), {})", elem, goals, name)?;
+            },
+            ExpressionContents::Tuple { vals } => {
+                let mut names: Vec<String> =
+                    Vec::with_capacity(vals.len());
+                for val in vals {
+                    names.push(val.prolog_print_val(w)?);
+                }
+                write!(w, "{} = tuple({})", name, names.join(", "))?;
+            },
+            ExpressionContents::Dict { entries } => {
+                let mut vals: Vec<String> =
+                    Vec::with_capacity(entries.len());
+                for (_, val) in entries.iter() {
+                    vals.push(val.prolog_print_val(w)?);
+                }
+                write!(w, "{} = _{{", name)?;
+                for i in 0..entries.len() {
+                    if i != 0 {
+                        write!(w, ", ")?;
+                    }
+                    write!(w, "{}: {}", prolog_atom(&entries[i].0), vals[i])?;
+                }
+                write!(w, "}}")?;
+            },
         }
         write!(w, ",\n\t")?;
         Ok(name)
@@ -539,4 +852,420 @@ aroundzero(x) {
 
         Ok(())
     }
+
+    #[test]
+    pub fn test_refute() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+small(x) {
+    refute x > 3
+};
+member((x:_)) ~ x;
+member((_:xs)) {
+    relate member(xs)
+};
+lonely(x, l) {
+    refute {
+        y ~ member(l)
+        y == x + 1
+    }
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__refute.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__refute.pl")
+            .write_stdin("[__test__refute].\nsmall(5).")
+            .assert()
+            .stdout(contains("false."));
+        
+        Command::new("swipl")
+            .arg("__test__refute.pl")
+            .write_stdin("[__test__refute].\nlonely(1, [1, 3]).")
+            .assert()
+            .stdout(contains("true."));
+        
+        Command::new("swipl")
+            .arg("__test__refute.pl")
+            .write_stdin("[__test__refute].\nlonely(1, [1, 2]).")
+            .assert()
+            .stdout(contains("false."));
+
+        std::fs::remove_file("__test__refute.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_dicts() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+person() ~ {name: 'bob, age: 30};
+person() ~ {name: 'sue, age: 41, job: 'chef};
+name({name: n}) ~ n;
+adults() {
+    p ~ person()
+    p.age > 35
+    relate p.name
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__dicts.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__dicts.pl")
+            .write_stdin("[__test__dicts].\nperson(P), name(P, N).")
+            .assert()
+            .stdout(contains("N = bob"));
+        
+        Command::new("swipl")
+            .arg("__test__dicts.pl")
+            .write_stdin("[__test__dicts].\nadults(N).")
+            .assert()
+            .stdout(contains("N = sue."));
+
+        std::fs::remove_file("__test__dicts.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_tuples() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+swap((a, b)) ~ (b, a);
+wrap(x) {
+    t ~ (x, x + 1)
+    relate t
+};
+sums(x, y) {
+    (a, b) ~ (x + 1, y * 2)
+    relate (a, b)
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__tuples.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__tuples.pl")
+            .write_stdin("[__test__tuples].\nwrap(3, T), swap(T, A, B).")
+            .assert()
+            .stdout(contains("A = 4").and(contains("B = 3")));
+        
+        Command::new("swipl")
+            .arg("__test__tuples.pl")
+            .write_stdin("[__test__tuples].\nsums(1, 2, P, Q).")
+            .assert()
+            .stdout(contains("P = 2").and(contains("Q = 4")));
+
+        std::fs::remove_file("__test__tuples.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_ranges() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+evens(n) {
+    x in 0..n by 2
+    relate x
+};
+countdown(n) {
+    relate n..<0 by -1
+};
+small() {
+    x in [1, 2, 3]
+    x > 1
+    relate x
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__ranges.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__ranges.pl")
+            .write_stdin("[__test__ranges].\nfindall(X, evens(5, X), L).")
+            .assert()
+            .stdout(contains("L = [0, 2, 4]."));
+        
+        Command::new("swipl")
+            .arg("__test__ranges.pl")
+            .write_stdin("[__test__ranges].\ncountdown(3, L).")
+            .assert()
+            .stdout(contains("L = [3, 2, 1]."));
+
+        Command::new("swipl")
+            .arg("__test__ranges.pl")
+            .write_stdin("[__test__ranges].\nsmall(X).")
+            .assert()
+            .stdout(contains("X = 2"));
+
+        std::fs::remove_file("__test__ranges.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_univ() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+parts(t) {
+    l ~ univ(t)
+    relate l
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        assert!(source.contains("'=..'"));
+        
+        let mut file = File::create("__test__univ.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__univ.pl")
+            .write_stdin("[__test__univ].\nparts(f(1, 2), L).")
+            .assert()
+            .stdout(contains("L = [f, 1, 2]."));
+
+        std::fs::remove_file("__test__univ.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sorting() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+desc(a, b) {
+    relate compare(b, a)
+};
+sorted(l) {
+    s ~ predsort('desc, l)
+    relate s
+};
+bykey(l) {
+    s ~ sort_by_key(2, l)
+    relate s
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__sorting.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__sorting.pl")
+            .write_stdin("[__test__sorting].\nsorted([1, 3, 2, 3], L).")
+            .assert()
+            .stdout(contains("L = [3, 2, 1]."));
+
+        Command::new("swipl")
+            .arg("__test__sorting.pl")
+            .write_stdin("[__test__sorting].\nbykey([tuple(1, b), tuple(2, a)], L).")
+            .assert()
+            .stdout(contains("L = [tuple(2, a), tuple(1, b)]."));
+
+        std::fs::remove_file("__test__sorting.pl")?;
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_catch() -> Result<(), Box<dyn std::error::Error>> {
+        let program_input =
+r#"
+check(x) {
+    x > 0
+    throw({error: 'bad_value, value: x})
+};
+guarded(x) {
+    catch {
+        check(x)
+        relate 'ok
+    } with {value: v} {
+        relate v
+    }
+};
+rethrown() {
+    catch {
+        throw('oops)
+    } with {value: v} {
+        relate v
+    }
+};
+"#.to_string();
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__catch.pl")?;
+        write!(file, "{}", source)?;
+        
+        Command::new("swipl")
+            .arg("__test__catch.pl")
+            .write_stdin("[__test__catch].\nguarded(5, R).")
+            .assert()
+            .stdout(contains("R = 5."));
+        
+        Command::new("swipl")
+            .arg("__test__catch.pl")
+            .write_stdin("[__test__catch].\ncatch(rethrown(R), B, true).")
+            .assert()
+            .stdout(contains("B = oops."));
+
+        std::fs::remove_file("__test__catch.pl")?;
+
+        Ok(())
+    }
+
+    // Random expressions which evaluate to numbers
+    fn random_num_expr(rng: &mut impl rand::Rng, depth: u32) -> String {
+        if depth == 0 {
+            return rng.gen_range(0, 10).to_string();
+        }
+        match rng.gen_range(0, 4) {
+            0 => format!("{} + {}", random_num_expr(rng, depth - 1), random_num_expr(rng, depth - 1)),
+            1 => format!("({} - {})", random_num_expr(rng, depth - 1), random_num_expr(rng, depth - 1)),
+            2 => format!("({} * {})", random_num_expr(rng, depth - 1), random_num_expr(rng, depth - 1)),
+            _ => rng.gen_range(0, 10).to_string(),
+        }
+    }
+
+    // Random expressions which evaluate to lists, including
+    // cons lists with any kind of list expression as the tail
+    fn random_list_expr(rng: &mut impl rand::Rng, depth: u32) -> String {
+        if depth == 0 {
+            return "[]".to_string();
+        }
+        match rng.gen_range(0, 4) {
+            0 => {
+                let len = rng.gen_range(0, 3);
+                let vals: Vec<String> = (0..len)
+                    .map(|_| random_num_expr(rng, depth - 1))
+                    .collect();
+                format!("[{}]", vals.join(", "))
+            },
+            1 => {
+                let len = rng.gen_range(1, 3);
+                let vals: Vec<String> = (0..len)
+                    .map(|_| random_num_expr(rng, depth - 1))
+                    .collect();
+                format!("({} : {})", vals.join(" : "), random_list_expr(rng, depth - 1))
+            },
+            2 => format!("pair({})", random_num_expr(rng, depth - 1)),
+            _ => format!("(({} : {}))", random_num_expr(rng, depth - 1), random_list_expr(rng, depth - 1)),
+        }
+    }
+
+    #[test]
+    pub fn test_cons_agrees() -> Result<(), Box<dyn std::error::Error>> {
+        let mut rng = rand::thread_rng();
+        let mut program_input = "pair(x) ~ [x, x];\n".to_string();
+        let num_exprs = 40;
+        for i in 0..num_exprs {
+            program_input.push_str(&format!("t{}() {{\n    relate {}\n}};\n", i, random_list_expr(&mut rng, 4)));
+        }
+        let pairs = BevelParser::parse(Rule::program, &program_input)?;
+
+        let prog = parse_program(pairs, program_input.as_ref());
+
+        // What the solver computes for each expression
+        let mut prog_rules = solver::parse::parse_program(&prog);
+        let mut expects = vec![];
+        for i in 0..num_exprs {
+            let input = format!("x ~ t{}()", i);
+            let raw_parse = BevelParser::parse(Rule::assignment, &input)?.next().unwrap();
+            let anode = ast::AssignmentNode::parse(raw_parse, &input);
+            let query = solver::Query {
+                goals: solver::parse::parse_assignment(&anode, REPL_FRAME_ID),
+            };
+            let mut state = solver::solve::new_solver_state(query);
+            let solution = solver::solve::solve(&mut prog_rules, solver::solve::SolverState {
+                master: &mut state.master,
+                curr_query: &mut state.curr_query,
+                new_query: &mut state.new_query,
+                fact_indx: &mut state.fact_indx,
+                clauses: &mut state.clauses,
+                choice_points: &mut state.choice_points,
+                catches: &mut state.catches,
+            }).expect("uncaught exception").expect("expression failed to evaluate");
+            let x = solution.iter()
+                .find(|(k, _)| k.name == "x")
+                .map(|(_, v)| v.to_string())
+                .unwrap();
+            expects.push(format!("{}:{}\n", i, x.replace(" ", "")));
+        }
+
+        // What Prolog computes for each expression
+        let mut source = String::new();
+
+        prog.prolog_print(&mut source)?;
+        
+        let mut file = File::create("__test__cons.pl")?;
+        write!(file, "{}", source)?;
+
+        let goals: Vec<String> = (0..num_exprs)
+            .map(|i| format!("t{}(X{}), format('{}:~w~n', [X{}])", i, i, i, i))
+            .collect();
+        let mut assert = Command::new("swipl")
+            .arg("__test__cons.pl")
+            .write_stdin(format!("[__test__cons].\n{}.", goals.join(", ")))
+            .assert();
+        for expect in expects {
+            assert = assert.stdout(contains(expect));
+        }
+
+        std::fs::remove_file("__test__cons.pl")?;
+
+        Ok(())
+    }
 }
@@ -2,8 +2,53 @@
 use crate::solver::*;
 
 use crate::solver::unify::compute_most_gen_unifier;
+use crate::REPL_FRAME_ID;
 
-pub type Builtin = fn(&CompoundTerm) -> Option<Unifier>;
+use rand::prelude::RngCore;
+use rand::thread_rng;
+use std::cmp::Ordering;
+
+// A solution to a builtin, and the index to start searching
+// from for the next solution, if there might be more
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub unifier: Unifier,
+    pub next: Option<usize>,
+}
+
+impl Answer {
+    // A solution with nothing after it
+    pub fn last(unifier: Unifier) -> Answer {
+        Answer {
+            unifier,
+            next: None,
+        }
+    }
+}
+
+// Builtins give an error term when they can't be run,
+// which is thrown like any other exception
+pub type BuiltinResult = Result<Option<Answer>, Term>;
+
+// Builtins are given the index to start searching for a solution
+// from, which is 0 when they're first called. When an answer has
+// a next index, the solver leaves a choice point which calls the
+// builtin again from there
+pub type Builtin = fn(&CompoundTerm, usize) -> BuiltinResult;
+
+// Builtins which change the rules database
+pub type DatabaseBuiltin = fn(&CompoundTerm, &mut Rules, usize) -> BuiltinResult;
+
+// Solves a goal in a nested solver, giving its first solution
+pub type Call<'a> = dyn FnMut(Term) -> Result<Option<Unifier>, Term> + 'a;
+
+// Builtins which run goals, like the relations they're given
+pub type MetaBuiltin = fn(&CompoundTerm, &mut Call) -> BuiltinResult;
+
+// For builtins with at most one solution
+pub fn once(unifier: Option<Unifier>) -> BuiltinResult {
+    Ok(unifier.map(Answer::last))
+}
 
 pub fn builtins() -> HashMap<String, Builtin> {
     let mut res = HashMap::new();
@@ -35,18 +80,179 @@ pub fn builtins() -> HashMap<String, Builtin> {
                builtin_writeln as Builtin);
     res.insert("atom".to_string(),
                builtin_atom as Builtin);
+    res.insert("var".to_string(),
+               builtin_var as Builtin);
+    res.insert("nonvar".to_string(),
+               builtin_nonvar as Builtin);
+    res.insert("number".to_string(),
+               builtin_number as Builtin);
+    res.insert("is_list".to_string(),
+               builtin_is_list as Builtin);
+    res.insert("ground".to_string(),
+               builtin_ground as Builtin);
+    res.insert("functor".to_string(),
+               builtin_functor as Builtin);
+    res.insert("arg".to_string(),
+               builtin_arg as Builtin);
+    res.insert("univ".to_string(),
+               builtin_univ as Builtin);
+    res.insert("copy_term".to_string(),
+               builtin_copy_term as Builtin);
+    res.insert("compare".to_string(),
+               builtin_compare as Builtin);
+    res.insert("term_lt".to_string(),
+               builtin_term_lt as Builtin);
+    res.insert("term_gt".to_string(),
+               builtin_term_gt as Builtin);
+    res.insert("term_leq".to_string(),
+               builtin_term_leq as Builtin);
+    res.insert("term_geq".to_string(),
+               builtin_term_geq as Builtin);
+    res.insert("sort".to_string(),
+               builtin_sort as Builtin);
+    res.insert("msort".to_string(),
+               builtin_msort as Builtin);
+    res.insert("sort_by_key".to_string(),
+               builtin_sort_by_key as Builtin);
+    res.insert("length".to_string(),
+               builtin_length as Builtin);
+    res.insert("append".to_string(),
+               builtin_append as Builtin);
+    res.insert("reverse".to_string(),
+               builtin_reverse as Builtin);
+    res.insert("nth".to_string(),
+               builtin_nth as Builtin);
+    res.insert("last".to_string(),
+               builtin_last as Builtin);
+    res.insert("member".to_string(),
+               builtin_list_member as Builtin);
+    res.insert("select".to_string(),
+               builtin_select as Builtin);
+    res.insert("sum_list".to_string(),
+               builtin_sum_list as Builtin);
+    res.insert("max_list".to_string(),
+               builtin_max_list as Builtin);
+    res.insert("flatten".to_string(),
+               builtin_flatten as Builtin);
+    res.insert("zip".to_string(),
+               builtin_zip as Builtin);
+    res.insert("open".to_string(),
+               streams::builtin_open as Builtin);
+    res.insert("close".to_string(),
+               streams::builtin_close as Builtin);
+    res.insert("read_line".to_string(),
+               streams::builtin_read_line as Builtin);
+    res.insert("read_term".to_string(),
+               streams::builtin_read_term as Builtin);
+    res.insert("write".to_string(),
+               streams::builtin_write as Builtin);
+    res.insert("print".to_string(),
+               streams::builtin_print as Builtin);
+    res.insert("nl".to_string(),
+               streams::builtin_nl as Builtin);
+    res.insert("read_file_to_lines".to_string(),
+               streams::builtin_read_file_to_lines as Builtin);
+    res.insert("exists_file".to_string(),
+               streams::builtin_exists_file as Builtin);
+    res.insert("format".to_string(),
+               format::builtin_format as Builtin);
+    res.insert("format_to_string".to_string(),
+               format::builtin_format_to_string as Builtin);
+    res.insert("get_dict".to_string(),
+               builtin_get_dict as Builtin);
+    res.insert("put_dict".to_string(),
+               builtin_put_dict as Builtin);
+    res.insert("del_dict".to_string(),
+               builtin_del_dict as Builtin);
+    res.insert("dict_keys".to_string(),
+               builtin_dict_keys as Builtin);
+    res.insert("succ".to_string(),
+               builtin_succ as Builtin);
+    res.insert("$range_list".to_string(),
+               builtin_range_list as Builtin);
+    res.insert("between".to_string(),
+               builtin_between as Builtin);
+    res.insert("$range".to_string(),
+               builtin_range as Builtin);
+    res.insert("$member".to_string(),
+               builtin_member as Builtin);
+    res
+}
+
+pub fn database_builtins() -> HashMap<String, DatabaseBuiltin> {
+    let mut res = HashMap::new();
+    res.insert("assert".to_string(),
+               builtin_assert as DatabaseBuiltin);
+    res.insert("asserta".to_string(),
+               builtin_asserta as DatabaseBuiltin);
+    res.insert("retract".to_string(),
+               builtin_retract as DatabaseBuiltin);
+    res
+}
+
+pub fn meta_builtins() -> HashMap<String, MetaBuiltin> {
+    let mut res = HashMap::new();
+    res.insert("predsort".to_string(),
+               builtin_predsort as MetaBuiltin);
+    res.insert("exclude".to_string(),
+               builtin_exclude as MetaBuiltin);
     res
 }
 
-pub fn builtin_eq(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
+// The numbers of arguments which each builtin can be called with,
+// for the checks. writeln takes any number, so it isn't listed
+pub fn builtin_arities() -> HashMap<String, Vec<usize>> {
+    let arities: &[(&str, &[usize])] = &[
+        ("=", &[2]), ("+", &[3]), ("-", &[3]), ("*", &[3]), ("/", &[3]), ("%", &[3]),
+        (">", &[2]), ("<", &[2]), ("<=", &[2]), (">=", &[2]), ("==", &[2]), ("!=", &[2]),
+        ("atom", &[1]), ("var", &[1]), ("nonvar", &[1]), ("number", &[1]),
+        ("is_list", &[1]), ("ground", &[1]),
+        ("functor", &[3]), ("arg", &[3]), ("univ", &[2]), ("copy_term", &[2]),
+        ("compare", &[3]), ("term_lt", &[2]), ("term_gt", &[2]), ("term_leq", &[2]), ("term_geq", &[2]),
+        ("sort", &[2]), ("msort", &[2]), ("sort_by_key", &[3]), ("predsort", &[3]),
+        ("length", &[2]), ("append", &[3]), ("reverse", &[2]), ("nth", &[3]), ("last", &[2]),
+        ("member", &[2]), ("select", &[3]), ("sum_list", &[2]), ("max_list", &[2]),
+        ("flatten", &[2]), ("zip", &[3]), ("exclude", &[3]),
+        ("open", &[3]), ("close", &[1]), ("read_line", &[1, 2]), ("read_term", &[1, 2]),
+        ("write", &[1, 2]), ("print", &[1, 2]), ("nl", &[0, 1]),
+        ("read_file_to_lines", &[2]), ("exists_file", &[1]),
+        ("format", &[2, 3]), ("format_to_string", &[3]),
+        ("get_dict", &[3]), ("put_dict", &[4]), ("del_dict", &[4]), ("dict_keys", &[2]),
+        ("succ", &[2]), ("between", &[3]),
+        ("$range_list", &[5]), ("$range", &[5]), ("$member", &[2]),
+        ("assert", &[1]), ("asserta", &[1]), ("retract", &[1]),
+    ];
+    arities.iter().map(|(name, arities)| (name.to_string(), arities.to_vec())).collect()
+}
+
+// Errors from builtins are dicts like
+// {error: 'instantiation_error, relation: '>}
+pub fn error_term(kind: &str, relation: &str) -> Term {
+    Term::Dict(DictTerm::new(vec![
+        ("error".to_string(), Term::Atom(format!("'{}", kind))),
+        ("relation".to_string(), Term::Atom(format!("'{}", relation))),
+    ], false))
+}
+
+// Builtins can be called with any number of arguments through call,
+// so the wrong number throws a type error rather than panicking
+pub fn check_args(cterm: &CompoundTerm, arity: usize) -> Result<(), Term> {
+    if cterm.args.len() == arity {
+        Ok(())
+    } else {
+        Err(error_term("type_error", &cterm.name))
+    }
+}
+
+pub fn builtin_eq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
-    compute_most_gen_unifier(vec![(a, b)])
+    once(compute_most_gen_unifier(vec![(a, b)]))
 }
 
-pub fn builtin_add(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 3);
+pub fn builtin_add(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     let c = cterm.args[2].clone();
@@ -56,39 +262,39 @@ pub fn builtin_add(cterm: &CompoundTerm) -> Option<Unifier> {
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b - a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Unknown(s),
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b - a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Unknown(s)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a + b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Number(c)) => {
             if a + b == c {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "+"))
         }
     }
 }
 
-pub fn builtin_sub(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 3);
+pub fn builtin_sub(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     let c = cterm.args[2].clone();
@@ -98,39 +304,39 @@ pub fn builtin_sub(cterm: &CompoundTerm) -> Option<Unifier> {
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b + a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Unknown(s),
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a - b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Unknown(s)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a - b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Number(c)) => {
             if a - b == c {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "-"))
         }
     }
 }
 
-pub fn builtin_mul(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 3);
+pub fn builtin_mul(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     let c = cterm.args[2].clone();
@@ -140,39 +346,42 @@ pub fn builtin_mul(cterm: &CompoundTerm) -> Option<Unifier> {
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b / a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Unknown(s),
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b / a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Unknown(s)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a * b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Number(c)) => {
             if a * b == c {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "*"))
         }
     }
 }
 
-pub fn builtin_div(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 3);
+pub fn builtin_div(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    if cterm.args[1] == Term::Number(0.into()) {
+        return Err(error_term("evaluation_error", "/"));
+    }
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     let c = cterm.args[2].clone();
@@ -182,39 +391,42 @@ pub fn builtin_div(cterm: &CompoundTerm) -> Option<Unifier> {
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(b * a));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Unknown(s),
         Term::Number(b)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a / b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Unknown(s)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a / b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Number(c)) => {
             if a / b == c {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "/"))
         }
     }
 }
 
-pub fn builtin_mod(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 3);
+pub fn builtin_mod(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    if cterm.args[1] == Term::Number(0.into()) {
+        return Err(error_term("evaluation_error", "%"));
+    }
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     let c = cterm.args[2].clone();
@@ -224,162 +436,367 @@ pub fn builtin_mod(cterm: &CompoundTerm) -> Option<Unifier> {
         Term::Unknown(s)) => {
             let mut unif = Unifier::new();
             unif.insert(s.clone(), Term::Number(a % b));
-            Some(unif)
+            Ok(Some(Answer::last(unif)))
         },
         (Term::Number(a),
         Term::Number(b),
         Term::Number(c)) => {
             if a % b == c {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "%"))
         }
     }
 }
 
 
-pub fn builtin_gt(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
+pub fn builtin_gt(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     match (a, b) {
         (Term::Number(a),
         Term::Number(b)) => {
             if a > b {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", ">"))
         }
     }
 }
 
-pub fn builtin_lt(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
+pub fn builtin_lt(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     match (a, b) {
         (Term::Number(a),
         Term::Number(b)) => {
             if a < b {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "<"))
         }
     }
 }
 
-pub fn builtin_leq(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
+pub fn builtin_leq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     match (a, b) {
         (Term::Number(a),
         Term::Number(b)) => {
             if a <= b {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", "<="))
         }
     }
 }
 
-pub fn builtin_geq(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
+pub fn builtin_geq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     let a = cterm.args[0].clone();
     let b = cterm.args[1].clone();
     match (a, b) {
         (Term::Number(a),
         Term::Number(b)) => {
             if a >= b {
-                Some(Unifier::new())
+                Ok(Some(Answer::last(Unifier::new())))
             } else {
-                None
+                Ok(None)
             }
         },
         _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+            Err(error_term("instantiation_error", ">="))
         }
     }
 }
 
-pub fn builtin_equ(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
-    let a = cterm.args[0].clone();
-    let b = cterm.args[1].clone();
-    if equal_as_terms(&a, &b) {
-        Some(Unifier::new())
-    } else {
-        None
+// Structural equality, so unknowns are only equal to themselves
+pub fn builtin_equ(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) == Ordering::Equal)
+}
+
+pub fn builtin_neq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) != Ordering::Equal)
+}
+
+// Where each kind of term comes in the standard order: unknowns,
+// numbers, [], atoms, compound terms and non-empty lists, then dicts
+fn order_rank(term: &Term) -> u8 {
+    match term {
+        Term::Unknown(_) => 0,
+        Term::Number(_) => 1,
+        Term::List(lterm) if lterm.front.is_empty() => 2,
+        Term::Atom(_) => 3,
+        Term::Compound(_) | Term::List(_) => 4,
+        Term::Dict(_) => 5,
+        Term::Refute(_) | Term::Catch(_) => 6,
+    }
+}
+
+// The elements of a list after the first n, which
+// are the list's tail if there aren't any more
+fn list_from(lterm: &ListTerm, n: usize) -> Term {
+    match (n < lterm.front.len(), &lterm.tail) {
+        (false, ListTail::Unknown(tail)) => Term::Unknown(tail.clone()),
+        _ => Term::List(ListTerm {
+            front: lterm.front[n.min(lterm.front.len())..].to_vec(),
+            tail: lterm.tail.clone(),
+        }),
     }
 }
 
-fn equal_as_terms(a: &Term, b: &Term) -> bool {
+// The standard order of terms, which puts every pair of terms
+// in some order. Compound terms are ordered by their arity, then
+// their name, then their arguments from left to right
+pub fn compare_terms(a: &Term, b: &Term) -> Ordering {
     match (a, b) {
-        (Term::Number(a),
-        Term::Number(b)) => {
-            a == b
+        // A list with only an unknown tail is just that unknown
+        (Term::List(lterm), _) if lterm.front.is_empty() && lterm.tail != ListTail::End => {
+            compare_terms(&list_from(lterm, 0), b)
         },
-        (Term::Atom(a),
-        Term::Atom(b)) => {
-            a == b
+        (_, Term::List(lterm)) if lterm.front.is_empty() && lterm.tail != ListTail::End => {
+            compare_terms(a, &list_from(lterm, 0))
         },
-        (Term::List(ListTerm {
-            front: f1,
-            tail: ListTail::End
-        }),
-        Term::List(ListTerm {
-            front: f2,
-            tail: ListTail::End
-        })) => {
-            for i in 0..f1.len() {
-                if !equal_as_terms(&f1[i], &f2[i]) {
-                    return false;
-                }
-            }
-            return true;
+        (Term::Unknown(u1), Term::Unknown(u2)) => {
+            (u1.frame_id, &u1.name).cmp(&(u2.frame_id, &u2.name))
         },
-        _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+        (Term::Number(n1), Term::Number(n2)) => n1.cmp(n2),
+        (Term::Atom(a1), Term::Atom(a2)) => a1.cmp(a2),
+        (Term::List(l1), Term::List(l2)) if !l1.front.is_empty() && !l2.front.is_empty() => {
+            let n = l1.front.len().min(l2.front.len());
+            compare_all(&l1.front[..n], &l2.front[..n])
+                .then_with(|| compare_terms(&list_from(l1, n), &list_from(l2, n)))
+        },
+        (Term::Compound(c1), Term::Compound(c2)) => {
+            c1.args.len().cmp(&c2.args.len())
+                .then_with(|| c1.name.cmp(&c2.name))
+                .then_with(|| compare_all(&c1.args, &c2.args))
+        },
+        // Non-empty lists are '[|](head, tail)
+        (Term::List(lterm), Term::Compound(cterm)) if !lterm.front.is_empty() => {
+            (2, &CONS[1..]).cmp(&(cterm.args.len(), cterm.name.as_str()))
+        },
+        (Term::Compound(cterm), Term::List(lterm)) if !lterm.front.is_empty() => {
+            (cterm.args.len(), cterm.name.as_str()).cmp(&(2, &CONS[1..]))
+        },
+        (Term::Dict(d1), Term::Dict(d2)) => {
+            d1.entries.len().cmp(&d2.entries.len())
+                .then_with(|| {
+                    for ((k1, v1), (k2, v2)) in d1.entries.iter().zip(d2.entries.iter()) {
+                        let ord = k1.cmp(k2).then_with(|| compare_terms(v1, v2));
+                        if ord != Ordering::Equal {
+                            return ord;
+                        }
+                    }
+                    Ordering::Equal
+                })
+        },
+        // Goals, which are never values
+        (Term::Refute(_), Term::Refute(_)) | (Term::Catch(_), Term::Catch(_))
+        | (Term::Refute(_), Term::Catch(_)) | (Term::Catch(_), Term::Refute(_)) => {
+            format!("{:?}", a).cmp(&format!("{:?}", b))
+        },
+        _ => order_rank(a).cmp(&order_rank(b)),
+    }
+}
+
+fn compare_all(a: &[Term], b: &[Term]) -> Ordering {
+    for (v1, v2) in a.iter().zip(b.iter()) {
+        let ord = compare_terms(v1, v2);
+        if ord != Ordering::Equal {
+            return ord;
         }
     }
+    a.len().cmp(&b.len())
 }
 
-pub fn builtin_neq(cterm: &CompoundTerm) -> Option<Unifier> {
-    assert!(cterm.args.len() == 2);
-    let a = cterm.args[0].clone();
-    let b = cterm.args[1].clone();
-    match (a, b) {
-        (Term::Number(a),
-        Term::Number(b)) => {
-            if a != b {
-                Some(Unifier::new())
-            } else {
-                None
-            }
-        },
-        _ => {
-            panic!("arguments to arithmetic not defined enough! \nthis will be a non-fatal error in the future")
+fn order_atom(ord: Ordering) -> Term {
+    Term::Atom(match ord {
+        Ordering::Less => "'<",
+        Ordering::Equal => "'=",
+        Ordering::Greater => "'>",
+    }.to_string())
+}
+
+// compare(a, b) -> '<, '= or '>, like compare/3 in Prolog
+pub fn builtin_compare(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let ord = compare_terms(&cterm.args[0], &cterm.args[1]);
+    once(compute_most_gen_unifier(vec![(cterm.args[2].clone(), order_atom(ord))]))
+}
+
+pub fn builtin_term_lt(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) == Ordering::Less)
+}
+
+pub fn builtin_term_gt(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) == Ordering::Greater)
+}
+
+pub fn builtin_term_leq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) != Ordering::Greater)
+}
+
+pub fn builtin_term_geq(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    succeed_if(compare_terms(&cterm.args[0], &cterm.args[1]) != Ordering::Less)
+}
+
+// The elements of a list which must be complete to be sorted
+fn sort_arg(term: &Term, relation: &str) -> Result<Vec<Term>, Term> {
+    match term {
+        Term::List(ListTerm { front, tail: ListTail::End }) => Ok(front.clone()),
+        Term::Unknown(_) | Term::List(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+fn unify_list(term: &Term, items: Vec<Term>) -> BuiltinResult {
+    once(compute_most_gen_unifier(vec![(
+        term.clone(),
+        Term::List(ListTerm {
+            front: items,
+            tail: ListTail::End,
+        }),
+    )]))
+}
+
+// msort(l) -> l in the standard order, keeping duplicates
+pub fn builtin_msort(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let mut items = sort_arg(&cterm.args[0], "msort")?;
+    items.sort_by(compare_terms);
+    unify_list(&cterm.args[1], items)
+}
+
+// sort(l) -> l in the standard order, without duplicates
+pub fn builtin_sort(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let mut items = sort_arg(&cterm.args[0], "sort")?;
+    items.sort_by(compare_terms);
+    items.dedup_by(|a, b| compare_terms(a, b) == Ordering::Equal);
+    unify_list(&cterm.args[1], items)
+}
+
+// sort_by_key(n, l) -> l ordered by the nth argument of each
+// element. Elements with equal keys stay in the same order
+pub fn builtin_sort_by_key(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let n = integer_arg(&cterm.args[0], "sort_by_key")?;
+    let items = sort_arg(&cterm.args[1], "sort_by_key")?;
+    let mut keyed = Vec::with_capacity(items.len());
+    for item in items.into_iter() {
+        let (_, mut args) = decompose(&item, "sort_by_key")?;
+        if n < 1 || n as usize > args.len() {
+            return Err(error_term("type_error", "sort_by_key"));
+        }
+        keyed.push((args.swap_remove(n as usize - 1), item));
+    }
+    keyed.sort_by(|(k1, _), (k2, _)| compare_terms(k1, k2));
+    unify_list(&cterm.args[2], keyed.into_iter().map(|(_, item)| item).collect())
+}
+
+// The result of calling a user's relation to order two terms
+type OrderResult = Result<Option<Ordering>, Term>;
+
+// The goal calling the relation named by an atom, like 'small
+pub fn relation_goal(pred: &Term, args: Vec<Term>, relation: &str) -> Result<Term, Term> {
+    match pred {
+        Term::Atom(name) => Ok(Term::Compound(CompoundTerm {
+            name: name[1..].to_string(),
+            args,
+        })),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+// predsort(p, l) -> l ordered by the relation p, which gives
+// '<, '= or '> for two elements. Only the first of the elements
+// which p says are equal is kept. Fails if p fails
+pub fn builtin_predsort(cterm: &CompoundTerm, call: &mut Call) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    relation_goal(&cterm.args[0], vec![], "predsort")?;
+    let items = sort_arg(&cterm.args[1], "predsort")?;
+    let order = UnknownContents {
+        name: "$order".to_string(),
+        frame_id: REPL_FRAME_ID,
+    };
+    let mut compare = |a: &Term, b: &Term| -> OrderResult {
+        let goal = relation_goal(&cterm.args[0], vec![a.clone(), b.clone(), Term::Unknown(order.clone())], "predsort")?;
+        Ok(call(goal)?.and_then(|unifier| unifier.get(&order).and_then(atom_order)))
+    };
+    match predsort(items, &mut compare)? {
+        Some(items) => unify_list(&cterm.args[2], items),
+        None => Ok(None),
+    }
+}
+
+// A merge sort which can stop partway through
+fn predsort(mut items: Vec<Term>, order: &mut dyn FnMut(&Term, &Term) -> OrderResult)
+            -> Result<Option<Vec<Term>>, Term> {
+    if items.len() <= 1 {
+        return Ok(Some(items));
+    }
+    let right = items.split_off(items.len() / 2);
+    let (left, right) = match (predsort(items, order)?, predsort(right, order)?) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Ok(None),
+    };
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        match order(a, b)? {
+            Some(Ordering::Less) => merged.push(left.next().unwrap()),
+            Some(Ordering::Greater) => merged.push(right.next().unwrap()),
+            Some(Ordering::Equal) => {
+                right.next();
+            },
+            None => return Ok(None),
         }
     }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(Some(merged))
+}
+
+// The ordering given by an atom from compare
+fn atom_order(term: &Term) -> Option<Ordering> {
+    match term {
+        Term::Atom(s) if s == "'<" => Some(Ordering::Less),
+        Term::Atom(s) if s == "'=" => Some(Ordering::Equal),
+        Term::Atom(s) if s == "'>" => Some(Ordering::Greater),
+        _ => None,
+    }
 }
 
-pub fn builtin_writeln(cterm: &CompoundTerm) -> Option<Unifier> {
+pub fn builtin_writeln(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
     let mut first = true;
     for term in cterm.args.iter() {
         if first {
@@ -390,19 +807,902 @@ pub fn builtin_writeln(cterm: &CompoundTerm) -> Option<Unifier> {
         }
     }
     println!();
-    Some(Unifier::new())
+    Ok(Some(Answer::last(Unifier::new())))
+}
+
+pub fn builtin_atom(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    match &cterm.args[0] {
+        Term::Atom(_) => Ok(Some(Answer::last(Unifier::new()))),
+        _ => Ok(None),
+    }
+}
+
+pub fn succeed_if(test: bool) -> BuiltinResult {
+    if test {
+        Ok(Some(Answer::last(Unifier::new())))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn builtin_var(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    succeed_if(matches!(cterm.args[0], Term::Unknown(_)))
 }
 
-pub fn builtin_atom(cterm: &CompoundTerm) -> Option<Unifier> {
-    if cterm.args.len() > 1 {
-        panic!("Too many arguments passed to atom/1! \nthis will be a non-fatal error in the future");
+pub fn builtin_nonvar(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    succeed_if(!matches!(cterm.args[0], Term::Unknown(_)))
+}
+
+pub fn builtin_number(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    succeed_if(matches!(cterm.args[0], Term::Number(_)))
+}
+
+// Lists with an unknown tail aren't (yet) lists
+pub fn builtin_is_list(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    succeed_if(matches!(cterm.args[0], Term::List(ListTerm { tail: ListTail::End, .. })))
+}
+
+fn is_ground(term: &Term) -> bool {
+    match term {
+        Term::Unknown(_) => false,
+        Term::Atom(_) | Term::Number(_) => true,
+        Term::List(lterm) => {
+            lterm.tail == ListTail::End && lterm.front.iter().all(is_ground)
+        },
+        Term::Dict(dterm) => dterm.entries.iter().all(|(_, val)| is_ground(val)),
+        Term::Compound(cterm) => cterm.args.iter().all(is_ground),
+        // Goals, which are never values
+        Term::Refute(_) | Term::Catch(_) => true,
+    }
+}
+
+pub fn builtin_ground(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    succeed_if(is_ground(&cterm.args[0]))
+}
+
+// The name of a non-empty list, which is '[|](head, tail)
+// like it is in Prolog
+const CONS: &str = "'[|]";
+
+// Everything after the first element of a non-empty list
+fn list_rest(lterm: &ListTerm) -> Term {
+    match (lterm.front.len(), &lterm.tail) {
+        (1, ListTail::Unknown(tail)) => Term::Unknown(tail.clone()),
+        _ => Term::List(ListTerm {
+            front: lterm.front[1..].to_vec(),
+            tail: lterm.tail.clone(),
+        }),
+    }
+}
+
+// The name and arguments of a term. Atoms, numbers
+// and [] are their own names, with no arguments
+fn decompose(term: &Term, relation: &str) -> Result<(Term, Vec<Term>), Term> {
+    match term {
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        Term::Compound(cterm) => {
+            Ok((Term::Atom(format!("'{}", cterm.name)), cterm.args.clone()))
+        },
+        Term::List(lterm) if !lterm.front.is_empty() => {
+            Ok((Term::Atom(CONS.to_string()), vec![lterm.front[0].clone(), list_rest(lterm)]))
+        },
+        Term::Dict(_) | Term::Refute(_) | Term::Catch(_) => Err(error_term("type_error", relation)),
+        atomic => Ok((atomic.clone(), vec![])),
     }
+}
+
+// Build a term from its name and arguments
+fn compose(name: &Term, mut args: Vec<Term>, relation: &str) -> Result<Term, Term> {
+    match name {
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        Term::Atom(_) | Term::Number(_) | Term::List(_) if args.is_empty() => Ok(name.clone()),
+        Term::Atom(s) if s == CONS && args.len() == 2 => {
+            let tail = args.pop().unwrap();
+            let head = args.pop().unwrap();
+            match tail {
+                Term::List(mut lterm) => {
+                    lterm.front.insert(0, head);
+                    Ok(Term::List(lterm))
+                },
+                Term::Unknown(tail) => Ok(Term::List(ListTerm {
+                    front: vec![head],
+                    tail: ListTail::Unknown(tail),
+                })),
+                _ => Err(error_term("type_error", relation)),
+            }
+        },
+        Term::Atom(s) => Ok(Term::Compound(CompoundTerm {
+            name: s[1..].to_string(),
+            args,
+        })),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+fn fresh_var(frame_id: u32, indx: usize) -> UnknownContents {
+    UnknownContents {
+        name: format!("_{}", indx),
+        frame_id,
+    }
+}
+
+// functor(term) -> (name, arity), or build a term
+// with unknown arguments from its name and arity
+pub fn builtin_functor(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    match &cterm.args[0] {
+        Term::Unknown(_) => {
+            let arity = match &cterm.args[2] {
+                Term::Number(n) if n.is_integer() && n.to_integer() >= 0 => n.to_integer() as usize,
+                Term::Unknown(_) => return Err(error_term("instantiation_error", "functor")),
+                _ => return Err(error_term("type_error", "functor")),
+            };
+            let frame_id = thread_rng().next_u32();
+            let args = (0..arity).map(|indx| Term::Unknown(fresh_var(frame_id, indx))).collect();
+            let term = compose(&cterm.args[1], args, "functor")?;
+            once(compute_most_gen_unifier(vec![(cterm.args[0].clone(), term)]))
+        },
+        term => {
+            let (name, args) = decompose(term, "functor")?;
+            once(compute_most_gen_unifier(vec![
+                (cterm.args[1].clone(), name),
+                (cterm.args[2].clone(), Term::Number((args.len() as i32).into())),
+            ]))
+        },
+    }
+}
+
+// arg(n, term) -> the nth argument of term, counting from 1.
+// Gives every argument in turn if n is unknown
+pub fn builtin_arg(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let (_, args) = decompose(&cterm.args[1], "arg")?;
+    match &cterm.args[0] {
+        Term::Unknown(_) => {
+            for indx in start..args.len() {
+                let unifier = compute_most_gen_unifier(vec![
+                    (cterm.args[0].clone(), Term::Number((indx as i32 + 1).into())),
+                    (cterm.args[2].clone(), args[indx].clone()),
+                ]);
+                if let Some(unifier) = unifier {
+                    return Ok(Some(Answer {
+                        unifier,
+                        next: if indx + 1 < args.len() { Some(indx + 1) } else { None },
+                    }));
+                }
+            }
+            Ok(None)
+        },
+        Term::Number(n) if n.is_integer() => {
+            let n = n.to_integer();
+            if n < 1 || n as usize > args.len() {
+                return Ok(None);
+            }
+            once(compute_most_gen_unifier(vec![(cterm.args[2].clone(), args[n as usize - 1].clone())]))
+        },
+        _ => Err(error_term("type_error", "arg")),
+    }
+}
+
+// univ(term) -> [name, args...], like =.. in Prolog
+pub fn builtin_univ(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
     match &cterm.args[0] {
-        Term::Atom(_) => Some(Unifier::new()),
+        Term::Unknown(_) => {
+            let parts = match &cterm.args[1] {
+                Term::List(ListTerm { front, tail: ListTail::End }) if !front.is_empty() => front,
+                Term::Unknown(_) | Term::List(ListTerm { tail: ListTail::Unknown(_), .. }) => {
+                    return Err(error_term("instantiation_error", "univ"));
+                },
+                _ => return Err(error_term("type_error", "univ")),
+            };
+            let term = compose(&parts[0], parts[1..].to_vec(), "univ")?;
+            once(compute_most_gen_unifier(vec![(cterm.args[0].clone(), term)]))
+        },
+        term => {
+            let (name, mut args) = decompose(term, "univ")?;
+            args.insert(0, name);
+            once(compute_most_gen_unifier(vec![(
+                cterm.args[1].clone(),
+                Term::List(ListTerm {
+                    front: args,
+                    tail: ListTail::End,
+                }),
+            )]))
+        },
+    }
+}
+
+// Replace each unknown with a new one, using the same
+// new unknown everywhere the old one appeared
+fn rename_vars(term: &Term, renamed: &mut HashMap<UnknownContents, UnknownContents>, frame_id: u32) -> Term {
+    let mut rename = |unknown: &UnknownContents| {
+        let indx = renamed.len();
+        renamed.entry(unknown.clone())
+            .or_insert_with(|| fresh_var(frame_id, indx))
+            .clone()
+    };
+    match term {
+        Term::Unknown(unknown) => Term::Unknown(rename(unknown)),
+        Term::List(lterm) => {
+            let tail = match &lterm.tail {
+                ListTail::End => ListTail::End,
+                ListTail::Unknown(unknown) => ListTail::Unknown(rename(unknown)),
+            };
+            Term::List(ListTerm {
+                front: lterm.front.iter().map(|item| rename_vars(item, renamed, frame_id)).collect(),
+                tail,
+            })
+        },
+        Term::Dict(dterm) => Term::Dict(DictTerm {
+            entries: dterm.entries.iter()
+                .map(|(key, val)| (key.clone(), rename_vars(val, renamed, frame_id)))
+                .collect(),
+            partial: dterm.partial,
+        }),
+        Term::Compound(cterm) => Term::Compound(CompoundTerm {
+            name: cterm.name.clone(),
+            args: cterm.args.iter().map(|arg| rename_vars(arg, renamed, frame_id)).collect(),
+        }),
+        other => other.clone(),
+    }
+}
+
+// copy_term(term) -> the same term, with new unknowns
+pub fn builtin_copy_term(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let copy = rename_vars(&cterm.args[0], &mut HashMap::new(), thread_rng().next_u32());
+    once(compute_most_gen_unifier(vec![(cterm.args[1].clone(), copy)]))
+}
+
+// Keys are given as atoms, like 'age
+fn dict_key(term: &Term, relation: &str) -> Result<String, Term> {
+    match term {
+        Term::Atom(s) => Ok(s[1..].to_string()),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+fn dict_arg(term: &Term, relation: &str) -> Result<DictTerm, Term> {
+    match term {
+        Term::Dict(dterm) => Ok(dterm.clone()),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+// get_dict(key, dict) -> value
+pub fn builtin_get_dict(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let key = dict_key(&cterm.args[0], "get_dict")?;
+    let dict = dict_arg(&cterm.args[1], "get_dict")?;
+    match dict.get(&key) {
+        Some(val) => once(compute_most_gen_unifier(vec![(val.clone(), cterm.args[2].clone())])),
+        None => Ok(None),
+    }
+}
+
+// put_dict(key, dict, value) -> dict
+pub fn builtin_put_dict(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 4)?;
+    let key = dict_key(&cterm.args[0], "put_dict")?;
+    let mut dict = dict_arg(&cterm.args[1], "put_dict")?;
+    dict.put(&key, cterm.args[2].clone());
+    once(compute_most_gen_unifier(vec![(Term::Dict(dict), cterm.args[3].clone())]))
+}
+
+// del_dict(key, dict) -> (value, dict)
+pub fn builtin_del_dict(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 4)?;
+    let key = dict_key(&cterm.args[0], "del_dict")?;
+    let mut dict = dict_arg(&cterm.args[1], "del_dict")?;
+    match dict.delete(&key) {
+        Some(val) => once(compute_most_gen_unifier(vec![
+            (val, cterm.args[2].clone()),
+            (Term::Dict(dict), cterm.args[3].clone()),
+        ])),
+        None => Ok(None),
+    }
+}
+
+// dict_keys(dict) -> keys
+pub fn builtin_dict_keys(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let dict = dict_arg(&cterm.args[0], "dict_keys")?;
+    let keys: Vec<Term> = dict.entries.iter()
+        .map(|(key, _)| Term::Atom(format!("'{}", key)))
+        .collect();
+    once(compute_most_gen_unifier(vec![(
+        Term::List(ListTerm {
+            front: keys,
+            tail: ListTail::End,
+        }),
+        cterm.args[1].clone()
+    )]))
+}
+
+fn integer_arg(term: &Term, relation: &str) -> Result<i32, Term> {
+    match term {
+        Term::Number(n) if n.is_integer() => Ok(n.to_integer()),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+// Unify the last argument with n, if it's a number, or
+// check that it's an integer, if it's already bound
+fn unify_integer(term: &Term, n: i32, relation: &str) -> Result<Option<Unifier>, Term> {
+    match term {
+        Term::Unknown(_) | Term::Number(_) => {
+            Ok(compute_most_gen_unifier(vec![(term.clone(), Term::Number(n.into()))]))
+        },
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+// succ(a) -> a + 1, for natural numbers. Either side can be unknown
+pub fn builtin_succ(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    match (&cterm.args[0], &cterm.args[1]) {
+        (Term::Unknown(_), Term::Unknown(_)) => Err(error_term("instantiation_error", "succ")),
+        (Term::Unknown(_), b) => {
+            let b = integer_arg(b, "succ")?;
+            if b < 0 {
+                return Err(error_term("type_error", "succ"));
+            }
+            if b == 0 {
+                return Ok(None);
+            }
+            once(unify_integer(&cterm.args[0], b - 1, "succ")?)
+        },
+        (a, b) => {
+            let a = integer_arg(a, "succ")?;
+            if a < 0 {
+                return Err(error_term("type_error", "succ"));
+            }
+            once(unify_integer(b, a + 1, "succ")?)
+        },
+    }
+}
+
+// The bounds and step of lo..hi by step. The upper bound is None
+// for between(lo, 'inf)
+struct Range {
+    lo: i32,
+    hi: Option<i32>,
+    step: i32,
+    exclusive: bool,
+}
+
+impl Range {
+    // The nth element, if the range has one
+    fn nth(&self, n: usize) -> Option<i32> {
+        let val = (n as i32).checked_mul(self.step)?.checked_add(self.lo)?;
+        let hi = match self.hi {
+            None => return Some(val),
+            Some(hi) => hi,
+        };
+        let inside = match (self.step > 0, self.exclusive) {
+            (true, false) => val <= hi,
+            (true, true) => val < hi,
+            (false, false) => val >= hi,
+            (false, true) => val > hi,
+        };
+        if inside { Some(val) } else { None }
+    }
+
+    // Unify x with each element from the nth on, until one matches
+    fn solve(&self, x: &Term, mut n: usize, relation: &str) -> BuiltinResult {
+        while let Some(val) = self.nth(n) {
+            if let Some(unifier) = unify_integer(x, val, relation)? {
+                return Ok(Some(Answer {
+                    unifier,
+                    next: self.nth(n + 1).map(|_| n + 1),
+                }));
+            }
+            n += 1;
+        }
+        Ok(None)
+    }
+}
+
+// The arguments lo, hi, step, and 'inclusive or 'exclusive of a range
+fn range_args(args: &[Term]) -> Result<Range, Term> {
+    let step = integer_arg(&args[2], "..")?;
+    if step == 0 {
+        return Err(error_term("evaluation_error", ".."));
+    }
+    Ok(Range {
+        lo: integer_arg(&args[0], "..")?,
+        hi: Some(integer_arg(&args[1], "..")?),
+        step,
+        exclusive: args[3] == Term::Atom("'exclusive".to_string()),
+    })
+}
+
+// between(lo, hi) -> x, where hi can be 'inf
+pub fn builtin_between(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let hi = match &cterm.args[1] {
+        Term::Atom(s) if s == "'inf" => None,
+        hi => Some(integer_arg(hi, "between")?),
+    };
+    let range = Range {
+        lo: integer_arg(&cterm.args[0], "between")?,
+        hi,
+        step: 1,
+        exclusive: false,
+    };
+    if let Term::Number(x) = &cterm.args[2] {
+        // Only one thing to check
+        let inside = x.is_integer() && x.to_integer() >= range.lo
+            && range.hi.map(|hi| x.to_integer() <= hi).unwrap_or(true);
+        return Ok(if inside { Some(Answer::last(Unifier::new())) } else { None });
+    }
+    range.solve(&cterm.args[2], start, "between")
+}
+
+// x in lo..hi by step
+pub fn builtin_range(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 5)?;
+    range_args(&cterm.args[..4])?.solve(&cterm.args[4], start, "..")
+}
+
+// lo..hi by step as a list
+pub fn builtin_range_list(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 5)?;
+    let range = range_args(&cterm.args[..4])?;
+    let front: Vec<Term> = (0..)
+        .map(|n| range.nth(n))
+        .take_while(|val| val.is_some())
+        .map(|val| Term::Number(val.unwrap().into()))
+        .collect();
+    once(compute_most_gen_unifier(vec![(
+        Term::List(ListTerm {
+            front,
+            tail: ListTail::End,
+        }),
+        cterm.args[4].clone(),
+    )]))
+}
+
+// x in list
+pub fn builtin_member(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let items = match &cterm.args[1] {
+        Term::List(lterm) => &lterm.front,
+        Term::Unknown(_) => return Err(error_term("instantiation_error", "in")),
+        _ => return Err(error_term("type_error", "in")),
+    };
+    for n in start..items.len() {
+        if let Some(unifier) = compute_most_gen_unifier(vec![(cterm.args[0].clone(), items[n].clone())]) {
+            return Ok(Some(Answer {
+                unifier,
+                next: if n + 1 < items.len() { Some(n + 1) } else { None },
+            }));
+        }
+    }
+    Ok(None)
+}
+
+// Interpret a term as the head of a fact, like parent('a, 'b) or 'sunny
+fn fact_head(term: &Term, relation: &str) -> Result<CompoundTerm, Term> {
+    match term {
+        Term::Compound(cterm) => Ok(cterm.clone()),
+        Term::Atom(s) => Ok(CompoundTerm {
+            name: s[1..].to_string(),
+            args: vec![],
+        }),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+fn new_fact(cterm: &CompoundTerm, relation: &str) -> Result<Rule, Term> {
+    check_args(cterm, 1)?;
+    let mut head = fact_head(&cterm.args[0], relation)?;
+    // Keep the variables of the new fact seperate from the caller's
+    let frame_id = thread_rng().next_u32();
+    for arg in head.args.iter_mut() {
+        arg.set_new_frame_id(frame_id);
+    }
+    Ok(Rule {
+        gives: head,
+        requires: Query { goals: vec![] },
+    })
+}
+
+pub fn builtin_assert(cterm: &CompoundTerm, rules: &mut Rules, _start: usize) -> BuiltinResult {
+    rules.assertz(new_fact(cterm, "assert")?);
+    Ok(Some(Answer::last(Unifier::new())))
+}
+
+pub fn builtin_asserta(cterm: &CompoundTerm, rules: &mut Rules, _start: usize) -> BuiltinResult {
+    rules.asserta(new_fact(cterm, "asserta")?);
+    Ok(Some(Answer::last(Unifier::new())))
+}
+
+pub fn builtin_retract(cterm: &CompoundTerm, rules: &mut Rules, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    let head = Term::Compound(fact_head(&cterm.args[0], "retract")?);
+    // Remove the first fact which matches
+    for (indx, rule) in rules.contents.iter().enumerate() {
+        if !rule.requires.goals.is_empty() {
+            continue;
+        }
+        let unifier = compute_most_gen_unifier(vec![(head.clone(), Term::Compound(rule.gives.clone()))]);
+        if let Some(unifier) = unifier {
+            rules.retract(indx);
+            return Ok(Some(Answer::last(unifier)));
+        }
+    }
+    Ok(None)
+}
+
+// The number of elements of a complete list
+fn proper_len(term: &Term) -> Option<usize> {
+    match term {
+        Term::List(ListTerm { front, tail: ListTail::End }) => Some(front.len()),
         _ => None,
     }
 }
 
+// The number of elements known so far
+fn front_len(term: &Term) -> usize {
+    match term {
+        Term::List(lterm) => lterm.front.len(),
+        _ => 0,
+    }
+}
+
+fn fresh_vars(frame_id: u32, from: usize, n: usize) -> Vec<Term> {
+    (from..from + n).map(|indx| Term::Unknown(fresh_var(frame_id, indx))).collect()
+}
+
+fn closed_list(front: Vec<Term>) -> Term {
+    Term::List(ListTerm {
+        front,
+        tail: ListTail::End,
+    })
+}
+
+// The list of front followed by the elements of rest, if rest is a list
+fn prepend(mut front: Vec<Term>, rest: &Term) -> Option<Term> {
+    match rest {
+        _ if front.is_empty() => Some(rest.clone()),
+        Term::List(lterm) => {
+            front.extend(lterm.front.iter().cloned());
+            Some(Term::List(ListTerm {
+                front,
+                tail: lterm.tail.clone(),
+            }))
+        },
+        Term::Unknown(tail) => Some(Term::List(ListTerm {
+            front,
+            tail: ListTail::Unknown(tail.clone()),
+        })),
+        _ => None,
+    }
+}
+
+// Lists which are only partly known are solved by trying lists of
+// each length in turn, from the start index. This stops after max,
+// if it's known. Otherwise it stops when a length past every known
+// element fails, since every longer list would fail the same way
+fn by_length<F>(start: usize, max: Option<usize>, known: usize, mut attempt: F) -> BuiltinResult
+    where F: FnMut(usize) -> Option<Unifier> {
+    let mut n = start;
+    loop {
+        match max {
+            Some(max) if n > max => return Ok(None),
+            _ => {},
+        }
+        match attempt(n) {
+            Some(unifier) => return Ok(Some(Answer {
+                unifier,
+                next: if max == Some(n) { None } else { Some(n + 1) },
+            })),
+            None if max.is_none() && n > known => return Ok(None),
+            None => n += 1,
+        }
+    }
+}
+
+// Lists can only be known or unknown
+fn list_arg(term: &Term, relation: &str) -> Result<(), Term> {
+    match term {
+        Term::List(_) | Term::Unknown(_) => Ok(()),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+// length(l) -> the number of elements in l. If l isn't
+// complete, it's given unknown elements to make it long enough,
+// or every length in turn if the length is unknown too
+pub fn builtin_length(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let (list, len) = (&cterm.args[0], &cterm.args[1]);
+    list_arg(list, "length")?;
+    if let Some(n) = proper_len(list) {
+        return once(unify_integer(len, n as i32, "length")?);
+    }
+    let frame_id = thread_rng().next_u32();
+    match len {
+        Term::Unknown(_) => by_length(start, None, front_len(list), |n| {
+            compute_most_gen_unifier(vec![
+                (list.clone(), closed_list(fresh_vars(frame_id, 0, n))),
+                (len.clone(), Term::Number((n as i32).into())),
+            ])
+        }),
+        _ => {
+            let n = integer_arg(len, "length")?;
+            if n < 0 {
+                return Err(error_term("type_error", "length"));
+            }
+            once(compute_most_gen_unifier(vec![(list.clone(), closed_list(fresh_vars(frame_id, 0, n as usize)))]))
+        },
+    }
+}
+
+// append(a, b) -> a followed by b. Also splits up
+// a list, when a is unknown
+pub fn builtin_append(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let (a, b, ab) = (&cterm.args[0], &cterm.args[1], &cterm.args[2]);
+    list_arg(a, "append")?;
+    if let Term::List(ListTerm { front, tail: ListTail::End }) = a {
+        return match prepend(front.clone(), b) {
+            Some(joined) => once(compute_most_gen_unifier(vec![(ab.clone(), joined)])),
+            None => Ok(None),
+        };
+    }
+    let frame_id = thread_rng().next_u32();
+    by_length(start, proper_len(ab), front_len(a) + front_len(ab), |n| {
+        let front = fresh_vars(frame_id, 0, n);
+        let joined = prepend(front.clone(), b)?;
+        compute_most_gen_unifier(vec![(a.clone(), closed_list(front)), (ab.clone(), joined)])
+    })
+}
+
+// reverse(l) -> the elements of l in the opposite order
+pub fn builtin_reverse(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let (list, rev) = (&cterm.args[0], &cterm.args[1]);
+    list_arg(list, "reverse")?;
+    list_arg(rev, "reverse")?;
+    let frame_id = thread_rng().next_u32();
+    let attempt = |n| {
+        let front = fresh_vars(frame_id, 0, n);
+        let back = front.iter().rev().cloned().collect();
+        compute_most_gen_unifier(vec![(list.clone(), closed_list(front)), (rev.clone(), closed_list(back))])
+    };
+    match proper_len(list).or_else(|| proper_len(rev)) {
+        Some(n) if start == 0 => once(attempt(n)),
+        Some(_) => Ok(None),
+        None => by_length(start, None, front_len(list) + front_len(rev), attempt),
+    }
+}
+
+// nth(n, l) -> the nth element of l, counting from 1. Gives
+// every element in turn if n is unknown
+pub fn builtin_nth(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let (indx, list, elem) = (&cterm.args[0], &cterm.args[1], &cterm.args[2]);
+    list_arg(list, "nth")?;
+    let frame_id = thread_rng().next_u32();
+    // The list with at least n elements, and the last of them
+    let pattern = |n: usize| {
+        let front = fresh_vars(frame_id, 0, n);
+        let nth = front[n - 1].clone();
+        vec![
+            (list.clone(), Term::List(ListTerm {
+                front,
+                tail: ListTail::Unknown(fresh_var(frame_id, n)),
+            })),
+            (elem.clone(), nth),
+        ]
+    };
+    match indx {
+        Term::Unknown(_) => {
+            if proper_len(list) == Some(0) {
+                return Ok(None);
+            }
+            let max = proper_len(list).map(|len| len - 1);
+            by_length(start, max, front_len(list), |n| {
+                let mut pairs = pattern(n + 1);
+                pairs.push((indx.clone(), Term::Number((n as i32 + 1).into())));
+                compute_most_gen_unifier(pairs)
+            })
+        },
+        _ => {
+            let n = integer_arg(indx, "nth")?;
+            if n < 1 {
+                return Ok(None);
+            }
+            once(compute_most_gen_unifier(pattern(n as usize)))
+        },
+    }
+}
+
+// last(l) -> the last element of l
+pub fn builtin_last(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let (list, elem) = (&cterm.args[0], &cterm.args[1]);
+    list_arg(list, "last")?;
+    if let Term::List(ListTerm { front, tail: ListTail::End }) = list {
+        return match front.last() {
+            Some(last) => once(compute_most_gen_unifier(vec![(elem.clone(), last.clone())])),
+            None => Ok(None),
+        };
+    }
+    let frame_id = thread_rng().next_u32();
+    by_length(start.max(1), None, front_len(list), |n| {
+        let front = fresh_vars(frame_id, 0, n);
+        let last = front[n - 1].clone();
+        compute_most_gen_unifier(vec![(list.clone(), closed_list(front)), (elem.clone(), last)])
+    })
+}
+
+// member(x, l) succeeds for each element of l which x unifies
+// with. If l isn't complete, x can also be one of its unknown elements
+pub fn builtin_list_member(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let (elem, list) = (&cterm.args[0], &cterm.args[1]);
+    list_arg(list, "member")?;
+    if let Term::List(ListTerm { tail: ListTail::End, .. }) = list {
+        let goal = CompoundTerm {
+            name: "$member".to_string(),
+            args: vec![elem.clone(), list.clone()],
+        };
+        return builtin_member(&goal, start);
+    }
+    let frame_id = thread_rng().next_u32();
+    let max = proper_len(list).map(|len| len - 1);
+    by_length(start, max, front_len(list), |n| {
+        let front = fresh_vars(frame_id, 0, n + 1);
+        let nth = front[n].clone();
+        let pattern = Term::List(ListTerm {
+            front,
+            tail: ListTail::Unknown(fresh_var(frame_id, n + 1)),
+        });
+        compute_most_gen_unifier(vec![(list.clone(), pattern), (elem.clone(), nth)])
+    })
+}
+
+// select(x, l) -> l without one element which x unifies with.
+// Also puts x in each place in a list, when l is unknown
+pub fn builtin_select(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let (elem, list, rest) = (&cterm.args[0], &cterm.args[1], &cterm.args[2]);
+    list_arg(list, "select")?;
+    list_arg(rest, "select")?;
+    let frame_id = thread_rng().next_u32();
+    let max = match (proper_len(list), proper_len(rest)) {
+        (Some(0), _) => return Ok(None),
+        (Some(len), _) => Some(len - 1),
+        (None, Some(len)) => Some(len),
+        (None, None) => None,
+    };
+    by_length(start, max, front_len(list) + front_len(rest), |n| {
+        let before = fresh_vars(frame_id, 0, n);
+        let tail = fresh_var(frame_id, n);
+        let mut with = before.clone();
+        with.push(elem.clone());
+        compute_most_gen_unifier(vec![
+            (list.clone(), Term::List(ListTerm {
+                front: with,
+                tail: ListTail::Unknown(tail.clone()),
+            })),
+            (rest.clone(), Term::List(ListTerm {
+                front: before,
+                tail: ListTail::Unknown(tail),
+            })),
+        ])
+    })
+}
+
+// The numbers in a complete list
+fn number_list(term: &Term, relation: &str) -> Result<Vec<Rational32>, Term> {
+    sort_arg(term, relation)?.iter()
+        .map(|item| match item {
+            Term::Number(n) => Ok(*n),
+            Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+            _ => Err(error_term("type_error", relation)),
+        })
+        .collect()
+}
+
+pub fn builtin_sum_list(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let sum = number_list(&cterm.args[0], "sum_list")?.into_iter()
+        .fold(Rational32::from_integer(0), |a, b| a + b);
+    once(compute_most_gen_unifier(vec![(cterm.args[1].clone(), Term::Number(sum))]))
+}
+
+// Fails for the empty list
+pub fn builtin_max_list(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    match number_list(&cterm.args[0], "max_list")?.into_iter().max() {
+        Some(max) => once(compute_most_gen_unifier(vec![(cterm.args[1].clone(), Term::Number(max))])),
+        None => Ok(None),
+    }
+}
+
+// Everything in a list and the lists inside of it, in order.
+// Unknowns are kept as they are, like in Prolog
+fn flatten_into(term: &Term, flat: &mut Vec<Term>) {
+    match term {
+        Term::List(lterm) => {
+            for item in lterm.front.iter() {
+                flatten_into(item, flat);
+            }
+            if let ListTail::Unknown(tail) = &lterm.tail {
+                flat.push(Term::Unknown(tail.clone()));
+            }
+        },
+        other => flat.push(other.clone()),
+    }
+}
+
+pub fn builtin_flatten(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let mut flat = vec![];
+    flatten_into(&cterm.args[0], &mut flat);
+    unify_list(&cterm.args[1], flat)
+}
+
+// zip(a, b) -> the list of pairs of elements of a and b, which
+// must be the same length. Also unzips a list of pairs
+pub fn builtin_zip(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let (a, b, pairs) = (&cterm.args[0], &cterm.args[1], &cterm.args[2]);
+    for list in cterm.args.iter() {
+        list_arg(list, "zip")?;
+    }
+    let frame_id = thread_rng().next_u32();
+    let attempt = |n| {
+        let firsts = fresh_vars(frame_id, 0, n);
+        let seconds = fresh_vars(frame_id, n, n);
+        let zipped = firsts.iter().zip(seconds.iter())
+            .map(|(x, y)| Term::Compound(CompoundTerm {
+                name: TUPLE.to_string(),
+                args: vec![x.clone(), y.clone()],
+            }))
+            .collect();
+        compute_most_gen_unifier(vec![
+            (a.clone(), closed_list(firsts)),
+            (b.clone(), closed_list(seconds)),
+            (pairs.clone(), closed_list(zipped)),
+        ])
+    };
+    match proper_len(a).or_else(|| proper_len(b)).or_else(|| proper_len(pairs)) {
+        Some(n) if start == 0 => once(attempt(n)),
+        Some(_) => Ok(None),
+        None => by_length(start, None, front_len(a) + front_len(b) + front_len(pairs), attempt),
+    }
+}
+
+// exclude(p, l) -> the elements of l for which the relation p fails
+pub fn builtin_exclude(cterm: &CompoundTerm, call: &mut Call) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    relation_goal(&cterm.args[0], vec![], "exclude")?;
+    let mut kept = vec![];
+    for item in sort_arg(&cterm.args[1], "exclude")? {
+        let goal = relation_goal(&cterm.args[0], vec![item.clone()], "exclude")?;
+        if call(goal)?.is_none() {
+            kept.push(item);
+        }
+    }
+    unify_list(&cterm.args[2], kept)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -437,8 +1737,8 @@ aroundzero(x) {
             
             let prog = parse_program(pairs, program_input.as_ref());
             
-            let errs = checks::perform_checks(&prog, "test".to_string());
-            let num_errs = errs.len();
+            let errs = checks::perform_checks(&prog, None, "test".to_string());
+            let num_errs = errs.iter().filter(|err| !err.is_warning()).count();
             
             assert!(num_errs == 0);
             let mut prog_rules = solver::parse::parse_program(&prog);
@@ -466,13 +1766,15 @@ aroundzero(x) {
                 goals: as_terms
             };
             let mut state = solver::solve::new_solver_state(query);
-            let solution = solver::solve::solve(&prog_rules, solver::solve::SolverState {
+            let solution = solver::solve::solve(&mut prog_rules, solver::solve::SolverState {
                 master: &mut state.master,
                 curr_query: &mut state.curr_query,
                 new_query: &mut state.new_query,
                 fact_indx: &mut state.fact_indx,
+                clauses: &mut state.clauses,
                 choice_points: &mut state.choice_points,
-            });
+                catches: &mut state.catches,
+            }).expect("uncaught exception");
             match solution {
                 Some(solution) => {
                     let asstr = solver::fmt_unifier(&solution);
@@ -490,4 +1792,275 @@ aroundzero(x) {
             }
         }
     }
+
+    #[test]
+    fn test_builtin_answers() {
+        use crate::solver::builtins::*;
+
+        let x = Term::Unknown(UnknownContents {
+            name: "x".to_string(),
+            frame_id: REPL_FRAME_ID,
+        });
+        let goal = CompoundTerm {
+            name: "between".to_string(),
+            args: vec![Term::Number(1.into()), Term::Number(3.into()), x.clone()],
+        };
+        // Follow the answers from the start until there are no more
+        let mut found = vec![];
+        let mut start = Some(0);
+        while let Some(indx) = start {
+            let answer = builtin_between(&goal, indx).unwrap().unwrap();
+            found.push(answer.unifier.get(&UnknownContents {
+                name: "x".to_string(),
+                frame_id: REPL_FRAME_ID,
+            }).unwrap().clone());
+            start = answer.next;
+        }
+        assert_eq!(found, vec![Term::Number(1.into()), Term::Number(2.into()), Term::Number(3.into())]);
+
+        // Deterministic builtins have a single answer
+        let goal = CompoundTerm {
+            name: "+".to_string(),
+            args: vec![Term::Number(1.into()), Term::Number(2.into()), x.clone()],
+        };
+        assert_eq!(builtin_add(&goal, 0).unwrap().unwrap().next, None);
+
+        // Elements which don't match are skipped
+        let goal = CompoundTerm {
+            name: "$member".to_string(),
+            args: vec![Term::Number(2.into()), Term::List(ListTerm {
+                front: vec![Term::Number(1.into()), Term::Number(2.into()), Term::Number(2.into())],
+                tail: ListTail::End,
+            })],
+        };
+        assert_eq!(builtin_member(&goal, 0).unwrap().unwrap().next, Some(2));
+        assert_eq!(builtin_member(&goal, 2).unwrap().unwrap().next, None);
+    }
+
+    #[test]
+    fn test_wrong_argument_counts() {
+        use crate::solver::builtins::*;
+
+        // No builtin takes six arguments
+        let builtins = builtins();
+        let names = [
+            "get_dict", "put_dict", "del_dict", "dict_keys",
+            "succ", "between", "$range", "$range_list", "$member",
+            "var", "nonvar", "number", "is_list", "ground", "functor", "arg", "univ", "copy_term",
+            "compare", "term_lt", "term_gt", "term_leq", "term_geq", "sort", "msort", "sort_by_key",
+            "length", "append", "reverse", "nth", "last", "member", "select",
+            "sum_list", "max_list", "flatten", "zip",
+        ];
+        for name in names.iter() {
+            let goal = CompoundTerm {
+                name: name.to_string(),
+                args: vec![Term::Number(1.into()); 6],
+            };
+            assert_eq!(builtins[*name](&goal, 0).err(), Some(error_term("type_error", name)));
+        }
+        for (name, builtin) in meta_builtins().iter() {
+            let goal = CompoundTerm {
+                name: name.to_string(),
+                args: vec![Term::Number(1.into()); 6],
+            };
+            assert_eq!(builtin(&goal, &mut |_| Ok(None)).err(), Some(error_term("type_error", name)));
+        }
+        let mut rules = Rules::new(vec![], HashSet::new());
+        for (name, builtin) in database_builtins().iter() {
+            let goal = CompoundTerm {
+                name: name.to_string(),
+                args: vec![Term::Number(1.into()); 6],
+            };
+            assert_eq!(builtin(&goal, &mut rules, 0).err(), Some(error_term("type_error", name)));
+        }
+    }
+
+    #[test]
+    fn test_builtin_arities() {
+        use crate::solver::builtins::*;
+
+        let arities = builtin_arities();
+        let mut names: Vec<String> = builtins().into_keys()
+            .chain(database_builtins().into_keys())
+            .chain(meta_builtins().into_keys())
+            .filter(|name| name != "writeln")
+            .collect();
+        let mut listed: Vec<String> = arities.keys().cloned().collect();
+        names.sort();
+        listed.sort();
+        assert_eq!(names, listed);
+    }
+
+    #[test]
+    fn test_partial_lists() {
+        use crate::solver::builtins::*;
+
+        let var = |name: &str| UnknownContents {
+            name: name.to_string(),
+            frame_id: REPL_FRAME_ID,
+        };
+        let partial = Term::List(ListTerm {
+            front: vec![Term::Number(1.into())],
+            tail: ListTail::Unknown(var("t")),
+        });
+        let goal = |name: &str, args: Vec<Term>| CompoundTerm {
+            name: name.to_string(),
+            args,
+        };
+
+        // A partial list is neither a variable nor a list
+        assert!(builtin_var(&goal("var", vec![partial.clone()]), 0).unwrap().is_none());
+        assert!(builtin_is_list(&goal("is_list", vec![partial.clone()]), 0).unwrap().is_none());
+        assert!(builtin_ground(&goal("ground", vec![partial.clone()]), 0).unwrap().is_none());
+
+        // Its second argument is the unknown tail
+        let answer = builtin_arg(&goal("arg", vec![
+            Term::Number(2.into()), partial.clone(), Term::Unknown(var("a"))
+        ]), 0).unwrap().unwrap();
+        assert_eq!(answer.unifier.get(&var("a")), Some(&Term::Unknown(var("t"))));
+
+        // Building it back up from its parts gives the same list
+        let answer = builtin_univ(&goal("univ", vec![
+            Term::Unknown(var("l")),
+            Term::List(ListTerm {
+                front: vec![Term::Atom(CONS.to_string()), Term::Number(1.into()), Term::Unknown(var("t"))],
+                tail: ListTail::End,
+            }),
+        ]), 0).unwrap().unwrap();
+        assert_eq!(answer.unifier.get(&var("l")), Some(&partial));
+
+        // Copies keep the tail unknown, but with a new name
+        let answer = builtin_copy_term(&goal("copy_term", vec![partial.clone(), Term::Unknown(var("c"))]), 0)
+            .unwrap().unwrap();
+        match answer.unifier.get(&var("c")) {
+            Some(Term::List(ListTerm { front, tail: ListTail::Unknown(tail) })) => {
+                assert_eq!(front, &vec![Term::Number(1.into())]);
+                assert_ne!(tail, &var("t"));
+            },
+            other => panic!("Bad copy {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_defined_relations() {
+        use crate::solver::builtins::*;
+        use std::collections::HashSet;
+
+        let fact = |n: i32| CompoundTerm {
+            name: "assert".to_string(),
+            args: vec![Term::Compound(CompoundTerm {
+                name: "seen".to_string(),
+                args: vec![Term::Number(n.into())],
+            })],
+        };
+        let mut rules = Rules::new(vec![], HashSet::new());
+        assert!(!rules.is_defined("seen", 1));
+        builtin_assert(&fact(1), &mut rules, 0).unwrap();
+        builtin_asserta(&fact(2), &mut rules, 0).unwrap();
+        assert!(rules.is_defined("seen", 1));
+        assert!(!rules.is_defined("seen", 2));
+
+        // It stays defined until its last clause is removed
+        builtin_retract(&fact(1), &mut rules, 0).unwrap().unwrap();
+        assert!(rules.is_defined("seen", 1));
+        builtin_retract(&fact(2), &mut rules, 0).unwrap().unwrap();
+        assert!(!rules.is_defined("seen", 1));
+    }
+
+    #[test]
+    fn test_standard_order() {
+        use crate::solver::builtins::*;
+        use std::cmp::Ordering;
+
+        let list = |items: Vec<i32>| Term::List(ListTerm {
+            front: items.into_iter().map(|n| Term::Number(n.into())).collect(),
+            tail: ListTail::End,
+        });
+        let unknown = Term::Unknown(UnknownContents {
+            name: "x".to_string(),
+            frame_id: REPL_FRAME_ID,
+        });
+        let pair = Term::Compound(CompoundTerm {
+            name: TUPLE.to_string(),
+            args: vec![Term::Number(1.into()), Term::Number(2.into())],
+        });
+        let dict = Term::Dict(DictTerm::new(vec![("a".to_string(), Term::Number(1.into()))], false));
+
+        let mut terms = vec![
+            dict.clone(), pair.clone(), list(vec![1]), Term::Atom("'a".to_string()),
+            list(vec![]), Term::Number(2.into()), unknown.clone(),
+        ];
+        terms.sort_by(compare_terms);
+        assert_eq!(terms, vec![
+            unknown.clone(), Term::Number(2.into()), list(vec![]), Term::Atom("'a".to_string()),
+            list(vec![1]), pair.clone(), dict.clone(),
+        ]);
+
+        // Lists are compared element by element, then by length
+        assert_eq!(compare_terms(&list(vec![1]), &list(vec![1, 2])), Ordering::Less);
+        assert_eq!(compare_terms(&list(vec![2]), &list(vec![1, 2])), Ordering::Greater);
+
+        // A partial list is ordered by its tail once its elements run out
+        let partial = Term::List(ListTerm {
+            front: vec![Term::Number(1.into())],
+            tail: ListTail::Unknown(UnknownContents {
+                name: "t".to_string(),
+                frame_id: REPL_FRAME_ID,
+            }),
+        });
+        assert_eq!(compare_terms(&partial, &list(vec![1, 2])), Ordering::Less);
+        assert_eq!(compare_terms(&partial, &partial.clone()), Ordering::Equal);
+
+        // Equality is structural and never an error
+        let goal = |a: Term, b: Term| CompoundTerm {
+            name: "==".to_string(),
+            args: vec![a, b],
+        };
+        assert!(builtin_equ(&goal(list(vec![1]), list(vec![1, 2])), 0).unwrap().is_none());
+        assert!(builtin_equ(&goal(Term::Atom("'a".to_string()), pair.clone()), 0).unwrap().is_none());
+        assert!(builtin_equ(&goal(unknown.clone(), unknown.clone()), 0).unwrap().is_some());
+        assert!(builtin_neq(&goal(unknown.clone(), Term::Number(1.into())), 0).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_partial_list_modes() {
+        use crate::solver::builtins::*;
+
+        let var = |name: &str| UnknownContents {
+            name: name.to_string(),
+            frame_id: REPL_FRAME_ID,
+        };
+        let partial = |item: &str, tail: &str| Term::List(ListTerm {
+            front: vec![Term::Atom(item.to_string())],
+            tail: ListTail::Unknown(var(tail)),
+        });
+        let goal = |name: &str, args: Vec<Term>| CompoundTerm {
+            name: name.to_string(),
+            args,
+        };
+
+        // append('a : t, b) -> ('b : s) can never succeed, and
+        // stops instead of trying longer and longer lists
+        let append = goal("append", vec![partial("'a", "t"), Term::Unknown(var("b")), partial("'b", "s")]);
+        assert_eq!(builtin_append(&append, 0), Ok(None));
+
+        // With nothing known, every length is tried in turn
+        let append = goal("append", vec![Term::Unknown(var("a")), Term::Unknown(var("b")), Term::Unknown(var("c"))]);
+        let answer = builtin_append(&append, 0).unwrap().unwrap();
+        assert_eq!(answer.next, Some(1));
+        assert_eq!(answer.unifier.get(&var("a")), Some(&Term::List(ListTerm {
+            front: vec![],
+            tail: ListTail::End,
+        })));
+        assert_eq!(builtin_append(&append, 5).unwrap().unwrap().next, Some(6));
+
+        // A length fills in the unknown tail
+        let length = goal("length", vec![partial("'a", "t"), Term::Number(2.into())]);
+        let answer = builtin_length(&length, 0).unwrap().unwrap();
+        assert_eq!(answer.next, None);
+        match answer.unifier.get(&var("t")) {
+            Some(Term::List(ListTerm { front, tail: ListTail::End })) => assert_eq!(front.len(), 1),
+            other => panic!("Bad tail {:?}", other),
+        }
+    }
 }
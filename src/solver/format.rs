@@ -0,0 +1,236 @@
+use crate::solver::*;
+
+use crate::solver::builtins::{check_args, error_term, once, succeed_if, BuiltinResult};
+use crate::solver::streams::{plain_text, readable_text, text_arg};
+use crate::solver::unify::compute_most_gen_unifier;
+
+use std::io::{self, Write as IoWrite};
+
+// The directives in a template start with ~, which can be
+// followed by a numeric argument and then:
+//   w  the next argument, written like write
+//   q  the next argument, written like print
+//   a  the next argument, which must be an atom
+//   d  the next argument, which must be an integer, with a
+//      decimal point inserted that many digits from the right
+//   f  the next argument as a decimal, with that many digits
+//      after the point (6 by default)
+//   e  the next argument in scientific notation
+//   n  that many line breaks
+//   ~  a ~
+//   t  a point to fill with padding, with the fill character
+//      given like ~`-t
+//   |  a column stop at that column
+//   +  a column stop that many columns after the last one
+//
+// Text between column stops is padded to reach the column,
+// at its fill points or else at the end
+struct Formatter {
+    out: String,
+    // Where the current line started
+    line_start: usize,
+    // The column of the last column stop
+    last_stop: usize,
+    fills: Vec<(usize, char)>,
+}
+
+impl Formatter {
+    fn column(&self) -> usize {
+        self.out[self.line_start..].chars().count()
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.line_start = self.out.len();
+        self.last_stop = 0;
+        self.fills.clear();
+    }
+
+    fn column_stop(&mut self, target: usize) {
+        let column = self.column();
+        if column < target {
+            let pad = target - column;
+            if self.fills.is_empty() {
+                self.fills.push((self.out.len(), ' '));
+            }
+            // Spread the padding over the fill points, with
+            // any left over going to the last ones
+            let count = self.fills.len();
+            for (i, (pos, fill)) in self.fills.iter().enumerate().rev() {
+                let width = pad / count + if i >= count - pad % count { 1 } else { 0 };
+                let padding = fill.to_string().repeat(width);
+                self.out.insert_str(*pos, &padding);
+            }
+        }
+        self.last_stop = target.max(column);
+        self.fills.clear();
+    }
+}
+
+fn number_arg(term: &Term) -> Result<f64, Term> {
+    match term {
+        Term::Number(n) => Ok(*n.numer() as f64 / *n.denom() as f64),
+        Term::Unknown(_) => Err(error_term("instantiation_error", "format")),
+        _ => Err(error_term("type_error", "format")),
+    }
+}
+
+fn format_text(template: &str, args: &[Term]) -> Result<String, Term> {
+    let format_error = || error_term("format_error", "format");
+    let mut fmt = Formatter {
+        out: String::new(),
+        line_start: 0,
+        last_stop: 0,
+        fills: vec![],
+    };
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            fmt.newline();
+            continue;
+        } else if c != '~' {
+            fmt.out.push(c);
+            continue;
+        }
+        let mut fill = ' ';
+        let mut numeric = None;
+        if chars.peek() == Some(&'`') {
+            chars.next();
+            fill = chars.next().ok_or_else(format_error)?;
+        } else {
+            let mut digits = String::new();
+            while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(*d);
+                chars.next();
+            }
+            numeric = digits.parse::<usize>().ok();
+        }
+        let mut next_arg = || args.next().ok_or_else(format_error);
+        match chars.next().ok_or_else(format_error)? {
+            'w' => fmt.out.push_str(&plain_text(next_arg()?)),
+            'q' => fmt.out.push_str(&readable_text(next_arg()?)),
+            'a' => match next_arg()? {
+                Term::Atom(s) => fmt.out.push_str(&s[1..]),
+                Term::Unknown(_) => return Err(error_term("instantiation_error", "format")),
+                _ => return Err(error_term("type_error", "format")),
+            },
+            'd' => match next_arg()? {
+                Term::Number(n) if n.is_integer() => {
+                    let digits = n.to_integer().unsigned_abs().to_string();
+                    let sign = if n.to_integer() < 0 { "-" } else { "" };
+                    match numeric {
+                        Some(point) if point > 0 => {
+                            let digits = format!("{:0>width$}", digits, width = point + 1);
+                            let (whole, frac) = digits.split_at(digits.len() - point);
+                            fmt.out.push_str(&format!("{}{}.{}", sign, whole, frac));
+                        },
+                        _ => fmt.out.push_str(&format!("{}{}", sign, digits)),
+                    }
+                },
+                Term::Unknown(_) => return Err(error_term("instantiation_error", "format")),
+                _ => return Err(error_term("type_error", "format")),
+            },
+            'f' => {
+                let n = number_arg(next_arg()?)?;
+                fmt.out.push_str(&format!("{:.*}", numeric.unwrap_or(6), n));
+            },
+            'e' => {
+                // Exponents are written with a sign and two digits, like 1.5e+03
+                let n = format!("{:.*e}", numeric.unwrap_or(6), number_arg(next_arg()?)?);
+                let (mantissa, exp) = n.split_at(n.find('e').unwrap());
+                let exp: i32 = exp[1..].parse().unwrap();
+                let sign = if exp < 0 { '-' } else { '+' };
+                fmt.out.push_str(&format!("{}e{}{:02}", mantissa, sign, exp.abs()));
+            },
+            'n' => {
+                for _ in 0..numeric.unwrap_or(1) {
+                    fmt.newline();
+                }
+            },
+            '~' => fmt.out.push('~'),
+            't' => fmt.fills.push((fmt.out.len(), fill)),
+            '|' => {
+                let column = fmt.column();
+                fmt.column_stop(numeric.unwrap_or(column));
+            },
+            '+' => {
+                let target = fmt.last_stop + numeric.unwrap_or(8);
+                fmt.column_stop(target);
+            },
+            _ => return Err(format_error()),
+        }
+    }
+    if args.next().is_some() {
+        return Err(format_error());
+    }
+    Ok(fmt.out)
+}
+
+// The arguments to a template are a list, or a single term
+fn format_args(cterm: &CompoundTerm) -> Result<String, Term> {
+    let template = text_arg(&cterm.args[0], "format")?;
+    match &cterm.args[1] {
+        Term::List(ListTerm { front, tail: ListTail::End }) => format_text(&template, front),
+        Term::Unknown(_) | Term::List(_) => Err(error_term("instantiation_error", "format")),
+        term => format_text(&template, std::slice::from_ref(term)),
+    }
+}
+
+// format(template, args) prints the text, unless it's used
+// as an expression, where it's the same as format_to_string
+pub fn builtin_format(cterm: &CompoundTerm, start: usize) -> BuiltinResult {
+    if cterm.args.len() == 3 {
+        return builtin_format_to_string(cterm, start);
+    }
+    check_args(cterm, 2)?;
+    print!("{}", format_args(cterm)?);
+    io::stdout().flush().map_err(|_| error_term("io_error", "format"))?;
+    succeed_if(true)
+}
+
+pub fn builtin_format_to_string(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let text = Term::Atom(format!("'{}", format_args(cterm)?));
+    once(compute_most_gen_unifier(vec![(cterm.args[2].clone(), text)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(name: &str) -> Term {
+        Term::Atom(format!("'{}", name))
+    }
+
+    #[test]
+    fn test_format_text() {
+        let args = vec![atom("a b"), atom("a b"), Term::Number(Rational32::new(5, 2)), Term::Number(1234.into())];
+        assert_eq!(format_text("~w|~q|~4f|~2d~n", &args), Ok("a b|\"a b\"|2.5000|12.34\n".to_string()));
+        assert_eq!(format_text("~a", &args[2..3]), Err(error_term("type_error", "format")));
+        assert_eq!(format_text("~2e", &args[2..3]), Ok("2.50e+00".to_string()));
+        let row = vec![atom("name"), Term::Number(42.into())];
+        assert_eq!(format_text("~a~t~8|~t~w~6+|", &row), Ok("name        42|".to_string()));
+        assert_eq!(format_text("~`-t~30|", &[]), Ok("-".repeat(30)));
+        assert_eq!(format_text("~t~w~t~8|", &[atom("mid")]), Ok("  mid   ".to_string()));
+        assert_eq!(format_text("~w ~w", &row[..1]), Err(error_term("format_error", "format")));
+        assert_eq!(format_text("~w", &row), Err(error_term("format_error", "format")));
+        assert_eq!(format_text("~z", &[]), Err(error_term("format_error", "format")));
+        let min = Term::Number(i32::MIN.into());
+        assert_eq!(format_text("~d ~2d", &[min.clone(), min]), Ok("-2147483648 -21474836.48".to_string()));
+    }
+
+    #[test]
+    fn test_wrong_argument_counts() {
+        let goal = CompoundTerm {
+            name: "format".to_string(),
+            args: vec![atom("~w")],
+        };
+        assert_eq!(builtin_format(&goal, 0).err(), Some(error_term("type_error", "format")));
+        let goal = CompoundTerm {
+            name: "format_to_string".to_string(),
+            args: vec![atom("~w"), atom("a")],
+        };
+        assert_eq!(builtin_format_to_string(&goal, 0).err(), Some(error_term("type_error", "format_to_string")));
+    }
+}
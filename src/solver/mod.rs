@@ -4,6 +4,8 @@ pub mod solve;
 pub mod parse;
 pub mod builtins;
 pub mod optimize;
+pub mod streams;
+pub mod format;
 
 use std::fmt;
 
@@ -11,10 +13,16 @@ use num_rational::Rational32;
 
 use std::fmt::Write;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use std::rc::Rc;
 
 pub type Unifier = HashMap<UnknownContents, Term>;
 
+// Tuples are compounds with this name, which is
+// also what they're called in the Prolog backend
+pub const TUPLE: &str = "tuple";
+
 pub fn fmt_unifier(unif: &Unifier) -> String {
     let mut res = String::new();
     let mut first = true;
@@ -33,7 +41,48 @@ pub fn fmt_unifier(unif: &Unifier) -> String {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rules {
-    pub contents: Vec<Rule>
+    // Shared so that running goals can keep a snapshot
+    // of the database while it's being changed
+    pub contents: Rc<Vec<Rule>>,
+    // Relations declared dynamic, which can have no clauses
+    pub dynamic: Rc<HashSet<(String, usize)>>,
+    // The name and arity of every relation with a clause, so
+    // goals can be looked up without going through every rule
+    pub defined: Rc<HashSet<(String, usize)>>,
+}
+
+impl Rules {
+    pub fn new(contents: Vec<Rule>, dynamic: HashSet<(String, usize)>) -> Rules {
+        let defined = contents.iter().map(Rule::key).collect();
+        Rules {
+            contents: Rc::new(contents),
+            dynamic: Rc::new(dynamic),
+            defined: Rc::new(defined),
+        }
+    }
+
+    pub fn is_defined(&self, name: &str, arity: usize) -> bool {
+        self.defined.contains(&(name.to_string(), arity))
+    }
+
+    pub fn assertz(&mut self, rule: Rule) {
+        Rc::make_mut(&mut self.defined).insert(rule.key());
+        Rc::make_mut(&mut self.contents).push(rule);
+    }
+
+    pub fn asserta(&mut self, rule: Rule) {
+        Rc::make_mut(&mut self.defined).insert(rule.key());
+        Rc::make_mut(&mut self.contents).insert(0, rule);
+    }
+
+    pub fn retract(&mut self, indx: usize) -> Rule {
+        let rule = Rc::make_mut(&mut self.contents).remove(indx);
+        let key = rule.key();
+        if !self.contents.iter().any(|other| other.key() == key) {
+            Rc::make_mut(&mut self.defined).remove(&key);
+        }
+        rule
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,6 +91,13 @@ pub struct Rule {
     pub requires: Query,
 }
 
+impl Rule {
+    // The name and arity of the relation this is a clause of
+    pub fn key(&self) -> (String, usize) {
+        (self.gives.name.clone(), self.gives.args.len())
+    }
+}
+
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Query {
@@ -60,8 +116,10 @@ pub enum Term {
     Atom(String),
     Number(Rational32),
     List(ListTerm),
+    Dict(DictTerm),
     Compound(CompoundTerm),
-    Refute(CompoundTerm),
+    Refute(Query),
+    Catch(CatchTerm),
 }
 
 impl fmt::Display for Term {
@@ -80,11 +138,17 @@ impl fmt::Display for Term {
             Term::List(lterm) => {
                 write!(f, "{}", lterm)
             },
+            Term::Dict(dterm) => {
+                write!(f, "{}", dterm)
+            },
             Term::Compound(cterm) => {
                 write!(f, "{}", cterm)
             },
-            Term::Refute(cterm) => {
-                write!(f, "refute {}", cterm)
+            Term::Refute(query) => {
+                write!(f, "refute {}", query)
+            },
+            Term::Catch(cterm) => {
+                write!(f, "catch {} with {} {}", cterm.goals, cterm.pattern, cterm.handler)
             },
         }
     }
@@ -92,9 +156,40 @@ impl fmt::Display for Term {
 
 impl fmt::Display for ListTerm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[")?;
+        match &self.tail {
+            ListTail::End => {
+                write!(f, "[")?;
+                let mut first = true;
+                for term in self.front.iter() {
+                    if first {
+                        first = false;
+                    } else {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", term)?;
+                }
+                write!(f, "]")
+            },
+            ListTail::Unknown(UnknownContents{
+                name: s,
+                frame_id: _,
+            }) => {
+                // Written the same way as a cons list in Bevel
+                write!(f, "(")?;
+                for term in self.front.iter() {
+                    write!(f, "{} : ", term)?;
+                }
+                write!(f, "{})", s)
+            },
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
         let mut first = true;
-        for term in self.front.iter() {
+        for term in self.goals.iter() {
             if first {
                 first = false;
             } else {
@@ -102,19 +197,15 @@ impl fmt::Display for ListTerm {
             }
             write!(f, "{}", term)?;
         }
-        match &self.tail {
-            ListTail::End => write!(f, "]"),
-            ListTail::Unknown(UnknownContents{
-                name: s,
-                frame_id: _,
-            }) => write!(f, "{}", s),
-        }
+        write!(f, ")")
     }
 }
 
 impl fmt::Display for CompoundTerm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)?;
+        if self.name != TUPLE {
+            write!(f, "{}", self.name)?;
+        }
         write!(f, "(")?;
         let mut first = true;
         for term in self.args.iter() {
@@ -145,11 +236,30 @@ impl Term {
                     contents.frame_id = frame_id;
                 }
             },
-            Term::Compound(cterm) | Term::Refute(cterm) => {
+            Term::Dict(dterm) => {
+                for (_, term) in dterm.entries.iter_mut() {
+                    term.set_new_frame_id(frame_id);
+                }
+            },
+            Term::Compound(cterm) => {
                 for term in cterm.args.iter_mut() {
                     term.set_new_frame_id(frame_id);
                 }
             },
+            Term::Refute(query) => {
+                for term in query.goals.iter_mut() {
+                    term.set_new_frame_id(frame_id);
+                }
+            },
+            Term::Catch(cterm) => {
+                for term in cterm.goals.goals.iter_mut() {
+                    term.set_new_frame_id(frame_id);
+                }
+                cterm.pattern.set_new_frame_id(frame_id);
+                for term in cterm.handler.goals.iter_mut() {
+                    term.set_new_frame_id(frame_id);
+                }
+            },
         }
     }
 }
@@ -175,6 +285,72 @@ pub enum ListTail {
     Unknown(UnknownContents),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictTerm {
+    // Sorted by key
+    pub entries: Vec<(String, Term)>,
+    // Dicts from patterns only need their own
+    // keys to be present to unify with another dict
+    pub partial: bool,
+}
+
+impl DictTerm {
+    pub fn new(mut entries: Vec<(String, Term)>, partial: bool) -> DictTerm {
+        // Keys are never repeated, since the checks
+        // report dicts which give a key twice
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        DictTerm {
+            entries,
+            partial,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Term> {
+        self.entries.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, val)| val)
+    }
+
+    pub fn put(&mut self, key: &str, val: Term) {
+        match self.entries.binary_search_by(|(k, _)| k.as_str().cmp(key)) {
+            Ok(indx) => self.entries[indx].1 = val,
+            Err(indx) => self.entries.insert(indx, (key.to_string(), val)),
+        }
+    }
+
+    pub fn delete(&mut self, key: &str) -> Option<Term> {
+        match self.entries.binary_search_by(|(k, _)| k.as_str().cmp(key)) {
+            Ok(indx) => Some(self.entries.remove(indx).1),
+            Err(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DictTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut first = true;
+        for (key, val) in self.entries.iter() {
+            if first {
+                first = false;
+            } else {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", key, val)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+// Runs goals, and if they throw something which
+// unifies with the pattern, runs the handler instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatchTerm {
+    pub goals: Query,
+    pub pattern: Box<Term>,
+    pub handler: Query,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompoundTerm {
     pub name: String,
@@ -1,5 +1,7 @@
 use crate::solver::*;
 
+use std::rc::Rc;
+
 impl Rule {
     pub fn substitute_all(&mut self, un: &Unifier) -> Option<()> {
         for term in self.requires.goals.iter_mut() {
@@ -13,7 +15,7 @@ impl Rule {
 }
 
 pub fn apply_optimizations(rules: &mut Rules) {
-    for rule in rules.contents.iter_mut() {
+    for rule in Rc::make_mut(&mut rules.contents).iter_mut() {
         while apply_unneccessary_variable_opt(rule) {}
     }
 }
@@ -15,9 +15,13 @@ pub fn parse_program<'p>(pnode: &ProgramNode<'p>) -> Rules {
     for rnode in pnode.relations.iter() {
         contents.push(parse_relation(&rnode));
     }
-    Rules {
-        contents: contents
+    let mut dynamic = HashSet::new();
+    for decl in pnode.declarations.iter() {
+        if let DeclarationNode::Dynamic(ddnode) = decl {
+            dynamic.insert((ddnode.relation.name.clone(), ddnode.arity));
+        }
     }
+    Rules::new(contents, dynamic)
 }
 
 pub fn parse_relation<'p>(rnode: &RelationNode<'p>) -> Rule {
@@ -48,7 +52,11 @@ pub fn parse_relation_block<'p>(rblock: &RelationBlock<'p>, frame_id: u32, cterm
         RelationBlock::Const(clist) => {
             let mut my_terms: Vec<Term> = clist.constants.iter()
                 .map(|cterm| {
-                    parse_constant(&cterm, frame_id)
+                    // These are values given by the relation,
+                    // not patterns
+                    let mut term = parse_constant(cterm, frame_id);
+                    close_dicts(&mut term);
+                    term
                 })
                 .collect();
             cterm.args.append(&mut my_terms);
@@ -119,16 +127,58 @@ pub fn parse_constant<'p>(cnode: &ConstantNode<'p>, frame_id: u32) -> Term {
                 })
             })
         },
+        ConstantContents::Tuple(items) => {
+            Term::Compound(CompoundTerm {
+                name: TUPLE.to_string(),
+                args: items.iter().map(|constant| {
+                    parse_constant(constant, frame_id)
+                }).collect(),
+            })
+        },
+        ConstantContents::Dict(entries) => {
+            Term::Dict(DictTerm::new(
+                entries.iter().map(|(key, constant)| {
+                    (key.clone(), parse_constant(constant, frame_id))
+                }).collect(),
+                true
+            ))
+        },
+    }
+}
+
+// Dicts from patterns only match on their own keys,
+// but dicts which are values should be exact
+fn close_dicts(term: &mut Term) {
+    match term {
+        Term::Dict(dterm) => {
+            dterm.partial = false;
+            for (_, val) in dterm.entries.iter_mut() {
+                close_dicts(val);
+            }
+        },
+        Term::List(lterm) => {
+            for val in lterm.front.iter_mut() {
+                close_dicts(val);
+            }
+        },
+        Term::Compound(cterm) => {
+            for val in cterm.args.iter_mut() {
+                close_dicts(val);
+            }
+        },
+        _ => {},
     }
 }
 
 pub fn parse_statement<'p>(statement: &StatementNode<'p>, frame_id: u32) -> Vec<Term> {
     match &statement {
-        StatementNode::Assignment(anode) => parse_assignment(&anode, frame_id),
-        StatementNode::Relate(rnode) => parse_relate(&rnode, frame_id),
-        StatementNode::Refute(rfnode) => parse_refute(&rfnode, frame_id),
-        StatementNode::BinaryFact(brnode) => parse_bfactnode(&brnode, frame_id),
-        StatementNode::Relation(rcallnode) => parse_relationcall(&rcallnode, frame_id),
+        StatementNode::Assignment(anode) => parse_assignment(anode, frame_id),
+        StatementNode::Relate(rnode) => parse_relate(rnode, frame_id),
+        StatementNode::Refute(rfnode) => parse_refute(rfnode, frame_id),
+        StatementNode::Catch(cnode) => parse_catch(cnode, frame_id),
+        StatementNode::In(inode) => parse_in(inode, frame_id),
+        StatementNode::BinaryFact(brnode) => parse_bfactnode(brnode, frame_id),
+        StatementNode::Relation(rcallnode) => parse_relationcall(rcallnode, frame_id),
     }
 }
 
@@ -182,10 +232,22 @@ pub fn parse_assignment<'p>(assignment: &AssignmentNode<'p>, frame_id: u32) -> V
         _ => {
             let mut res = vec![];
             let name = parse_expr_name(&assignment.rhs, frame_id, &mut res);
+            let lhs = &assignment.lhs.constants;
+            // Several patterns destructure a tuple
+            let pattern = if lhs.len() == 1 {
+                parse_constant(&lhs[0], frame_id)
+            } else {
+                Term::Compound(CompoundTerm {
+                    name: TUPLE.to_string(),
+                    args: lhs.iter().map(|constant| {
+                        parse_constant(constant, frame_id)
+                    }).collect(),
+                })
+            };
             let assign_term = Term::Compound(CompoundTerm {
                 name: "=".to_string(),
                 args: vec![
-                    parse_constant(&assignment.lhs.constants[0], frame_id),
+                    pattern,
                     Term::Unknown(name),
                 ],
             });
@@ -272,75 +334,9 @@ pub fn parse_expr_name<'p>(expr: &ExpressionNode<'p>, frame_id: u32, res: &mut V
             for expr in vals[..vals.len() - 1].iter() {
                 names.push(Term::Unknown(parse_expr_name(&expr, frame_id, res)));
             }
-            let list_tail =
-                match &vals[vals.len() - 1].contents {
-                    ExpressionContents::List { vals } => {
-                        for expr in vals {
-                            names.push(Term::Unknown(parse_expr_name(&expr, frame_id, res)));
-                        }
-                        ListTail::End
-                    },
-                    ExpressionContents::Const(cnode) => {
-                        match &cnode.contents {
-                            ConstantContents::Var(s) => {
-                                ListTail::Unknown(UnknownContents {
-                                    name: s.clone(),
-                                    frame_id: frame_id
-                                })
-                            },
-                            ConstantContents::List(l) => {
-                                for expr in l {
-                                    let new_name = UnknownContents {
-                                        name: format!("Tmp{}", iter::repeat(())
-                                            .map(|()| rng.sample(Alphanumeric))
-                                            .filter(|c| !c.is_digit(10))
-                                            .take(6)
-                                            .collect::<String>()),
-                                        frame_id: frame_id
-                                    };
-                                    let cterm = parse_constant(expr, frame_id);
-                                    let assign_term = Term::Compound(CompoundTerm {
-                                        name: "=".to_string(),
-                                        args: vec![
-                                            cterm,
-                                            Term::Unknown(new_name.clone()),
-                                        ],
-                                    });
-                                    res.push(assign_term);
-                                }
-                                ListTail::End
-                            },
-                            ConstantContents::ConsList(_) => {
-                                unimplemented!()
-                            },
-                            _ => panic!()
-                        }
-                    },
-                    ExpressionContents::Call { rel, args } => {
-                        let new_name = UnknownContents {
-                            name: format!("Tmp{}", iter::repeat(())
-                                .map(|()| rng.sample(Alphanumeric))
-                                .filter(|c| !c.is_digit(10))
-                                .take(6)
-                                .collect::<String>()),
-                            frame_id: frame_id
-                        };
-                        let mut names = vec![];
-                        for exprnode in args.iter() {
-                            names.push(Term::Unknown(parse_expr_name(exprnode, frame_id, res)));
-                        }
-                        names.push(Term::Unknown(new_name.clone()));
-                        res.push(Term::Compound(CompoundTerm {
-                            name: rel.name.clone(),
-                            args: names,
-                        }));
-                        ListTail::Unknown(UnknownContents {
-                            name: new_name.name.clone(),
-                            frame_id: frame_id
-                        })
-                    },
-                    _ => panic!()
-                };
+            // The tail can be any expression, so evaluate it
+            // like any other and use its result as the tail
+            let list_tail = ListTail::Unknown(parse_expr_name(&vals[vals.len() - 1], frame_id, res));
             let list_term = Term::List(ListTerm {
                 front: names,
                 tail: list_tail,
@@ -354,15 +350,109 @@ pub fn parse_expr_name<'p>(expr: &ExpressionNode<'p>, frame_id: u32, res: &mut V
             });
             res.push(assign_term);
         },
+        ExpressionContents::Tuple { vals } => {
+            let mut names = vec![];
+            for expr in vals.iter() {
+                names.push(Term::Unknown(parse_expr_name(expr, frame_id, res)));
+            }
+            let assign_term = Term::Compound(CompoundTerm {
+                name: "=".to_string(),
+                args: vec![
+                    Term::Compound(CompoundTerm {
+                        name: TUPLE.to_string(),
+                        args: names,
+                    }),
+                    Term::Unknown(name.clone()),
+                ]
+            });
+            res.push(assign_term);
+        },
+        ExpressionContents::Range { lo, hi, step, exclusive } => {
+            let mut args = parse_range_args(lo, hi, step, *exclusive, frame_id, res);
+            args.push(Term::Unknown(name.clone()));
+            res.push(Term::Compound(CompoundTerm {
+                name: "$range_list".to_string(),
+                args,
+            }));
+        },
+        ExpressionContents::Dict { entries } => {
+            let entries: Vec<(String, Term)> = entries.iter()
+                .map(|(key, expr)| {
+                    (key.clone(), Term::Unknown(parse_expr_name(expr, frame_id, res)))
+                })
+                .collect();
+            let assign_term = Term::Compound(CompoundTerm {
+                name: "=".to_string(),
+                args: vec![
+                    Term::Dict(DictTerm::new(entries, false)),
+                    Term::Unknown(name.clone()),
+                ]
+            });
+            res.push(assign_term);
+        },
     }
     name
 }
 
+// The bounds, step and kind of a range, as arguments
+// to $range or $range_list
+fn parse_range_args<'p>(lo: &ExpressionNode<'p>, hi: &ExpressionNode<'p>, step: &Option<Box<ExpressionNode<'p>>>,
+                        exclusive: bool, frame_id: u32, res: &mut Vec<Term>) -> Vec<Term> {
+    let lo = Term::Unknown(parse_expr_name(lo, frame_id, res));
+    let hi = Term::Unknown(parse_expr_name(hi, frame_id, res));
+    let step = match step {
+        Some(step) => Term::Unknown(parse_expr_name(step, frame_id, res)),
+        None => Term::Number(1.into()),
+    };
+    let kind = if exclusive { "'exclusive" } else { "'inclusive" };
+    vec![lo, hi, step, Term::Atom(kind.to_string())]
+}
+
+pub fn parse_in<'p>(inode: &InNode<'p>, frame_id: u32) -> Vec<Term> {
+    let mut res = vec![];
+    let pattern = parse_constant(&inode.pattern, frame_id);
+    match &inode.collection.contents {
+        // Ranges are counted through without building a list
+        ExpressionContents::Range { lo, hi, step, exclusive } => {
+            let mut args = parse_range_args(lo, hi, step, *exclusive, frame_id, &mut res);
+            args.push(pattern);
+            res.push(Term::Compound(CompoundTerm {
+                name: "$range".to_string(),
+                args,
+            }));
+        },
+        _ => {
+            let list = parse_expr_name(&inode.collection, frame_id, &mut res);
+            res.push(Term::Compound(CompoundTerm {
+                name: "$member".to_string(),
+                args: vec![pattern, Term::Unknown(list)],
+            }));
+        },
+    }
+    res
+}
+
 pub fn parse_relationcall<'p>(rcallnode: &RelationCallNode<'p>, frame_id: u32) -> Vec<Term> {
     let mut res = vec![];
     let mut names = vec![];
     for expr in rcallnode.args.iter() {
-        names.push(Term::Unknown(parse_expr_name(&expr, frame_id, &mut res)));
+        match &expr.contents {
+            // The fact given to assert or retract isn't called,
+            // only its arguments are evaluated
+            ExpressionContents::Call { rel, args } if is_database_relation(&rcallnode.rel.name) => {
+                let mut arg_names = vec![];
+                for arg in args.iter() {
+                    arg_names.push(Term::Unknown(parse_expr_name(arg, frame_id, &mut res)));
+                }
+                names.push(Term::Compound(CompoundTerm {
+                    name: rel.name.clone(),
+                    args: arg_names,
+                }));
+            },
+            _ => {
+                names.push(Term::Unknown(parse_expr_name(expr, frame_id, &mut res)));
+            },
+        }
     }
     let cterm = Term::Compound(CompoundTerm {
         name: rcallnode.rel.name.clone(),
@@ -372,18 +462,34 @@ pub fn parse_relationcall<'p>(rcallnode: &RelationCallNode<'p>, frame_id: u32) -
     res
 }
 
+pub fn parse_catch<'p>(cnode: &CatchNode<'p>, frame_id: u32) -> Vec<Term> {
+    let parse_block = |bnode: &BlockNode<'p>| {
+        bnode.statements.iter()
+            .flat_map(|statement| parse_statement(statement, frame_id))
+            .collect()
+    };
+    vec![Term::Catch(CatchTerm {
+        goals: Query { goals: parse_block(&cnode.body) },
+        pattern: Box::new(parse_constant(&cnode.pattern, frame_id)),
+        handler: Query { goals: parse_block(&cnode.handler) },
+    })]
+}
+
 pub fn parse_refute<'p>(rfnode: &RefuteNode<'p>, frame_id: u32) -> Vec<Term> {
-    let mut res = vec![];
-    let mut names = vec![];
-    for expr in rfnode.statement.args.iter() {
-        names.push(Term::Unknown(parse_expr_name(&expr, frame_id, &mut res)));
-    }
-    let cterm = Term::Refute(CompoundTerm {
-        name: rfnode.statement.rel.name.clone(),
-        args: names,
-    });
-    res.push(cterm);
-    res
+    // Everything needed to evaluate the refuted statements (including
+    // temporaries for expressions) goes inside the refuted query, so
+    // that none of it leaks out of the negation
+    let goals: Vec<Term> = match &rfnode.body {
+        RefuteBody::Statement(statement) => parse_statement(statement, frame_id),
+        RefuteBody::Block(bnode) => {
+            bnode.statements.iter()
+                .flat_map(|statement| parse_statement(statement, frame_id))
+                .collect()
+        },
+    };
+    vec![Term::Refute(Query {
+        goals
+    })]
 }
 
 pub fn parse_relate<'p>(rnode: &RelateNode<'p>, frame_id: u32) -> Vec<Term> {
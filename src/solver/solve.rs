@@ -1,17 +1,45 @@
 
 use crate::solver::*;
 use crate::solver::unify::*;
-use crate::solver::builtins::builtins;
+use crate::solver::builtins::{builtins, database_builtins, meta_builtins, relation_goal, check_args, Answer, BuiltinResult};
 use crate::REPL_FRAME_ID;
 use rand::prelude::RngCore;
 use rand::{thread_rng};
+use std::rc::Rc;
+
+// The state of the solver when it chose a clause, the index of the
+// next clause to try, the clauses being tried, and the active catches
+pub type ChoicePoint = (Unifier, Query, usize, Rc<Vec<Rule>>, Vec<CatchFrame>);
+
+// A solution, or the term which was thrown and never caught
+pub type SolveResult = Result<Option<Unifier>, Term>;
+
+// A catch whose goals are still running. If a matching term is
+// thrown, the solver goes back to the state it was in when the
+// catch started and runs the handler
+#[derive(Debug, Clone)]
+pub struct CatchFrame {
+    pub master: Unifier,
+    pub choice_depth: usize,
+    pub pattern: Term,
+    pub handler: Query,
+    pub rest: Query,
+}
+
+// Goal marking the end of the innermost catch's goals
+const EXIT_CATCH: &str = "$exit_catch";
+
+// call('rel, args...) runs the goal rel(args...)
+const CALL: &str = "call";
 
 pub struct SolverStateOwner {
     pub master: Unifier,
     pub curr_query: Query,
     pub new_query: Query,
     pub fact_indx: usize,
-    pub choice_points: Vec<(Unifier, Query, usize)>,
+    pub clauses: Option<Rc<Vec<Rule>>>,
+    pub choice_points: Vec<ChoicePoint>,
+    pub catches: Vec<CatchFrame>,
 }
 
 pub fn new_solver_state<'a>(query: Query) -> SolverStateOwner {
@@ -20,7 +48,9 @@ pub fn new_solver_state<'a>(query: Query) -> SolverStateOwner {
         curr_query: query,
         new_query: Query { goals: vec![] },
         fact_indx: 0,
-        choice_points: vec![]
+        clauses: None,
+        choice_points: vec![],
+        catches: vec![],
     }
 }
 
@@ -29,19 +59,113 @@ pub struct SolverState<'a> {
     pub curr_query: &'a mut Query,
     pub new_query: &'a mut Query,
     pub fact_indx: &'a mut usize,
-    pub choice_points: &'a mut Vec<(Unifier, Query, usize)>,
+    pub clauses: &'a mut Option<Rc<Vec<Rule>>>,
+    pub choice_points: &'a mut Vec<ChoicePoint>,
+    pub catches: &'a mut Vec<CatchFrame>,
 }
 
-pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
+// Unwind to the innermost catch whose pattern matches the thrown
+// term, and continue with its handler. Gives back the term if
+// nothing catches it
+fn throw(ball: Term, master: &mut Unifier, new_query: &mut Query,
+         choice_points: &mut Vec<ChoicePoint>, catches: &mut Vec<CatchFrame>) -> Result<(), Term> {
+    while let Some(frame) = catches.pop() {
+        if let Some(unifier) = compute_most_gen_unifier(vec![(frame.pattern.clone(), ball.clone())]) {
+            let unifier = solve_unifier(&unifier);
+            choice_points.truncate(frame.choice_depth);
+            *master = frame.master;
+            for (k, v) in unifier.iter() {
+                master.insert(k.clone(), v.clone());
+            }
+            new_query.goals = frame.handler.goals.iter()
+                .chain(frame.rest.goals.iter())
+                .map(|goal| {
+                    let mut copy = goal.clone();
+                    copy.substitute_all(&unifier);
+                    copy
+                })
+                .collect();
+            return Ok(());
+        }
+    }
+    Err(ball)
+}
+
+// Solve a goal in a nested solver, for builtins which
+// run goals. Only its first solution is found
+fn call_goal(facts: &mut Rules, goal: Term) -> SolveResult {
+    let mut state = new_solver_state(Query { goals: vec![goal] });
+    solve(facts, SolverState {
+        master: &mut state.master,
+        curr_query: &mut state.curr_query,
+        new_query: &mut state.new_query,
+        fact_indx: &mut state.fact_indx,
+        clauses: &mut state.clauses,
+        choice_points: &mut state.choice_points,
+        catches: &mut state.catches,
+    })
+}
+
+// Relations defined by the program take the place
+// of builtins with the same name and arity
+fn user_defined(facts: &Rules, cterm: &CompoundTerm) -> bool {
+    facts.is_defined(&cterm.name, cterm.args.len())
+}
+
+// Goals for relations without any clauses are thrown as errors, rather
+// than failing like goals which no clause matches. Dynamic relations
+// can have their clauses added later, so they just fail
+fn existence_error(facts: &Rules, cterm: &CompoundTerm) -> Option<Term> {
+    let key = (cterm.name.clone(), cterm.args.len());
+    if facts.defined.contains(&key) || facts.dynamic.contains(&key) {
+        return None;
+    }
+    Some(Term::Dict(DictTerm::new(vec![
+        ("error".to_string(), Term::Atom("'existence_error".to_string())),
+        ("relation".to_string(), Term::Atom(format!("'{}", cterm.name))),
+        ("arity".to_string(), Term::Number((cterm.args.len() as i32).into())),
+    ], false)))
+}
+
+pub fn solve(facts: &mut Rules, resume_state: SolverState) -> SolveResult {
     let mut rng = thread_rng();
     let builtins = builtins();
+    let database_builtins = database_builtins();
+    let meta_builtins = meta_builtins();
     let fact_indx: &mut usize = resume_state.fact_indx;
     let master: &mut Unifier = resume_state.master;
     let curr_query: &mut Query = resume_state.curr_query;
     let new_query: &mut Query = resume_state.new_query;
+    // The clauses being tried for the current goal. Each goal
+    // sees the database as it was when the goal was first tried
+    // (the logical update view), so changes to the database
+    // don't affect goals which are already running
+    let clauses: &mut Option<Rc<Vec<Rule>>> = resume_state.clauses;
     // A stack of querys, states, and fact indices
-    let choice_points: &mut Vec<(Unifier, Query, usize)> = resume_state.choice_points;
+    let choice_points: &mut Vec<ChoicePoint> = resume_state.choice_points;
+    // The catches which the current goal is inside of
+    let catches: &mut Vec<CatchFrame> = resume_state.catches;
     loop {
+        let called = match curr_query.goals.first() {
+            Some(Term::Compound(cterm)) if cterm.name == CALL && !cterm.args.is_empty() => {
+                Some(relation_goal(&cterm.args[0], cterm.args[1..].to_vec(), CALL))
+            },
+            _ => None,
+        };
+        match called {
+            None => {},
+            Some(Ok(goal)) => {
+                curr_query.goals[0] = goal;
+                continue;
+            },
+            Some(Err(ball)) => {
+                throw(ball, master, new_query, choice_points, catches)?;
+                *fact_indx = 0;
+                *clauses = None;
+                *curr_query = new_query.clone();
+                continue;
+            },
+        }
         match curr_query.goals.get(0) {
             None => {
                 let unif = solve_unifier(&master);
@@ -52,33 +176,77 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                         filtered.insert(key.clone(), val.clone());
                     }
                 }
-                return Some(filtered)
+                return Ok(Some(filtered))
             },
             Some(goal) => {
                 let mut skip = false;
                 let mut nomatching = true;
+                let mut thrown: Option<Term> = None;
                 // Check for special goals here
                 if let Term::Compound(cterm) = goal {
-                    match builtins.get(&cterm.name) {
+                    let builtin_res: Option<BuiltinResult> = match (builtins.get(&cterm.name), database_builtins.get(&cterm.name)) {
+                        _ if cterm.name == "throw" => {
+                            match check_args(cterm, 1) {
+                                Ok(()) => Some(Err(cterm.args[0].clone())),
+                                Err(ball) => Some(Err(ball)),
+                            }
+                        },
+                        _ if cterm.name == EXIT_CATCH => {
+                            // The catch's goals succeeded, so it
+                            // doesn't catch anything after this
+                            catches.pop();
+                            Some(Ok(Some(Answer::last(Unifier::new()))))
+                        },
+                        (None, None) if !meta_builtins.contains_key(&cterm.name) => None,
+                        _ if user_defined(facts, cterm) => None,
+                        // fact_indx is where to start searching for a
+                        // solution, like it is for clauses
+                        (Some(builtin), _) => Some(builtin(cterm, *fact_indx)),
+                        (None, Some(builtin)) => Some(builtin(cterm, facts, *fact_indx)),
+                        // These run goals in a nested solver, like refute
+                        (None, None) => {
+                            let builtin = meta_builtins[&cterm.name];
+                            Some(builtin(cterm, &mut |goal| call_goal(facts, goal)))
+                        },
+                    };
+                    let builtin_res = match builtin_res {
+                        // Only the first time the goal is tried, since
+                        // the clauses it's retried with must exist
+                        None if clauses.is_none() => existence_error(facts, cterm).map(Err),
+                        builtin_res => builtin_res,
+                    };
+                    match builtin_res {
                         None => {},
-                        Some(builtin) => {
-                            let builtin_res =
-                                builtin(&cterm);
+                        Some(Err(ball)) => {
+                            thrown = Some(ball);
+                            skip = true;
+                            nomatching = false;
+                        },
+                        Some(Ok(builtin_res)) => {
                             match builtin_res {
                                 None => {
                                     // backtrack
                                     match choice_points.pop() {
-                                        None => return None,
+                                        None => return Ok(None),
                                         Some(choice_point) => {
                                             *master = choice_point.0;
                                             *curr_query = choice_point.1;
                                             *fact_indx = choice_point.2;
+                                            *clauses = Some(choice_point.3);
+                                            *catches = choice_point.4;
                                             continue;
                                         }
                                     }
                                 },
-                                Some(unifier) => {
-                                    let unifier = solve_unifier(&unifier);
+                                Some(answer) => {
+                                    // Builtins with more solutions leave a choice point
+                                    // which tries them again from where they left off
+                                    if let Some(next) = answer.next {
+                                        choice_points.push((
+                                            master.clone(), curr_query.clone(), next, facts.contents.clone(), catches.clone()
+                                        ));
+                                    }
+                                    let unifier = solve_unifier(&answer.unifier);
                                     for (k, v) in unifier.iter() {
                                         master.insert(k.clone(), v.clone());
                                     }
@@ -91,6 +259,7 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                                                      }).collect();
                                     *new_query = Query { goals: new_query_vec };
                                     *fact_indx = 0;
+                                    *clauses = None;
                                     skip = true;
                                     nomatching = false;
                                 },
@@ -98,32 +267,65 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                         }
                     }
                 }
-                if let Term::Refute(cterm) = goal {
+                if let Term::Refute(query) = goal {
                     skip = true;
-                    // Try to solve "goal"
-                    let query = Query {
-                        goals: vec![Term::Compound(cterm.clone())],
-                    };
-                    let mut state = new_solver_state(query);
+                    // Try to solve the refuted goals in a nested solver
+                    let mut state = new_solver_state(query.clone());
                     let solution = solve(facts, SolverState {
                         master: &mut state.master,
                         curr_query: &mut state.curr_query,
                         new_query: &mut state.new_query,
                         fact_indx: &mut state.fact_indx,
+                        clauses: &mut state.clauses,
                         choice_points: &mut state.choice_points,
+                        catches: &mut state.catches,
                     });
-                    if let Some(_) = solution {
-                        nomatching = true;
-                    } else {
-                        nomatching = false;
+                    match solution {
+                        Ok(Some(_)) => nomatching = true,
+                        Ok(None) => nomatching = false,
+                        Err(ball) => {
+                            thrown = Some(ball);
+                            nomatching = false;
+                        },
                     }
                     new_query.goals = curr_query.goals[1..].iter()
                         .cloned().collect();
+                    *fact_indx = 0;
+                    *clauses = None;
+                }
+                if let Term::Catch(cterm) = goal {
+                    skip = true;
+                    nomatching = false;
+                    catches.push(CatchFrame {
+                        master: master.clone(),
+                        choice_depth: choice_points.len(),
+                        pattern: (*cterm.pattern).clone(),
+                        handler: cterm.handler.clone(),
+                        rest: Query { goals: curr_query.goals[1..].to_vec() },
+                    });
+                    let mut new_query_vec = cterm.goals.goals.clone();
+                    new_query_vec.push(Term::Compound(CompoundTerm {
+                        name: EXIT_CATCH.to_string(),
+                        args: vec![],
+                    }));
+                    new_query_vec.extend(curr_query.goals[1..].iter().cloned());
+                    *new_query = Query { goals: new_query_vec };
+                    *fact_indx = 0;
+                    *clauses = None;
+                }
+                if let Some(ball) = thrown {
+                    throw(ball, master, new_query, choice_points, catches)?;
+                    *fact_indx = 0;
+                    *clauses = None;
                 }
                 // Find a clause that matches
                 // the current goal
                 if !skip {
-                    for clause in facts.contents[*fact_indx..].iter() {
+                    let goal_clauses: Rc<Vec<Rule>> = match clauses {
+                        Some(goal_clauses) => goal_clauses.clone(),
+                        None => facts.contents.clone(),
+                    };
+                    for clause in goal_clauses[*fact_indx..].iter() {
                         // println!("unify: {:?},\n{:?}", &goal, &clause.gives);
                         let unification =
                             compute_most_gen_unifier(vec![(goal.clone(), Term::Compound(clause.gives.clone()))]);
@@ -135,7 +337,7 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                                 nomatching = false;
                                 // choose to take it
                                 choice_points.push((
-                                    master.clone(), curr_query.clone(), *fact_indx + 1
+                                    master.clone(), curr_query.clone(), *fact_indx + 1, goal_clauses.clone(), catches.clone()
                                 ));
                                 let new_frame_id = rng.next_u32();
                                 for (_k, v) in unifier.iter_mut() {
@@ -166,6 +368,7 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                                                  }).collect());
                                 *new_query = Query { goals: new_query_vec };
                                 *fact_indx = 0;
+                                *clauses = None;
                                 break;
                             }
                         }
@@ -173,11 +376,13 @@ pub fn solve(facts: &Rules, resume_state: SolverState) -> Option<Unifier> {
                 }
                 if nomatching {
                     match choice_points.pop() {
-                        None => return None,
+                        None => return Ok(None),
                         Some(choice_point) => {
                             *master = choice_point.0;
                             *curr_query = choice_point.1;
                             *fact_indx = choice_point.2;
+                            *clauses = Some(choice_point.3);
+                            *catches = choice_point.4;
                             continue;
                         }
                     }
@@ -0,0 +1,385 @@
+use crate::solver::*;
+
+use crate::solver::builtins::{check_args, error_term, once, succeed_if, BuiltinResult};
+use crate::solver::parse::parse_constant;
+use crate::solver::unify::compute_most_gen_unifier;
+use crate::ast::{ExpressionNode, ExpressionContents};
+use crate::ast::parse::ParseNode;
+use crate::{BevelParser, Rule};
+
+use pest::Parser;
+use rand::prelude::RngCore;
+use rand::thread_rng;
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write as IoWrite};
+use std::path::Path;
+
+// Files opened by the program. They're given to the program as
+// $stream(n) terms, and the standard streams are the atoms
+// 'user_input, 'user_output and 'user_error
+enum Stream {
+    Input(BufReader<File>),
+    Output(File),
+}
+
+struct Streams {
+    next_id: i32,
+    open: HashMap<i32, Stream>,
+}
+
+thread_local! {
+    static STREAMS: RefCell<Streams> = RefCell::new(Streams {
+        next_id: 0,
+        open: HashMap::new(),
+    });
+}
+
+const STREAM: &str = "$stream";
+
+// The stream a term refers to
+#[derive(Clone, Copy)]
+enum Handle {
+    UserInput,
+    UserOutput,
+    UserError,
+    File(i32),
+}
+
+fn stream_arg(term: &Term, relation: &str) -> Result<Handle, Term> {
+    match term {
+        Term::Atom(s) if s == "'user_input" => Ok(Handle::UserInput),
+        Term::Atom(s) if s == "'user_output" => Ok(Handle::UserOutput),
+        Term::Atom(s) if s == "'user_error" => Ok(Handle::UserError),
+        Term::Compound(cterm) if cterm.name == STREAM && cterm.args.len() == 1 => {
+            match &cterm.args[0] {
+                Term::Number(n) if n.is_integer() => Ok(Handle::File(n.to_integer())),
+                _ => Err(error_term("domain_error", relation)),
+            }
+        },
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("domain_error", relation)),
+    }
+}
+
+// Text like a path, given as an atom or string
+pub fn text_arg(term: &Term, relation: &str) -> Result<String, Term> {
+    match term {
+        Term::Atom(s) => Ok(s[1..].to_string()),
+        Term::Unknown(_) => Err(error_term("instantiation_error", relation)),
+        _ => Err(error_term("type_error", relation)),
+    }
+}
+
+fn text_term(text: &str) -> Term {
+    Term::Atom(format!("'{}", text))
+}
+
+fn end_of_file() -> Term {
+    Term::Atom("'end_of_file".to_string())
+}
+
+// I/O errors are thrown like errors from any other builtin
+fn io_error(e: io::Error, relation: &str) -> Term {
+    let kind = match e.kind() {
+        io::ErrorKind::NotFound => "existence_error",
+        io::ErrorKind::PermissionDenied => "permission_error",
+        _ => "io_error",
+    };
+    error_term(kind, relation)
+}
+
+// Read a line without its line ending, or nothing
+// if the stream has already been read to the end
+fn read_line_from(handle: Handle, relation: &str) -> Result<Option<String>, Term> {
+    let mut line = String::new();
+    let read = match handle {
+        Handle::UserInput => io::stdin().lock().read_line(&mut line),
+        Handle::File(id) => STREAMS.with(|streams| {
+            match streams.borrow_mut().open.get_mut(&id) {
+                Some(Stream::Input(reader)) => Ok(reader.read_line(&mut line)),
+                Some(Stream::Output(_)) => Err(error_term("permission_error", relation)),
+                None => Err(error_term("existence_error", relation)),
+            }
+        })?,
+        _ => return Err(error_term("permission_error", relation)),
+    }.map_err(|e| io_error(e, relation))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn write_to(handle: Handle, text: &str, relation: &str) -> Result<(), Term> {
+    match handle {
+        Handle::UserOutput => {
+            print!("{}", text);
+            io::stdout().flush()
+        },
+        Handle::UserError => {
+            eprint!("{}", text);
+            Ok(())
+        },
+        Handle::File(id) => STREAMS.with(|streams| {
+            match streams.borrow_mut().open.get_mut(&id) {
+                Some(Stream::Output(file)) => Ok(file.write_all(text.as_bytes())),
+                Some(Stream::Input(_)) => Err(error_term("permission_error", relation)),
+                None => Err(error_term("existence_error", relation)),
+            }
+        })?,
+        Handle::UserInput => return Err(error_term("permission_error", relation)),
+    }.map_err(|e| io_error(e, relation))
+}
+
+// Relations which can read from or write to a stream take it as
+// their first argument, or use a standard stream if it's left out
+fn optional_stream(cterm: &CompoundTerm, arity: usize, default: Handle)
+        -> Result<(Handle, &[Term]), Term> {
+    if cterm.args.len() == arity {
+        Ok((default, &cterm.args[..]))
+    } else if cterm.args.len() == arity + 1 {
+        Ok((stream_arg(&cterm.args[0], &cterm.name)?, &cterm.args[1..]))
+    } else {
+        Err(error_term("type_error", &cterm.name))
+    }
+}
+
+// A term as text, without the quotes on atoms
+pub fn plain_text(term: &Term) -> String {
+    match term {
+        Term::Atom(s) => s[1..].to_string(),
+        _ => term.to_string(),
+    }
+}
+
+// A term as it would be written in a program, so that it
+// can be read back with read_term
+pub fn readable_text(term: &Term) -> String {
+    let join = |terms: &[Term]| terms.iter()
+        .map(readable_text)
+        .collect::<Vec<_>>()
+        .join(", ");
+    match term {
+        Term::Atom(s) => {
+            let name = &s[1..];
+            let mut chars = name.chars();
+            let ident = chars.next().is_some_and(char::is_alphabetic)
+                && chars.all(|c| c.is_alphanumeric() || c == '_');
+            if ident {
+                s.clone()
+            } else {
+                let escaped = name.replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t");
+                format!("\"{}\"", escaped)
+            }
+        },
+        Term::List(ListTerm { front, tail: ListTail::End }) => format!("[{}]", join(front)),
+        Term::Compound(cterm) if cterm.name == TUPLE => format!("({})", join(&cterm.args)),
+        Term::Dict(dterm) => {
+            let entries = dterm.entries.iter()
+                .map(|(key, val)| format!("{}: {}", key, readable_text(val)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", entries.join(", "))
+        },
+        _ => term.to_string(),
+    }
+}
+
+// Read terms are constant expressions, where unknowns with the
+// same name are the same unknown
+fn parse_term(text: &str, relation: &str) -> Result<Term, Term> {
+    let syntax_error = || error_term("syntax_error", relation);
+    let text = text.trim();
+    let pair = BevelParser::parse(Rule::expr, text)
+        .map_err(|_| syntax_error())?
+        .next()
+        .unwrap();
+    if pair.as_str().len() != text.len() {
+        return Err(syntax_error());
+    }
+    let expr = ExpressionNode::parse(pair, text);
+    expr_term(&expr, thread_rng().next_u32()).ok_or_else(syntax_error)
+}
+
+fn expr_term(expr: &ExpressionNode, frame_id: u32) -> Option<Term> {
+    let terms = |vals: &[ExpressionNode]| vals.iter()
+        .map(|val| expr_term(val, frame_id))
+        .collect::<Option<Vec<_>>>();
+    match &expr.contents {
+        ExpressionContents::Const(cnode) => Some(parse_constant(cnode, frame_id)),
+        ExpressionContents::List { vals } => Some(Term::List(ListTerm {
+            front: terms(vals)?,
+            tail: ListTail::End,
+        })),
+        ExpressionContents::ConsList { vals } => {
+            let mut front = terms(&vals[..vals.len() - 1])?;
+            match expr_term(&vals[vals.len() - 1], frame_id)? {
+                Term::List(lterm) => {
+                    front.extend(lterm.front);
+                    Some(Term::List(ListTerm { front, tail: lterm.tail }))
+                },
+                Term::Unknown(ucontents) => Some(Term::List(ListTerm {
+                    front,
+                    tail: ListTail::Unknown(ucontents),
+                })),
+                _ => None,
+            }
+        },
+        ExpressionContents::Tuple { vals } => Some(Term::Compound(CompoundTerm {
+            name: TUPLE.to_string(),
+            args: terms(vals)?,
+        })),
+        ExpressionContents::Dict { entries } => {
+            let entries = entries.iter()
+                .map(|(key, val)| Some((key.clone(), expr_term(val, frame_id)?)))
+                .collect::<Option<Vec<_>>>()?;
+            let dict = DictTerm::new(entries, false);
+            // Like in a program, each key can only be given once
+            if dict.entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return None;
+            }
+            Some(Term::Dict(dict))
+        },
+        _ => None,
+    }
+}
+
+// open(path, mode) -> stream, where mode is 'read, 'write or 'append
+pub fn builtin_open(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 3)?;
+    let path = text_arg(&cterm.args[0], "open")?;
+    let stream = match &cterm.args[1] {
+        Term::Atom(mode) if mode == "'read" => {
+            File::open(&path).map(|file| Stream::Input(BufReader::new(file)))
+        },
+        Term::Atom(mode) if mode == "'write" => File::create(&path).map(Stream::Output),
+        Term::Atom(mode) if mode == "'append" => {
+            OpenOptions::new().append(true).create(true).open(&path).map(Stream::Output)
+        },
+        Term::Unknown(_) => return Err(error_term("instantiation_error", "open")),
+        _ => return Err(error_term("domain_error", "open")),
+    }.map_err(|e| io_error(e, "open"))?;
+    let id = STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        let id = streams.next_id;
+        streams.next_id += 1;
+        streams.open.insert(id, stream);
+        id
+    });
+    let handle = Term::Compound(CompoundTerm {
+        name: STREAM.to_string(),
+        args: vec![Term::Number(id.into())],
+    });
+    once(compute_most_gen_unifier(vec![(cterm.args[2].clone(), handle)]))
+}
+
+pub fn builtin_close(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    match stream_arg(&cterm.args[0], "close")? {
+        Handle::File(id) => {
+            let closed = STREAMS.with(|streams| streams.borrow_mut().open.remove(&id));
+            match closed {
+                Some(_) => succeed_if(true),
+                None => Err(error_term("existence_error", "close")),
+            }
+        },
+        // The standard streams stay open
+        _ => succeed_if(true),
+    }
+}
+
+// The next line of a stream, or 'end_of_file
+pub fn builtin_read_line(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    let (handle, args) = optional_stream(cterm, 1, Handle::UserInput)?;
+    let line = match read_line_from(handle, "read_line")? {
+        Some(line) => text_term(&line),
+        None => end_of_file(),
+    };
+    once(compute_most_gen_unifier(vec![(args[0].clone(), line)]))
+}
+
+// The term on the next line of a stream which isn't blank, or 'end_of_file
+pub fn builtin_read_term(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    let (handle, args) = optional_stream(cterm, 1, Handle::UserInput)?;
+    let term = loop {
+        match read_line_from(handle, "read_term")? {
+            Some(line) if line.trim().is_empty() => continue,
+            Some(line) => break parse_term(&line, "read_term")?,
+            None => break end_of_file(),
+        }
+    };
+    once(compute_most_gen_unifier(vec![(args[0].clone(), term)]))
+}
+
+pub fn builtin_write(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    let (handle, args) = optional_stream(cterm, 1, Handle::UserOutput)?;
+    write_to(handle, &plain_text(&args[0]), "write")?;
+    succeed_if(true)
+}
+
+pub fn builtin_print(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    let (handle, args) = optional_stream(cterm, 1, Handle::UserOutput)?;
+    write_to(handle, &readable_text(&args[0]), "print")?;
+    succeed_if(true)
+}
+
+pub fn builtin_nl(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    let (handle, _) = optional_stream(cterm, 0, Handle::UserOutput)?;
+    write_to(handle, "\n", "nl")?;
+    succeed_if(true)
+}
+
+pub fn builtin_read_file_to_lines(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 2)?;
+    let path = text_arg(&cterm.args[0], "read_file_to_lines")?;
+    let contents = fs::read_to_string(&path).map_err(|e| io_error(e, "read_file_to_lines"))?;
+    let lines = Term::List(ListTerm {
+        front: contents.lines().map(text_term).collect(),
+        tail: ListTail::End,
+    });
+    once(compute_most_gen_unifier(vec![(cterm.args[1].clone(), lines)]))
+}
+
+pub fn builtin_exists_file(cterm: &CompoundTerm, _start: usize) -> BuiltinResult {
+    check_args(cterm, 1)?;
+    let path = text_arg(&cterm.args[0], "exists_file")?;
+    succeed_if(Path::new(&path).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_terms() {
+        let term = parse_term("[1, 'a, \"b c\", (x : t)]", "read_term").unwrap();
+        assert_eq!(readable_text(&term).split(", (").next(), Some("[1, 'a, \"b c\""));
+        let pair = parse_term("(1, {k: [2]})", "read_term").unwrap();
+        assert_eq!(readable_text(&pair), "(1, {k: [2]})");
+        assert_eq!(parse_term("f(1)", "read_term"), Err(error_term("syntax_error", "read_term")));
+        assert_eq!(parse_term("1 2", "read_term"), Err(error_term("syntax_error", "read_term")));
+        assert_eq!(parse_term("{k: 1, k: 2}", "read_term"), Err(error_term("syntax_error", "read_term")));
+    }
+
+    #[test]
+    fn test_wrong_argument_counts() {
+        let write = CompoundTerm {
+            name: "write".to_string(),
+            args: vec![Term::Number(1.into()), Term::Number(2.into()), Term::Number(3.into())],
+        };
+        assert_eq!(builtin_write(&write, 0).err(), Some(error_term("type_error", "write")));
+        let close = CompoundTerm {
+            name: "close".to_string(),
+            args: vec![Term::Atom("'a".to_string()), Term::Atom("'b".to_string())],
+        };
+        assert_eq!(builtin_close(&close, 0).err(), Some(error_term("type_error", "close")));
+    }
+}
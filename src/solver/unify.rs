@@ -44,8 +44,8 @@ pub fn compute_most_gen_unifier(goal: Goal) -> Option<Unifier> {
         goal.into_iter()
         .map(|(t1, t2)| (false, t1, t2))
         .collect();
-    loop {
-        let equality = equations.pop_front()?;
+    // Running out of equations means everything unified
+    while let Some(equality) = equations.pop_front() {
         if equality.0 {
             equations.push_back(equality);
             continue;
@@ -118,7 +118,7 @@ pub fn compute_most_gen_unifier(goal: Goal) -> Option<Unifier> {
                                 .map(|x| x.clone()).collect();
                             let as_lterm = ListTerm {
                                 front: rest_vec,
-                                tail: ListTail::End,
+                                tail: lterm2.tail.clone(),
                             };
                             equations.push_back((false, Unknown(s.clone()), List(as_lterm)));
                         },
@@ -139,7 +139,7 @@ pub fn compute_most_gen_unifier(goal: Goal) -> Option<Unifier> {
                                 .map(|x| x.clone()).collect();
                             let as_lterm = ListTerm {
                                 front: rest_vec,
-                                tail: ListTail::End,
+                                tail: lterm.tail.clone(),
                             };
                             equations.push_back((false, Unknown(s.clone()), List(as_lterm)));
                         },
@@ -148,6 +148,26 @@ pub fn compute_most_gen_unifier(goal: Goal) -> Option<Unifier> {
                 continue;
             },
             (List(_), _) => { return None; },
+            (Dict(dterm), Dict(dterm2)) => {
+                // Keys missing from one side are only
+                // allowed if that side is partial
+                for (key, val) in dterm.entries.iter() {
+                    match dterm2.get(key) {
+                        Some(val2) => {
+                            equations.push_back((false, val.clone(), val2.clone()));
+                        },
+                        None if dterm2.partial => {},
+                        None => { return None; },
+                    }
+                }
+                if !dterm.partial {
+                    for (key, _) in dterm2.entries.iter() {
+                        dterm.get(key)?;
+                    }
+                }
+                continue;
+            },
+            (Dict(_), _) => { return None; },
             (Compound(cterm), Compound(cterm2)) => {
                 if cterm.name != cterm2.name {
                     return None;
@@ -161,7 +181,7 @@ pub fn compute_most_gen_unifier(goal: Goal) -> Option<Unifier> {
                 continue;
             },
             (Compound(_), _) => { return None; },
-            (Refute(_), _) => unreachable!(),
+            (Refute(_), _) | (Catch(_), _) => unreachable!(),
         }
         let mut done = true;
         // Since we haven't 'continue'd, that
@@ -213,11 +233,30 @@ impl Term {
                     item.simple_substitution(unknown, subs);
                 }
             },
-            Compound(cterm) | Refute(cterm) => {
+            Dict(dterm) => {
+                for (_, item) in dterm.entries.iter_mut() {
+                    item.simple_substitution(unknown, subs);
+                }
+            },
+            Compound(cterm) => {
                 for item in cterm.args.iter_mut() {
                     item.simple_substitution(unknown, subs);
                 }
             },
+            Refute(query) => {
+                for item in query.goals.iter_mut() {
+                    item.simple_substitution(unknown, subs);
+                }
+            },
+            Catch(cterm) => {
+                for item in cterm.goals.goals.iter_mut() {
+                    item.simple_substitution(unknown, subs);
+                }
+                cterm.pattern.simple_substitution(unknown, subs);
+                for item in cterm.handler.goals.iter_mut() {
+                    item.simple_substitution(unknown, subs);
+                }
+            },
         }
         Some(())
     }
@@ -14,48 +14,36 @@ impl<'p> Span<'p> {
     }
 
     pub fn line_no(&self) -> usize {
-        let mut curr = 1;
-        let mut iter = self.input[..=self.start].chars();
-        while let Some(c) = iter.next_back() {
-            if c == '\n' {
-                curr += 1;
-            }
-        }
-
-        curr
+        // Up to and including the character at the start
+        let end = self.input[self.start..].chars().next()
+            .map_or(self.start, |c| self.start + c.len_utf8());
+        self.input[..end].matches('\n').count() + 1
     }
 
     pub fn from_line_begin(&self) -> Span<'p> {
-        let mut curr = self.start;
-        let mut iter = self.input[..=self.start].chars();
-        while let Some(c) = iter.next_back() {
-            if c == '\n' {
-                break;
-            }
-            curr -= 1;
-        }
+        // The first line has no newline before it
+        let start = self.input[..self.start].rfind('\n').map_or(0, |i| i + 1);
         
         Span {
             input: self.input,
-            start: curr + 1,
+            start,
             end: self.end,
         }
     }
     
     pub fn to_line_end(&self) -> Span<'p> {
-        let mut curr = self.start;
-        let mut iter = self.input[..=self.start].chars();
-        while let Some(c) = iter.next() {
+        let mut curr = self.end;
+        for c in self.input[self.end..].chars() {
             if c == '\n' {
                 break;
             }
-            curr -= 1;
+            curr += c.len_utf8();
         }
         
         Span {
             input: self.input,
-            start: curr + 1,
-            end: self.end,
+            start: self.start,
+            end: curr,
         }
     }
     
@@ -74,7 +62,7 @@ impl<'p> Span<'p> {
             } else {
                 curr += 1;
             }
-            indx += 1;
+            indx += c.len_utf8();
         }
         unreachable!()
     }
@@ -87,3 +75,48 @@ pub fn new_span<'p>(pspan: pest::Span<'p>, source: &'p str) -> Span<'p> {
         end: pspan.end(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_line_end() {
+        let input = "\nf(x) ~ y;\ng(x) ~ 1;\n";
+        let span = Span { input, start: 8, end: 9 };
+        let full = span.to_line_end();
+        assert_eq!(full.as_str(), "y;");
+        assert_eq!(full.start, 8);
+
+        // The last line has no newline after it
+        let input = "\nf(x) ~ é;";
+        let span = Span { input, start: 8, end: 10 };
+        assert_eq!(span.to_line_end().as_str(), "é;");
+    }
+
+    #[test]
+    fn test_from_line_begin() {
+        // The first line has no newline before it
+        let input = "f(x) ~ y;\n";
+        let span = Span { input, start: 7, end: 8 };
+        let full = span.from_line_begin();
+        assert_eq!(full.as_str(), "f(x) ~ y");
+
+        let input = "\nf(é) ~ y;\n";
+        let span = Span { input, start: 9, end: 10 };
+        assert_eq!(span.from_line_begin().as_str(), "f(é) ~ y");
+    }
+
+    #[test]
+    fn test_non_ascii_positions() {
+        let input = "\nf(é) ~ é;\ng(x) ~ x;";
+        let span = Span { input, start: 9, end: 11 };
+        assert_eq!(span.as_str(), "é");
+        assert_eq!(span.line_no(), 2);
+        let full = span.from_line_begin().to_line_end();
+        assert_eq!(full.distance_from_start(span.start), 7);
+        assert_eq!(full.distance_from_start(span.end), 8);
+        let span = Span { input, start: 13, end: 14 };
+        assert_eq!(span.line_no(), 3);
+    }
+}
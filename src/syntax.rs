@@ -0,0 +1,411 @@
+use annotate_snippets::snippet::*;
+use pest::Parser;
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pairs;
+
+use crate::codes;
+use crate::error::{code_snippet, span_slice, Error};
+use crate::span::Span;
+use crate::{BevelParser, Rule};
+
+// Parse a program, or find every syntax error in it. After an error,
+// parsing starts again at the next relation, and inside of blocks at
+// the next statement, so one mistake is only reported once
+pub fn parse_source<'i>(input: &'i str, source: &str) -> Result<Pairs<'i, Rule>, Vec<Error>> {
+    BevelParser::parse(Rule::program, input).map_err(|e| {
+        let mut errs = vec![];
+        for (start, end) in split_items(input) {
+            errs.append(&mut check_item(input, start, end, source));
+        }
+        if errs.is_empty() {
+            // The pieces are fine on their own, so report the error as it is
+            let (pos, positives) = error_position(&e);
+            errs.push(expected_error(input, pos, &positives, source));
+        }
+        errs
+    })
+}
+
+// The characters which aren't in strings or comments, with their offsets.
+// Strings are kept as their quotes, so they still count as something
+fn code_chars(text: &str) -> Vec<(usize, char)> {
+    let mut res = vec![];
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '#' => {
+                for (j, c) in chars.by_ref() {
+                    if c == '\n' {
+                        res.push((j, c));
+                        break;
+                    }
+                }
+            },
+            '"' => {
+                res.push((i, c));
+                while let Some((j, c)) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == '"' || c == '\n' {
+                        res.push((j, c));
+                        break;
+                    }
+                }
+            },
+            _ => res.push((i, c)),
+        }
+    }
+    res
+}
+
+// Items end at a ;, or at a line which starts a new one at the
+// top level, in case the ; after the last one was left out
+fn split_items(input: &str) -> Vec<(usize, usize)> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut has_content = false;
+    let mut line_start = true;
+    for (i, c) in code_chars(input) {
+        if line_start && depth == 0 && has_content && !c.is_whitespace() && c != '{' && c != '}' {
+            items.push((start, i));
+            start = i;
+            has_content = false;
+        }
+        line_start = c == '\n';
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ';' => {
+                items.push((start, i + 1));
+                start = i + 1;
+                has_content = false;
+                depth = 0;
+                continue;
+            },
+            _ => {},
+        }
+        if !c.is_whitespace() {
+            has_content = true;
+        }
+    }
+    if has_content {
+        items.push((start, input.len()));
+    }
+    items
+}
+
+fn check_item(input: &str, start: usize, end: usize, source: &str) -> Vec<Error> {
+    let text = &input[start..end];
+    let e = match BevelParser::parse(Rule::item, text) {
+        Ok(_) => return vec![],
+        Err(e) => e,
+    };
+    let mut errs = vec![];
+    let mut unclosed_block = None;
+    if let Some(open) = block_start(text) {
+        let (body_end, closed) = match block_end(text, open) {
+            Some(close) => (close, true),
+            None => (text.trim_end().trim_end_matches(';').len(), false),
+        };
+        for (line_start, line_end) in statement_lines(text, open + 1, body_end) {
+            errs.extend(check_statement(input, start + line_start, start + line_end, source));
+        }
+        if !closed {
+            unclosed_block = Some(start + open);
+        }
+    }
+    if let Some(open) = unclosed_block {
+        let span = char_span(input, open);
+        errs.push(syntax_error(&span, "unclosed `{`", "this `{` is never closed".to_string(), source));
+    }
+    if !errs.is_empty() {
+        return errs;
+    }
+    if let Some(err) = delimiter_error(input, start, end, source) {
+        return vec![err];
+    }
+    // Check for a missing ; by trying again with one
+    let trimmed = text.trim_end();
+    if !trimmed.ends_with(';') && BevelParser::parse(Rule::item, &format!("{};", trimmed)).is_ok() {
+        let kind = match trimmed.split_whitespace().next() {
+            Some("type") | Some("mode") | Some("dynamic") | Some("export") | Some("table") => "declaration",
+            _ => "relation",
+        };
+        let span = char_span(input, start + last_char(trimmed));
+        return vec![syntax_error(&span, &format!("expected `;` after {}", kind), "expected `;` after this".to_string(), source)];
+    }
+    let (pos, positives) = error_position(&e);
+    vec![expected_error(input, start + pos, &positives, source)]
+}
+
+fn check_statement(input: &str, start: usize, end: usize, source: &str) -> Option<Error> {
+    match BevelParser::parse(Rule::statement_line, &input[start..end]) {
+        Ok(_) => None,
+        Err(e) => delimiter_error(input, start, end, source).or_else(|| {
+            let (pos, positives) = error_position(&e);
+            Some(expected_error(input, start + pos, &positives, source))
+        }),
+    }
+}
+
+// The { which starts the block of a relation, which is the first
+// one outside of its parameters
+fn block_start(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in code_chars(text) {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            '{' if depth == 0 => return Some(i),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn block_end(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in code_chars(&text[open..]) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+// Each statement in a block is on its own line, unless it has
+// a block of its own, like refute and catch
+fn statement_lines(text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut lines = vec![];
+    let mut line_start = start;
+    let mut depth = 0usize;
+    let mut has_content = false;
+    for (i, c) in code_chars(&text[start..end]) {
+        let i = start + i;
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '\n' if depth == 0 => {
+                if has_content {
+                    lines.push((line_start, i + 1));
+                }
+                line_start = i + 1;
+                has_content = false;
+                continue;
+            },
+            _ => {},
+        }
+        if !c.is_whitespace() {
+            has_content = true;
+        }
+    }
+    if has_content {
+        lines.push((line_start, end));
+    }
+    lines
+}
+
+// Brackets which are never closed, or which close the wrong thing
+fn delimiter_error(input: &str, start: usize, end: usize, source: &str) -> Option<Error> {
+    let mut open: Vec<(usize, char)> = vec![];
+    for (i, c) in code_chars(&input[start..end]) {
+        let i = start + i;
+        let opener = match c {
+            '(' | '[' | '{' => {
+                open.push((i, c));
+                continue;
+            },
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => continue,
+        };
+        match open.pop() {
+            Some((_, o)) if o == opener => {},
+            Some((j, o)) => {
+                let span = char_span(input, i);
+                let label = format!("this doesn't match the `{}` on line {}", o, char_span(input, j).line_no());
+                return Some(syntax_error(&span, &format!("mismatched closing `{}`", c), label, source));
+            },
+            None => {
+                let span = char_span(input, i);
+                return Some(syntax_error(&span, &format!("unexpected closing `{}`", c), "this doesn't close anything".to_string(), source));
+            },
+        }
+    }
+    open.pop().map(|(i, c)| {
+        let span = char_span(input, i);
+        syntax_error(&span, &format!("unclosed `{}`", c), format!("this `{}` is never closed", c), source)
+    })
+}
+
+fn error_position(e: &pest::error::Error<Rule>) -> (usize, Vec<Rule>) {
+    let pos = match e.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    };
+    let positives = match &e.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+        ErrorVariant::CustomError { .. } => vec![],
+    };
+    (pos, positives)
+}
+
+// What the rules which were expected mean, in words
+fn describe(rule: Rule) -> Option<&'static str> {
+    match rule {
+        Rule::EOI => Some("the end of the program"),
+        Rule::relation_decl => Some("a relation"),
+        Rule::type_kw | Rule::mode_kw | Rule::dynamic_kw | Rule::export_kw | Rule::table_kw => Some("a declaration"),
+        Rule::ident => Some("a name"),
+        Rule::pattern | Rule::pattern_list | Rule::empty_pat | Rule::list_pattern
+            | Rule::conslist_pattern | Rule::tuple_pattern | Rule::dict_pattern => Some("a pattern"),
+        Rule::expr | Rule::arith_expr | Rule::expr_list | Rule::mul_expr | Rule::num_literal
+            | Rule::atom | Rule::string | Rule::relation_call | Rule::list_expr | Rule::conslist_expr
+            | Rule::tuple_expr | Rule::dict_expr | Rule::access_expr => Some("an expression"),
+        Rule::add | Rule::subtract | Rule::multiply | Rule::divide | Rule::modulo => Some("an operator"),
+        Rule::gt | Rule::lt | Rule::leq | Rule::geq | Rule::eq | Rule::neq => Some("a comparison"),
+        Rule::type_expr | Rule::list_type | Rule::tuple_type => Some("a type"),
+        Rule::mode => Some("`in`, `out` or `any`"),
+        Rule::arity => Some("a number of arguments"),
+        Rule::block => Some("a block"),
+        Rule::param | Rule::param_list => Some("a parameter"),
+        Rule::dict_expr_entry | Rule::dict_pattern_entry => Some("a dict entry"),
+        Rule::in_kw => Some("`in`"),
+        Rule::with_kw => Some("`with`"),
+        _ => None,
+    }
+}
+
+fn describe_expected(positives: &[Rule]) -> Option<String> {
+    // Anything could start a statement, so don't list every kind
+    if positives.contains(&Rule::relate) || positives.contains(&Rule::mul_relate) {
+        return Some("a statement".to_string());
+    }
+    let mut names: Vec<&str> = vec![];
+    // A literal could start a pattern too
+    let in_pattern = positives.iter().any(|rule| describe(*rule) == Some("a pattern"));
+    for rule in positives.iter() {
+        let name = match describe(*rule) {
+            Some("an expression") if in_pattern => "a pattern",
+            Some(name) => name,
+            None => continue,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    // Names are expressions and patterns too
+    if names.contains(&"an expression") || names.contains(&"a pattern") {
+        names.retain(|name| *name != "a name");
+    }
+    match names.split_last() {
+        None => None,
+        Some((last, [])) => Some(last.to_string()),
+        Some((last, rest)) => Some(format!("{} or {}", rest.join(", "), last)),
+    }
+}
+
+fn expected_error(input: &str, pos: usize, positives: &[Rule], source: &str) -> Error {
+    let title = match describe_expected(positives) {
+        Some(expected) => format!("expected {}", expected),
+        None => "invalid syntax".to_string(),
+    };
+    match input[pos..].chars().next().filter(|c| *c != '\n' && *c != '\r') {
+        Some(c) => {
+            // Point at the whole word which was found
+            let len = if c.is_alphanumeric() || c == '_' {
+                input[pos..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(input.len() - pos)
+            } else {
+                c.len_utf8()
+            };
+            let span = Span { input, start: pos, end: pos + len };
+            let label = format!("found `{}`", span.as_str());
+            syntax_error(&span, &title, label, source)
+        },
+        None => {
+            // At the end of a line, point at what came before it
+            let before = input[..pos].trim_end();
+            let span = char_span(input, last_char(before));
+            syntax_error(&span, &title, format!("{} after this", title), source)
+        },
+    }
+}
+
+// Where the last character of some text starts
+fn last_char(text: &str) -> usize {
+    text.char_indices().last().map_or(0, |(i, _)| i)
+}
+
+fn char_span(input: &str, pos: usize) -> Span<'_> {
+    let len = input[pos..].chars().next().map_or(0, |c| c.len_utf8());
+    Span {
+        input,
+        start: pos,
+        end: pos + len,
+    }
+}
+
+fn syntax_error(span: &Span, title: &str, label: String, source: &str) -> Error {
+    Error::Code(code_snippet(
+        title.to_string(), codes::SYNTAX_ERROR, AnnotationType::Error,
+        vec![],
+        vec![span_slice(span, label, AnnotationType::Error, source)],
+    ).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_errors(program_input: &str) -> Vec<String> {
+        match parse_source(program_input, "test") {
+            Ok(_) => vec![],
+            Err(errs) => errs.iter().map(|err| format!("{}", err)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_recovery() {
+        let errs = syntax_errors(
+r#"
+fib(0) ~ 1
+fib(1) ~ 1;
+sum(l) {
+    n ~ length(l) +
+    x ~ (n, 1
+    relate n
+};
+pair(x) ~ (x, x);
+broken( ~ 2;
+type t = ;
+last(x) {
+    relate x
+}
+"#);
+        assert_eq!(errs.len(), 6);
+        assert!(errs[0].contains("expected `;` after relation"));
+        assert!(errs[1].contains("expected an expression"));
+        assert!(errs[2].contains("unclosed `(`"));
+        assert!(errs[3].contains("unclosed `(`"));
+        assert!(errs[4].contains("expected a type"));
+        assert!(errs[5].contains("expected `;` after relation"));
+        assert!(syntax_errors("\nf(x) ~ x;\n").is_empty());
+
+        // Errors after text which isn't ASCII
+        let errs = syntax_errors("f(x) ~ café\ng(y) ~ 1;\n");
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("expected `;` after relation"));
+        let errs = syntax_errors("\nf(é) {\n    é <\n};\n");
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("expected an expression"));
+    }
+}
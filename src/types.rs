@@ -0,0 +1,655 @@
+use annotate_snippets::snippet::*;
+
+use crate::ast::*;
+use crate::codes;
+use crate::error::{code_snippet, span_slice, Error, union_spans};
+use crate::span::Span;
+
+use std::collections::HashMap;
+use std::fmt;
+
+// Static types for bevel terms. Type annotations are optional,
+// so anything which isn't annotated or inferred is Any, and
+// only types which can't possibly overlap are reported
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Number,
+    Atom,
+    Literal(String),
+    List(Box<Type>),
+    Tuple(Vec<Type>),
+    Dict,
+    Named(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Number => write!(f, "number"),
+            Type::Atom => write!(f, "atom"),
+            Type::Literal(s) => write!(f, "{}", s),
+            Type::List(t) => write!(f, "[{}]", t),
+            Type::Tuple(ts) => {
+                write!(f, "(")?;
+                for (i, t) in ts.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ")")
+            },
+            Type::Dict => write!(f, "dict"),
+            Type::Named(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Type {
+    pub fn from_node(tnode: &TypeNode) -> Type {
+        match &tnode.contents {
+            TypeContents::Name(name) => {
+                match name.as_ref() {
+                    "number" => Type::Number,
+                    "atom" => Type::Atom,
+                    "any" => Type::Any,
+                    "dict" => Type::Dict,
+                    _ => Type::Named(name.clone()),
+                }
+            },
+            TypeContents::Literal(s) => Type::Literal(s.clone()),
+            TypeContents::List(inner) => Type::List(Box::new(Type::from_node(inner))),
+            TypeContents::Tuple(items) => Type::Tuple(items.iter().map(Type::from_node).collect()),
+        }
+    }
+
+    // The type of the elements of a list of this type
+    fn element(&self) -> Type {
+        match self {
+            Type::List(t) => (**t).clone(),
+            _ => Type::Any,
+        }
+    }
+
+    // The type of the nth element of a tuple of this type
+    fn nth(&self, n: usize, len: usize) -> Type {
+        match self {
+            Type::Tuple(ts) if ts.len() == len => ts[n].clone(),
+            _ => Type::Any,
+        }
+    }
+
+    // The smallest type containing both types which
+    // we're able to express
+    fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a.clone(),
+            (Type::Literal(_), Type::Literal(_))
+            | (Type::Literal(_), Type::Atom)
+            | (Type::Atom, Type::Literal(_)) => Type::Atom,
+            (Type::List(a), Type::List(b)) => Type::List(Box::new(a.join(b))),
+            (Type::Tuple(a), Type::Tuple(b)) if a.len() == b.len() => {
+                Type::Tuple(a.iter().zip(b.iter()).map(|(x, y)| x.join(y)).collect())
+            },
+            _ => Type::Any,
+        }
+    }
+}
+
+// Relation name and arity
+type Signatures = HashMap<(String, usize), Vec<Type>>;
+
+pub fn check_types(program: &ProgramNode, source: &String) -> Vec<Error> {
+    let mut checker = TypeChecker {
+        source,
+        types: HashMap::new(),
+        signatures: HashMap::new(),
+        env: HashMap::new(),
+        outputs: None,
+    };
+    let mut res = vec![];
+    for decl in program.declarations.iter() {
+        if let DeclarationNode::Type(tdnode) = decl {
+            checker.types.insert(tdnode.name.clone(),
+                tdnode.alternatives.iter().map(|alt| Type::from_node(alt)).collect());
+        }
+    }
+    for decl in program.declarations.iter() {
+        if let DeclarationNode::Type(tdnode) = decl {
+            for alt in tdnode.alternatives.iter() {
+                res.append(&mut checker.check_type_node(alt));
+            }
+        }
+    }
+    for relation in program.relations.iter() {
+        for tnode in relation.param_types.iter().flatten() {
+            res.append(&mut checker.check_type_node(tnode));
+        }
+        for tnode in relation.output_types.iter().flatten() {
+            res.append(&mut checker.check_type_node(tnode));
+        }
+        if let Some(sig) = relation_signature(relation) {
+            let key = (relation.relation.name.clone(), sig.len());
+            checker.signatures.entry(key).or_insert(sig);
+        }
+    }
+    res.append(&mut checker.visit_program(program));
+    res
+}
+
+pub fn relation_arity(relation: &RelationNode) -> usize {
+    let num_outputs = match &relation.output_types {
+        Some(outputs) => outputs.len(),
+        None => {
+            match &relation.block {
+                RelationBlock::Const(clist) => clist.constants.len(),
+                RelationBlock::Block(bnode) => find_num_results(bnode),
+            }
+        }
+    };
+    relation.params.constants.len() + num_outputs
+}
+
+// The types of each argument of a relation, if
+// the relation has any type annotations
+fn relation_signature(relation: &RelationNode) -> Option<Vec<Type>> {
+    if relation.output_types.is_none() && relation.param_types.iter().all(|t| t.is_none()) {
+        return None;
+    }
+    let mut sig: Vec<Type> = relation.param_types.iter()
+        .map(|t| t.as_ref().map(Type::from_node).unwrap_or(Type::Any))
+        .collect();
+    match &relation.output_types {
+        Some(outputs) => {
+            sig.extend(outputs.iter().map(Type::from_node));
+        },
+        None => {
+            sig.resize(relation_arity(relation), Type::Any);
+        },
+    }
+    Some(sig)
+}
+
+struct TypeChecker<'a> {
+    source: &'a String,
+    // User defined types, and their alternatives
+    types: HashMap<String, Vec<Type>>,
+    signatures: Signatures,
+    // Types of the variables in the current clause
+    env: HashMap<String, Type>,
+    // Declared output types of the current clause
+    outputs: Option<Vec<Type>>,
+}
+
+impl<'a> TypeChecker<'a> {
+    fn check_type_node(&self, tnode: &TypeNode) -> Vec<Error> {
+        match &tnode.contents {
+            TypeContents::Name(name) => {
+                match Type::from_node(tnode) {
+                    Type::Named(_) if !self.types.contains_key(name) => {
+                        vec![Error::Code(
+                            type_error_snippet(&tnode.span, codes::UNKNOWN_TYPE, "unknown type",
+                                format!("no type named `{}`", name), self.source).into()
+                        )]
+                    },
+                    _ => vec![],
+                }
+            },
+            TypeContents::Literal(_) => vec![],
+            TypeContents::List(inner) => self.check_type_node(inner),
+            TypeContents::Tuple(items) => {
+                items.iter().flat_map(|item| self.check_type_node(item)).collect()
+            },
+        }
+    }
+
+    // Whether some value could have both types
+    fn overlaps(&self, a: &Type, b: &Type) -> bool {
+        self.overlaps_depth(a, b, 0)
+    }
+
+    fn overlaps_depth(&self, a: &Type, b: &Type, depth: usize) -> bool {
+        // Give up on (mutually) recursive types
+        if depth > 16 {
+            return true;
+        }
+        match (a, b) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Named(x), Type::Named(y)) if x == y => true,
+            (Type::Named(name), other) | (other, Type::Named(name)) => {
+                match self.types.get(name) {
+                    // Unknown types are reported seperately
+                    None => true,
+                    Some(alternatives) => {
+                        alternatives.iter()
+                            .any(|alt| self.overlaps_depth(alt, other, depth + 1))
+                    },
+                }
+            },
+            (Type::Number, Type::Number) => true,
+            (Type::Dict, Type::Dict) => true,
+            (Type::Atom, Type::Atom)
+            | (Type::Atom, Type::Literal(_))
+            | (Type::Literal(_), Type::Atom) => true,
+            (Type::Literal(x), Type::Literal(y)) => x == y,
+            (Type::List(x), Type::List(y)) => self.overlaps_depth(x, y, depth + 1),
+            (Type::Tuple(xs), Type::Tuple(ys)) => {
+                xs.len() == ys.len()
+                    && xs.iter().zip(ys.iter()).all(|(x, y)| self.overlaps_depth(x, y, depth + 1))
+            },
+            _ => false,
+        }
+    }
+
+    fn expect(&self, span: &Span, expected: &Type, found: &Type) -> Vec<Error> {
+        if self.overlaps(expected, found) {
+            vec![]
+        } else {
+            vec![Error::Code(
+                type_error_snippet(span, codes::MISMATCHED_TYPES, "mismatched types",
+                    format!("expected `{}`, found `{}`", expected, found), self.source).into()
+            )]
+        }
+    }
+
+    // Narrow the type of a variable after it's been
+    // used somewhere requiring a specific type
+    fn refine(&mut self, expr: &ExpressionNode, ty: &Type) {
+        if let ExpressionContents::Const(ConstantNode {
+            contents: ConstantContents::Var(name), ..
+        }) = &expr.contents {
+            if let Some(Type::Any) | None = self.env.get(name) {
+                self.env.insert(name.clone(), ty.clone());
+            }
+        }
+    }
+
+    // Check a pattern against the type of the value
+    // it matches, and bind the variables inside it
+    fn bind_pattern(&mut self, cnode: &ConstantNode, ty: &Type) -> Vec<Error> {
+        match &cnode.contents {
+            ConstantContents::EmptyPattern => vec![],
+            ConstantContents::Var(name) => {
+                match self.env.get(name).cloned() {
+                    Some(prev) => {
+                        if prev == Type::Any {
+                            self.env.insert(name.clone(), ty.clone());
+                        }
+                        self.expect(&cnode.span, &prev, ty)
+                    },
+                    None => {
+                        self.env.insert(name.clone(), ty.clone());
+                        vec![]
+                    },
+                }
+            },
+            ConstantContents::Literal(_) => self.expect(&cnode.span, ty, &Type::Number),
+            ConstantContents::Atom(s) => self.expect(&cnode.span, ty, &Type::Literal(s.clone())),
+            ConstantContents::List(items) => {
+                let mut res = self.expect(&cnode.span, ty, &Type::List(Box::new(Type::Any)));
+                let element = ty.element();
+                for item in items.iter() {
+                    res.append(&mut self.bind_pattern(item, &element));
+                }
+                res
+            },
+            ConstantContents::ConsList(items) => {
+                let mut res = self.expect(&cnode.span, ty, &Type::List(Box::new(Type::Any)));
+                let element = ty.element();
+                for item in items[..items.len() - 1].iter() {
+                    res.append(&mut self.bind_pattern(item, &element));
+                }
+                res.append(&mut self.bind_pattern(&items[items.len() - 1], &Type::List(Box::new(element))));
+                res
+            },
+            ConstantContents::Tuple(items) => {
+                let mut res = self.expect(&cnode.span, ty, &Type::Tuple(vec![Type::Any; items.len()]));
+                for (i, item) in items.iter().enumerate() {
+                    res.append(&mut self.bind_pattern(item, &ty.nth(i, items.len())));
+                }
+                res
+            },
+            ConstantContents::Dict(entries) => {
+                let mut res = self.expect(&cnode.span, ty, &Type::Dict);
+                for (_, item) in entries.iter() {
+                    res.append(&mut self.bind_pattern(item, &Type::Any));
+                }
+                res
+            },
+        }
+    }
+
+    fn constant_type(&self, cnode: &ConstantNode) -> Type {
+        match &cnode.contents {
+            ConstantContents::EmptyPattern => Type::Any,
+            ConstantContents::Var(name) => self.env.get(name).cloned().unwrap_or(Type::Any),
+            ConstantContents::Literal(_) => Type::Number,
+            ConstantContents::Atom(s) => Type::Literal(s.clone()),
+            ConstantContents::List(items) => {
+                Type::List(Box::new(self.join_all(items.iter().map(|c| self.constant_type(c)))))
+            },
+            ConstantContents::ConsList(items) => {
+                let heads = self.join_all(items[..items.len() - 1].iter().map(|c| self.constant_type(c)));
+                let tail = self.constant_type(&items[items.len() - 1]).element();
+                Type::List(Box::new(heads.join(&tail)))
+            },
+            ConstantContents::Tuple(items) => {
+                Type::Tuple(items.iter().map(|c| self.constant_type(c)).collect())
+            },
+            ConstantContents::Dict(_) => Type::Dict,
+        }
+    }
+
+    fn join_all<I: Iterator<Item=Type>>(&self, types: I) -> Type {
+        let mut res: Option<Type> = None;
+        for ty in types {
+            res = Some(match res {
+                None => ty,
+                Some(prev) => prev.join(&ty),
+            });
+        }
+        res.unwrap_or(Type::Any)
+    }
+
+    // Check the arguments of a call to a relation against
+    // its signature, returning the types of any extra (output)
+    // arguments
+    fn check_call(&mut self, rel: &RelationId, args: &[ExpressionNode], num_outputs: usize, errs: &mut Vec<Error>) -> Vec<Type> {
+        let arg_types: Vec<Type> = args.iter()
+            .map(|arg| self.infer_expr(arg, errs))
+            .collect();
+        let key = (rel.name.clone(), args.len() + num_outputs);
+        match self.signatures.get(&key).cloned() {
+            None => vec![Type::Any; num_outputs],
+            Some(sig) => {
+                for (i, arg) in args.iter().enumerate() {
+                    errs.append(&mut self.expect(&expr_span(arg), &sig[i], &arg_types[i]));
+                    self.refine(arg, &sig[i]);
+                }
+                sig[args.len()..].to_vec()
+            },
+        }
+    }
+
+    fn infer_expr(&mut self, expr: &ExpressionNode, errs: &mut Vec<Error>) -> Type {
+        match &expr.contents {
+            ExpressionContents::Const(cnode) => self.constant_type(cnode),
+            ExpressionContents::Operation { op: _op, lhs, rhs } => {
+                for operand in [lhs, rhs].iter() {
+                    let ty = self.infer_expr(operand, errs);
+                    match &operand.contents {
+                        // Already reported by check_odd_ops
+                        ExpressionContents::List {..}
+                        | ExpressionContents::ConsList {..}
+                        | ExpressionContents::Tuple {..}
+                        | ExpressionContents::Range {..}
+                        | ExpressionContents::Dict {..} => {},
+                        _ => {
+                            errs.append(&mut self.expect(&expr_span(operand), &Type::Number, &ty));
+                            self.refine(operand, &Type::Number);
+                        },
+                    }
+                }
+                Type::Number
+            },
+            ExpressionContents::Call { rel, args } => {
+                self.check_call(rel, args, 1, errs).remove(0)
+            },
+            ExpressionContents::List { vals } => {
+                let types: Vec<Type> = vals.iter()
+                    .map(|val| self.infer_expr(val, errs))
+                    .collect();
+                Type::List(Box::new(self.join_all(types.into_iter())))
+            },
+            ExpressionContents::ConsList { vals } => {
+                let mut types: Vec<Type> = vals.iter()
+                    .map(|val| self.infer_expr(val, errs))
+                    .collect();
+                let tail = types.pop().unwrap();
+                errs.append(&mut self.expect(&expr_span(&vals[vals.len() - 1]), &Type::List(Box::new(Type::Any)), &tail));
+                let heads = self.join_all(types.into_iter());
+                Type::List(Box::new(heads.join(&tail.element())))
+            },
+            ExpressionContents::Tuple { vals } => {
+                Type::Tuple(vals.iter().map(|val| self.infer_expr(val, errs)).collect())
+            },
+            ExpressionContents::Dict { entries } => {
+                for (_, val) in entries.iter() {
+                    self.infer_expr(val, errs);
+                }
+                Type::Dict
+            },
+            ExpressionContents::Range { lo, hi, step, .. } => {
+                for bound in [Some(lo), Some(hi), step.as_ref()].iter().flatten() {
+                    let ty = self.infer_expr(bound, errs);
+                    errs.append(&mut self.expect(&expr_span(bound), &Type::Number, &ty));
+                    self.refine(bound, &Type::Number);
+                }
+                Type::List(Box::new(Type::Number))
+            },
+        }
+    }
+}
+
+impl<'a> ASTVisitor<Error> for TypeChecker<'a> {
+    fn visit_relation(&mut self, relation: &RelationNode) -> Vec<Error> {
+        let mut res = vec![];
+        self.env.clear();
+        // Annotations on this clause take priority over
+        // annotations on other clauses of the same relation
+        let arity = relation_arity(relation);
+        let sig: Vec<Type> = self.signatures.get(&(relation.relation.name.clone(), arity))
+            .cloned()
+            .unwrap_or(vec![Type::Any; arity]);
+        let num_params = relation.params.constants.len();
+        for (i, param) in relation.params.constants.iter().enumerate() {
+            let ty = match &relation.param_types[i] {
+                Some(tnode) => Type::from_node(tnode),
+                None => sig[i].clone(),
+            };
+            res.append(&mut self.bind_pattern(param, &ty));
+        }
+        let outputs: Vec<Type> = match &relation.output_types {
+            Some(outputs) => outputs.iter().map(Type::from_node).collect(),
+            None => sig[num_params..].to_vec(),
+        };
+        match &relation.block {
+            RelationBlock::Const(clist) => {
+                if outputs.len() == clist.constants.len() {
+                    for (constant, ty) in clist.constants.iter().zip(outputs.iter()) {
+                        res.append(&mut self.bind_pattern(constant, ty));
+                    }
+                }
+                self.outputs = None;
+            },
+            RelationBlock::Block(bnode) => {
+                self.outputs = Some(outputs);
+                res.append(&mut self.visit_block(bnode));
+            },
+        }
+        res
+    }
+
+    fn visit_assignment(&mut self, assignment: &AssignmentNode) -> Vec<Error> {
+        let mut res = vec![];
+        let lhs = &assignment.lhs.constants;
+        let types = match &assignment.rhs.contents {
+            ExpressionContents::Call { rel, args } => {
+                self.check_call(rel, args, lhs.len(), &mut res)
+            },
+            _ => {
+                let ty = self.infer_expr(&assignment.rhs, &mut res);
+                if lhs.len() == 1 {
+                    vec![ty]
+                } else {
+                    // Several patterns are matched against a tuple
+                    res.append(&mut self.expect(&expr_span(&assignment.rhs),
+                        &Type::Tuple(vec![Type::Any; lhs.len()]), &ty));
+                    (0..lhs.len()).map(|i| ty.nth(i, lhs.len())).collect()
+                }
+            },
+        };
+        for (constant, ty) in lhs.iter().zip(types.iter()) {
+            res.append(&mut self.bind_pattern(constant, ty));
+        }
+        res
+    }
+
+    fn visit_relate(&mut self, relate: &RelateNode) -> Vec<Error> {
+        let mut res = vec![];
+        let outputs = self.outputs.clone();
+        for (i, result) in relate.result.iter().enumerate() {
+            let ty = self.infer_expr(result, &mut res);
+            if let Some(outputs) = &outputs {
+                if outputs.len() == relate.result.len() {
+                    res.append(&mut self.expect(&expr_span(result), &outputs[i], &ty));
+                }
+            }
+        }
+        res
+    }
+
+    fn visit_refute(&mut self, refute: &RefuteNode) -> Vec<Error> {
+        // Nothing bound inside of a refute is visible outside of it
+        let env = self.env.clone();
+        let mut res = vec![];
+        match &refute.body {
+            RefuteBody::Statement(statement) => {
+                res.append(&mut self.visit_statement(statement));
+            },
+            RefuteBody::Block(bnode) => {
+                res.append(&mut self.visit_block(bnode));
+            },
+        }
+        self.env = env;
+        res
+    }
+
+    fn visit_in(&mut self, in_node: &InNode) -> Vec<Error> {
+        let mut res = vec![];
+        let ty = self.infer_expr(&in_node.collection, &mut res);
+        res.append(&mut self.expect(&expr_span(&in_node.collection), &Type::List(Box::new(Type::Any)), &ty));
+        res.append(&mut self.bind_pattern(&in_node.pattern, &ty.element()));
+        res
+    }
+
+    fn visit_bfact(&mut self, bfact: &BinaryFactNode) -> Vec<Error> {
+        let mut res = vec![];
+        let ltype = self.infer_expr(&bfact.lhs, &mut res);
+        let rtype = self.infer_expr(&bfact.rhs, &mut res);
+        match bfact.op {
+            BinaryFactOperation::Equ | BinaryFactOperation::Neq => {},
+            _ => {
+                res.append(&mut self.expect(&expr_span(&bfact.lhs), &Type::Number, &ltype));
+                res.append(&mut self.expect(&expr_span(&bfact.rhs), &Type::Number, &rtype));
+                self.refine(&bfact.lhs, &Type::Number);
+                self.refine(&bfact.rhs, &Type::Number);
+            },
+        }
+        res
+    }
+
+    fn visit_relcall(&mut self, rcall: &RelationCallNode) -> Vec<Error> {
+        let mut res = vec![];
+        self.check_call(&rcall.rel, &rcall.args, 0, &mut res);
+        res
+    }
+}
+
+// The span of an expression, including both sides of operations
+pub fn expr_span<'p>(expr: &ExpressionNode<'p>) -> Span<'p> {
+    match &expr.contents {
+        ExpressionContents::Operation { op: _op, lhs, rhs } => {
+            union_spans(&expr_span(lhs), &expr_span(rhs))
+        },
+        _ => expr.span.clone(),
+    }
+}
+
+fn type_error_snippet(span: &Span, code: &str, title: &str, label: String, source: &str) -> Snippet {
+    code_snippet(title.to_string(), code, AnnotationType::Error, vec![], vec![span_slice(span, label, AnnotationType::Error, source)])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn type_errors(program_input: &str) -> Vec<String> {
+        let pairs = BevelParser::parse(Rule::program, program_input).unwrap();
+
+        let prog = parse_program(pairs, program_input);
+
+        checks::perform_checks(&prog, None, "test".to_string()).iter()
+            .filter(|err| err.lint() != Some("singleton"))
+            .map(|err| format!("{}", err).to_string())
+            .collect()
+    }
+
+    // Each case is a program and the text of each of its type
+    // errors, which can be found in any order
+    #[test]
+    pub fn invoke_type_checks() {
+        let cases: &[(&str, &[&str])] = &[
+            (r#"
+len(l: [any]) -> number {
+    relate 0
+};
+double(x: number) -> number {
+    relate x * 2
+};
+bad(l) {
+    n ~ len(l)
+    relate double(n) + len(n)
+};
+"#, &["expected `[any]`, found `number`"]),
+            (r#"
+type color = 'red | 'green | 'blue;
+type palette = [color];
+warm('red: color) ~ 'true;
+first(p: palette) -> color {
+    (c:_) ~ p
+    relate c
+};
+wrong() -> palette {
+    relate [3]
+};
+unknown(x: shade) ~ x;
+"#, &["expected `palette`, found `[number]`", "no type named `shade`"]),
+            (r#"
+fst(p: (number, atom)) -> number {
+    (n, _) ~ p
+    relate n
+};
+good() {
+    relate fst((1, 'a))
+};
+swapped() {
+    relate fst(('a, 1))
+};
+short() {
+    (a, b, c) ~ (1, 2)
+    relate a
+};
+"#, &["expected `(number, atom)`, found `('a, number)`", "expected `(any, any, any)`, found `(number, number)`"]),
+            // Programs without types have no type errors
+            (r#"
+fib(0) ~ 1;
+fib(1) ~ 1;
+fib(x) {
+    x > 1
+    relate fib(x - 1) + fib(x - 2)
+};
+head((x:_)) ~ x;
+"#, &[]),
+        ];
+        for (program_input, expected) in cases.iter() {
+            let errs = type_errors(program_input);
+            assert_eq!(errs.len(), expected.len(), "{:?}", errs);
+            for part in expected.iter() {
+                assert!(errs.iter().any(|err| err.contains(part)), "{:?} doesn't have {:?}", errs, part);
+            }
+        }
+    }
+}
@@ -146,3 +146,1201 @@ samehead((x:_)) ~ (x:_);
 
     Ok(())
 }
+
+#[test]
+pub fn test_refute() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+small(x) {
+    refute x > 3
+};
+notpair(x) {
+    refute (a, b) ~ split(x)
+};
+split([a, b]) ~ (a, b);
+member((x:_)) ~ x;
+member((_:xs)) {
+    relate member(xs)
+};
+lonely(x, l) {
+    refute {
+        y ~ member(l)
+        y == x + 1
+    }
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("small(2)\nsmall(5)")
+        .assert()
+        .success()
+        .stdout("success\nfail\n");
+    
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("notpair([1])\nnotpair([1, 2])")
+        .assert()
+        .success()
+        .stdout("success\nfail\n");
+    
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("lonely(1, [1, 3])\nlonely(1, [1, 2])")
+        .assert()
+        .success()
+        .stdout("success\nfail\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_dynamic_database() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+dynamic counter/1;
+dynamic item/1;
+counter() ~ 0;
+bump() {
+	n ~ counter()
+	retract(counter(n))
+	assert(counter(n + 1))
+	relate n + 1
+};
+item() ~ 1;
+item() ~ 2;
+grow() {
+	x ~ item()
+	assert(item(x + 10))
+	x > 10
+};
+big() {
+	x ~ item()
+	x > 10
+	relate x
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ bump()\ny ~ bump()\nz ~ counter()")
+        .assert()
+        .success()
+        .stdout("x = 1\ny = 2\nz = 2\n");
+
+    // Clauses added while grow() runs aren't seen by it
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("grow()\nx ~ big()")
+        .assert()
+        .success()
+        .stdout("fail\nx = 11\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("seen() ~ 'a;\nq ~ seen()")
+        .assert()
+        .success()
+        .stdout("success\nq = 'a\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_dicts() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+person() ~ {name: 'bob, age: 30};
+person() ~ {name: 'sue, age: 41, job: 'chef};
+name({name: n}) ~ n;
+older(p) {
+	relate put_dict('age, p, p.age + 1)
+};
+adults() {
+	p ~ person()
+	p.age > 35
+	relate p.name
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("n ~ name(person())\nq ~ older(person())\na ~ adults()")
+        .assert()
+        .success()
+        .stdout("n = 'bob\nq = {age: 31, name: 'bob}\na = 'sue\n");
+
+    // Patterns only match the keys they mention
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("{job: j} ~ person()\n{age: a, zip: z} ~ person()")
+        .assert()
+        .success()
+        .stdout("j = 'chef\nfail\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("k ~ dict_keys(person())\n(v, d) ~ del_dict('age, person())")
+        .assert()
+        .success()
+        .stdout(contains("k = ['age, 'name]\n"))
+        .stdout(contains("v = 30"))
+        .stdout(contains("d = {name: 'bob}"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_exceptions() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+safediv(a, b) {
+	catch {
+		relate a / b
+	} with {error: 'evaluation_error} {
+		relate 0
+	}
+};
+check(x) {
+	x > 0
+	throw({error: 'bad_value, value: x})
+};
+guarded(x) {
+	catch {
+		check(x)
+		relate 'ok
+	} with {value: v} {
+		relate v
+	}
+};
+item() ~ 1;
+item() ~ 2;
+firstbig() {
+	catch {
+		x ~ item()
+		x > 1
+	} with _ {
+		relate 0
+	}
+	relate x
+};
+after() {
+	catch {
+		x ~ 1
+	} with _ {
+		x ~ 2
+	}
+	throw('later)
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ safediv(6, 3)\nx ~ safediv(6, 0)\nr ~ guarded(0)\nr ~ guarded(5)")
+        .assert()
+        .success()
+        .stdout("x = 2\nx = 0\nfail\nr = 5\n");
+
+    // Backtracking into a catch, and throwing after one has finished
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ firstbig()\nafter()\ncheck(3)")
+        .assert()
+        .success()
+        .stdout("x = 2\nuncaught exception: 'later\nuncaught exception: {error: 'bad_value, value: 3}\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ y + 1")
+        .assert()
+        .success()
+        .stdout("uncaught exception: {error: 'instantiation_error, relation: '+}\n");
+
+    // Calls with the wrong number of arguments throw a type error
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("throw(1, 2)\nx ~ call('atom, 1)")
+        .assert()
+        .success()
+        .stdout("uncaught exception: {error: 'type_error, relation: 'throw}\nuncaught exception: {error: 'type_error, relation: 'atom}\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_tuples() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+swap((a, b)) ~ (b, a);
+wrap(x) {
+	t ~ (x, x + 1)
+	relate t
+};
+sums(x, y) {
+	(a, b) ~ (x + 1, y * 2)
+	relate (a, b)
+};
+nested() {
+	(a, (b, c)) ~ (1, (2, 3))
+	relate [a, b, c]
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("t ~ wrap(3)\nl ~ nested()")
+        .assert()
+        .success()
+        .stdout("t = (3, 4)\nl = [1, 2, 3]\n");
+
+    // Multiple outputs and tuple destructuring
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("(x, y) ~ swap((1, 2))\n(p, q) ~ sums(1, 2)\nt ~ wrap(1), (a, b) ~ t")
+        .assert()
+        .success()
+        .stdout(contains("x = 2").and(contains("y = 1"))
+            .and(contains("p = 2")).and(contains("q = 4"))
+            .and(contains("a = 1")).and(contains("b = 2")));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_ranges() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+evens(n) {
+	x in 0..n by 2
+	relate x
+};
+countdown(n) {
+	relate n..<0 by -1
+};
+pairs() {
+	x in 1..2
+	y ~ between(x, 3)
+	relate (x, y)
+};
+showall() {
+	(a, b) ~ pairs()
+	writeln(a, b)
+	a > 5
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("l ~ 1..5\nl ~ 1..<5\nl ~ 5..4\nl ~ countdown(3)\nx ~ evens(5)")
+        .assert()
+        .success()
+        .stdout("l = [1, 2, 3, 4, 5]\nl = [1, 2, 3, 4]\nl = []\nl = [3, 2, 1]\nx = 0\n");
+
+    // Every solution of between and in is found when backtracking
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("showall()")
+        .assert()
+        .success()
+        .stdout("1\t1\n1\t2\n1\t3\n2\t2\n2\t3\nfail\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("y ~ succ(4)\nx ~ succ(0)\nx ~ between(1, 'inf)\nbetween(1, 3, 7)\nx ~ 1..2 by 0")
+        .assert()
+        .success()
+        .stdout("y = 5\nx = 1\nx = 1\nfail\nuncaught exception: {error: 'evaluation_error, relation: '..}\n");
+
+    // In statements can be queried directly
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x in 2..3\nx in [4, 5]\nx in 1..0")
+        .assert()
+        .success()
+        .stdout("x = 2\nx = 4\nfail\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_term_inspection() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+partial(x) ~ (x : t);
+args(t) {
+	a ~ arg(n, t)
+	writeln(n, a)
+	n > 5
+};
+inspect() {
+	l ~ partial(1)
+	nonvar(l)
+	(n, a) ~ functor(l)
+	rest ~ arg(2, l)
+	writeln(n, a, rest)
+	c ~ copy_term([x, y, (x : r)])
+	writeln(c)
+	('tuple, 2) ~ functor(t)
+	['tuple, 1, s] ~ univ(p)
+	writeln(t, p)
+};
+notlist() {
+	l ~ partial(1)
+	is_list(l)
+};
+notground() {
+	l ~ partial(1)
+	ground(l)
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("is_list([1, 2])\nground((1, 'a))\nnumber('a)\n(n, 0) ~ functor(3)\nl ~ univ((1, 2))\nnotlist()\nnotground()")
+        .assert()
+        .success()
+        .stdout("success\nsuccess\nfail\nn = 3\nl = ['tuple, 1, 2]\nfail\nfail\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("args((1, 2, 3))\ninspect()\nl ~ univ(x)")
+        .assert()
+        .success()
+        .stdout("1\t1\n2\t2\n3\t3\nfail\n'[|]\t2\tt\n[_0, _1, (_0 : _2)]\n(_0, _1)\t(1, s)\nsuccess\nuncaught exception: {error: 'instantiation_error, relation: 'univ}\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_sorting() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+desc(a, b) {
+	relate compare(b, a)
+};
+bylen(a, b) {
+	relate compare(a.len, b.len)
+};
+people() {
+	relate [{name: 'sue, len: 3}, {name: 'al, len: 2}, {name: 'bo, len: 2}]
+};
+mixed() {
+	relate [(1, 'b), 'a, 3, [], [1, 2], (0, 'c), 2]
+};
+equality() {
+	[1] != [1, 2]
+	(1, 2) == (1, 2)
+	'a != 1
+	(x : t) != (x : s)
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("l ~ sort([3, 1, 2, 1])\nl ~ msort([3, 1, 2, 1])\nl ~ msort(mixed())\no ~ compare(1, 'a)\nterm_lt([], 'a)\nequality()")
+        .assert()
+        .success()
+        .stdout("l = [1, 2, 3]\nl = [1, 1, 2, 3]\nl = [2, 3, [], 'a, [1, 2], (0, 'c), (1, 'b)]\no = '<\nsuccess\nsuccess\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("l ~ sort_by_key(2, [(1, 'b), (2, 'a), (3, 'b)])\nl ~ predsort('desc, [1, 3, 2, 3])\nl ~ predsort('bylen, people())\nl ~ sort(x)")
+        .assert()
+        .success()
+        .stdout("l = [(2, 'a), (1, 'b), (3, 'b)]\nl = [3, 2, 1]\nl = [{len: 2, name: 'al}, {len: 3, name: 'sue}]\nuncaught exception: {error: 'instantiation_error, relation: 'sort}\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_list_builtins() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+splits(l) {
+	l ~ append(a, b)
+	writeln(a, b)
+	a == 'never
+};
+partial(x) ~ (x : t);
+grow() {
+	l ~ partial(1)
+	3 ~ length(l)
+	writeln(l)
+	member(x, l)
+	writeln(x)
+	x == 'never
+};
+inserts(l) {
+	l ~ select(0, big)
+	writeln(big)
+	big == 'never
+};
+small(x) {
+	x < 3
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("n ~ length([1, 2, 3])\nl ~ append([1, 2], [3])\nr ~ reverse([1, 2, 3])\ne ~ nth(2, ['a, 'b])\nx ~ last([1, 2, 3])\nmember(2, [1, 2])\nr ~ select(2, [1, 2, 3])")
+        .assert()
+        .success()
+        .stdout("n = 3\nl = [1, 2, 3]\nr = [3, 2, 1]\ne = 'b\nx = 3\nsuccess\nr = [1, 3]\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("s ~ sum_list([1, 2, 3])\nm ~ max_list([1, 5, 3])\nf ~ flatten([1, [2, [3]], []])\nz ~ zip([1, 2], ['a, 'b])\nr ~ exclude('small, [1, 5, 2, 4])\ns ~ sum_list(x)")
+        .assert()
+        .success()
+        .stdout("s = 6\nm = 5\nf = [1, 2, 3]\nz = [(1, 'a), (2, 'b)]\nr = [5, 4]\nuncaught exception: {error: 'instantiation_error, relation: 'sum_list}\n");
+
+    // Lists which are only partly known are filled in
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("splits([1, 2])\ngrow()\ninserts([1, 2])")
+        .assert()
+        .success()
+        .stdout("[]\t[1, 2]\n[1]\t[2]\n[1, 2]\t[]\nfail\n[1, _1, _2]\n1\n_1\n_2\nfail\n[0, 1, 2]\n[1, 0, 2]\n[1, 2, 0]\nfail\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_shadowed_builtins() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+length(l) ~ 'mine;
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("n ~ length([1, 2, 3])\nl ~ reverse([1, 2])")
+        .assert()
+        .success()
+        .stdout("n = 'mine\nl = [2, 1]\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_prelude() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+double(x) {
+	relate x * 2
+};
+small(x) {
+	x < 3
+};
+add(a, b) {
+	relate a + b
+};
+filter(p, l) ~ 'mine;
+doubled(l) {
+	relate map('double, l)
+};
+"#;
+    
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("l ~ map('double, [1, 2, 3])\ns ~ fold('add, 0, [1, 2, 3])\nl ~ concat([[1], [2, 3]])\nl ~ permutation([1, 2])\nl ~ filter('small, [1, 5])\nx ~ call('double, 4)\nl ~ map(3, [1])")
+        .assert()
+        .success()
+        .stdout("l = [2, 4, 6]\ns = 6\nl = [1, 2, 3]\nl = [1, 2]\nl = 'mine\nx = 8\nuncaught exception: {error: 'type_error, relation: 'call}\n");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("--no-prelude")
+        .assert()
+        .failure()
+        .stderr(contains("no relation named `map` is defined"));
+
+    // The Prolog source includes the prelude's relations which are used
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-p")
+        .assert()
+        .success()
+        .stdout(contains("map(_Var_f, [], []).").and(contains("fold(").not()));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_strings() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+name() ~ "bob";
+greeting() ~ "hello,\tworld \"\\o/\"";
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("n ~ name(), n == 'bob\ng ~ greeting()")
+        .assert()
+        .success()
+        .stdout(contains("n = 'bob\n"))
+        .stdout(contains("g = 'hello,\tworld \"\\o/\"\n"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_file_io() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+save(path) {
+	s ~ open(path, 'write)
+	print(s, [1, 'a, "b c"])
+	nl(s)
+	write(s, "plain text")
+	nl(s)
+	close(s)
+};
+load(path) {
+	s ~ open(path, 'read)
+	t ~ read_term(s)
+	l ~ read_line(s)
+	e ~ read_line(s)
+	close(s)
+	relate (t, l, e)
+};
+missing(path) {
+	catch {
+		s ~ open(path, 'read)
+		relate 'opened
+	} with {error: e} {
+		relate e
+	}
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("out.txt");
+    let path = path.to_str().unwrap();
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin(format!("save(\"{0}\")\n(t, l, e) ~ load(\"{0}\")\nl ~ read_file_to_lines(\"{0}\")\nexists_file(\"{0}\")\ne ~ missing(\"{0}.nope\")\nwrite(\"hi \"), print(\"a b\"), nl()", path))
+        .assert()
+        .success()
+        .stdout(contains("t = [1, 'a, 'b c]"))
+        .stdout(contains("l = 'plain text"))
+        .stdout(contains("e = 'end_of_file"))
+        .stdout(contains("l = ['[1, 'a, \"b c\"], 'plain text]\nsuccess\ne = 'existence_error\nhi \"a b\"\nsuccess\n"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_undefined_relations() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+total(l) {
+	relate sum_lst(l)
+};
+sizes(l) {
+	n ~ lenght(l)
+	relate n
+};
+uses_dynamic() {
+	extra(1)
+};
+dynamic extra/1;
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains("no relation named `sum_lst` is defined"))
+        .stderr(contains("did you mean `sum_list`?"))
+        .stderr(contains("no relation named `lenght` is defined"))
+        .stderr(contains("did you mean `length`?"))
+        .stderr(contains("aborting due to the previous 2 errors"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_arity_checks() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+fib(0) ~ 1;
+fib(1) ~ 1;
+fib(n) {
+	n > 1
+	relate fib(n - 1) + fib(n - 2)
+};
+fib(n, _m) ~ n;
+bad(n) {
+	relate fib(n, n, n)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains("clauses have different arities"))
+        .stderr(contains("but this clause defines `fib/3`"))
+        .stderr(contains("wrong number of arguments"))
+        .stderr(contains("this calls `fib/4`, which isn't defined"))
+        .stderr(contains("`fib` is defined as `fib/2` and `fib/3`"))
+        .stderr(contains("aborting due to the previous 1 error"));
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .arg("-A")
+        .arg("arity")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains("clauses have different arities").not());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_syntax_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+one() ~ 1
+two() ~ 2;
+total(l) {
+	n ~ sum_list(l) *
+	relate (n, [1, 2)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains("expected `;` after relation"))
+        .stderr(contains("expected an expression"))
+        .stderr(contains("mismatched closing `)`"))
+        .stderr(contains("aborting due to the previous 3 errors"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_json_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let source = "count(l) {\n\tn ~ lenght(l)\n\trelate n\n};\nunused(x) ~ 1;\n";
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .arg("--error-format=json")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains(r#"{"severity":"error","code":"B0007","lint":null,"message":"undefined relation""#))
+        .stderr(contains(r#""message":"no relation named `lenght` is defined","severity":"error","primary":true"#))
+        .stderr(contains(r#""byte_start":16,"byte_end":22,"line_start":2,"column_start":6,"line_end":2,"column_end":12}"#))
+        .stderr(contains(r#""notes":[{"severity":"help","message":"did you mean `length`?"}]}"#))
+        .stderr(contains(r#"{"severity":"warning","code":"B0008","lint":"singleton","message":"singleton variable""#))
+        .stderr(contains("aborting").not());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_explain() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+sizes(l) {
+	relate lenght(l)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(contains("[B0007]"))
+        .stderr(contains("try `bevel --explain B0007`"));
+
+    Command::cargo_bin("bevel")?
+        .arg("--explain")
+        .arg("B0007")
+        .assert()
+        .success()
+        .stdout(contains("A relation is called which is never defined."))
+        .stdout(contains("dynamic seen/1;"));
+
+    Command::cargo_bin("bevel")?
+        .arg("--explain")
+        .arg("B9999")
+        .assert()
+        .failure()
+        .stderr(contains("no error or warning has the code `B9999`"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_unused_relations() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+export inc/2;
+color('red) ~ 1;
+color('blue) ~ 2;
+color('red) ~ 1;
+inc(x) {
+	relate x + 1
+};
+old_inc(x) {
+	relate x + 1
+};
+main() {
+	relate color('red)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ inc(1)")
+        .assert()
+        .success()
+        .stdout(contains("x = 2"))
+        .stderr(contains("`old_inc/2` is never used"))
+        .stderr(contains("`inc/2` is never used").not())
+        .stderr(contains("this clause is the same as an earlier one"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_left_recursion() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+edge('a) ~ 'b;
+edge('b) ~ 'c;
+path(x) {
+	y ~ path(x)
+	relate edge(y)
+};
+path(x) {
+	relate edge(x)
+};
+table reach/2;
+reach(x) {
+	y ~ reach(x)
+	relate edge(y)
+};
+reach(x) {
+	relate edge(x)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("")
+        .assert()
+        .success()
+        .stderr(contains("left recursion"))
+        .stderr(contains("the calls go `path/2` -> `path/2`"))
+        .stderr(contains("`reach/2`").not());
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-p")
+        .assert()
+        .success()
+        .stdout(contains(":- table reach/2."));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_unknown_relations() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+dynamic seen/1;
+item() ~ 1;
+guarded(x) {
+	catch {
+		call(x, 1)
+		relate 'called
+	} with {error: 'existence_error, relation: r} {
+		relate r
+	}
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ item()\nitme(x)\nx ~ item(2)\nseen(x)\nx ~ guarded('missing)\nseen() ~ 1;\nseen(x)")
+        .assert()
+        .success()
+        .stdout("x = 1\nuncaught exception: {arity: 1, error: 'existence_error, relation: 'itme}\nuncaught exception: {arity: 2, error: 'existence_error, relation: 'item}\nfail\nx = 'missing\nsuccess\nx = 1\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_underscore_is_not_a_number() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+id(x) ~ x;
+g() {
+    relate id(_)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    // A lone _ used to be read as a number, which panicked when it was parsed
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .assert()
+        .code(1)
+        .stderr(contains("relate id(_)"))
+        .stderr(contains("panicked").not());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_singleton_warnings() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+first(l) {
+	(x : rest) ~ l
+	relate x
+};
+second((_, (y : _rest))) ~ y;
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ first([1, 2])")
+        .assert()
+        .success()
+        .stdout("x = 1\n")
+        .stderr(contains("`rest` is only used once"))
+        .stderr(contains("`_rest` is only used once").not());
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .arg("-A")
+        .arg("singleton")
+        .write_stdin("x ~ first([1, 2])")
+        .assert()
+        .success()
+        .stdout("x = 1\n")
+        .stderr("");
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .arg("-D")
+        .arg("warnings")
+        .write_stdin("x ~ first([1, 2])")
+        .assert()
+        .failure()
+        .stderr(contains("error"))
+        .stderr(contains("aborting due to the previous 1 error"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_format() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+row(name, n) {
+	format("~a~t~10|~t~d~6+~n", [name, n])
+};
+label(x) {
+	relate format("<~w>", x)
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("row(\"apples\", 3), row('kiwi, 12)\ns ~ format(\"~2f and ~q\", [1/3, \"a b\"])\nl ~ label(3)\ns ~ format_to_string(\"~a\", 1)\nformat(\"~w ~w\", [1])")
+        .assert()
+        .success()
+        .stdout("apples         3\nkiwi          12\nsuccess\ns = '0.33 and \"a b\"\nl = '<3>\nuncaught exception: {error: 'type_error, relation: 'format}\nuncaught exception: {error: 'format_error, relation: 'format}\n");
+
+    Ok(())
+}
+
+#[test]
+pub fn test_type_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+type shade = 'light | 'dark;
+double(x: number) -> number {
+	relate x * 2
+};
+invert(s: shade) -> shade {
+	s == 'light
+	relate 'dark
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("x ~ double(3), y ~ invert('light)")
+        .assert()
+        .success()
+        .stdout(contains("x = 6"))
+        .stdout(contains("y = 'dark"))
+        .stderr("");
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}\ntwice() {{\n\trelate double('two)\n}};\npaint(c: color) ~ c;\n", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .assert()
+        .failure()
+        .stderr(contains("expected `number`, found `'two`"))
+        .stderr(contains("color"))
+        .stderr(contains("aborting due to the previous 2 errors"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_cons_tails() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+nested(a, b, c, rest) {
+	relate (a : b : (c : rest))
+};
+three() ~ [3];
+called(x) {
+	relate (x : three())
+};
+double(x) {
+	relate x * 2
+};
+computed(x, y) {
+	relate (x : (double(y) + 1 : []))
+};
+appended(x, l) {
+	relate (x : append(l, [x]))
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("l ~ nested(1, 2, 3, [4, 5])\nl ~ called(0)\nl ~ computed(1, 2)\nl ~ appended(0, [1, 2])\nl ~ nested(1, 2, 3, (4 : [5]))")
+        .assert()
+        .success()
+        .stdout("l = [1, 2, 3, 4, 5]\nl = [0, 3]\nl = [1, 5]\nl = [0, 1, 2, 0]\nl = [1, 2, 3, 4, 5]\n");
+
+    // A longer cons pattern keeps the rest of the list's tail
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("(a : b : rest) ~ (0 : x)")
+        .assert()
+        .success()
+        .stdout(contains("a = 0"))
+        .stdout(contains("x = (b : rest)"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_duplicate_dict_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+person() ~ {name: 'bob, age: 30, name: 'rob};
+older(p) {
+	relate {name: p.name, age: p.age + 1, age: 0}
+};
+named({name: n, name: _m}) ~ n;
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .assert()
+        .failure()
+        .stderr(contains("`name` is given more than once"))
+        .stderr(contains("`age` is given more than once"))
+        .stderr(contains("aborting due to the previous 3 errors"));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_builtin_backtracking() -> Result<(), Box<dyn std::error::Error>> {
+    let source =
+r#"
+caught(n) {
+	catch {
+		x ~ between(1, n)
+		writeln(x)
+	} with _ {
+		writeln('never)
+	}
+	1 == 2
+};
+none_above(l, n) {
+	refute {
+		member(x, l)
+		writeln(x)
+		x > n
+	}
+};
+third(n) {
+	catch {
+		x ~ between(1, n)
+		writeln(x)
+		x == 3
+		throw('found)
+	} with 'found {
+		relate 'caught
+	}
+};
+"#;
+
+    let mut file = NamedTempFile::new()?;
+    write!(file, "{}", source)?;
+
+    // Every answer of between and member is tried inside of a catch
+    // or refute, and each is printed before the next one is tried
+    Command::cargo_bin("bevel")?
+        .arg(file.path())
+        .arg("-i")
+        .write_stdin("caught(3)\nnone_above([1, 2, 3], 5)\nnone_above([4, 9, 6], 5)\nx ~ third(5)")
+        .assert()
+        .success()
+        .stdout("1\n2\n3\nfail\n1\n2\n3\nsuccess\n4\n9\nfail\n1\n2\n3\nx = 'caught\n");
+
+    Ok(())
+}